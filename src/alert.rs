@@ -0,0 +1,258 @@
+//! Storage thresholds and alerts.
+//!
+//! Thresholds are set per profile in the config file, keyed by folder path, or "Total" for the
+//! whole Document Center.  A threshold is a size such as "40 GB", or a percent of the
+//! `storage_quota` setting such as "90%":
+//!
+//! ```toml
+//! [profiles.prod]
+//! storage_quota = "100 GB"
+//! alert_file = "/var/lib/monitoring/linkbuilder.json"
+//! alert_command = "logger -t linkbuilder storage threshold crossed"
+//!
+//! [profiles.prod.thresholds]
+//! Total = "90%"
+//! "GIS/Plats" = "40 GB"
+//! ```
+//!
+//! Each storage report checks its [`FolderSizes`] against the [`Thresholds`].  When a folder
+//! reaches its threshold, [`notify`] writes an [`AlertSummary`] as JSON to the `alert_file`, if
+//! set, and runs the `alert_command`, if set, with the summary on its standard input.  The
+//! `report` command then exits with [`ALERT_EXIT`], so schedulers can tell a crossed threshold
+//! from a failed run.  A later report with no threshold crossed removes the `alert_file`.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::alert::Thresholds;
+//! # use linkbuilder::prelude::*;
+//! # fn main() -> LinkResult<()> {
+//! let config = Config::from_toml(
+//!     r#"
+//!     [profiles.prod]
+//!     storage_quota = "10 MB"
+//!     [profiles.prod.thresholds]
+//!     Total = "80%"
+//!     GIS = "5 MB"
+//!     "#,
+//! )?;
+//! let thresholds = Thresholds::from_settings(&config.profile(Some("prod"))?)?;
+//! let sizes = FolderSizes::from(vec![
+//!     FolderSize::new("GIS", 4_000.),
+//!     FolderSize::new("Total", 9_000.),
+//! ]);
+//! let alerts = thresholds.check(&sizes)?;
+//! assert_eq!(alerts.len(), 1);
+//! assert_eq!(alerts[0].folder, "Total");
+//! assert_eq!(alerts[0].percent, 112.5);
+//! # Ok(())
+//! # }
+//! ```
+use crate::history::quota_kb;
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use tracing::{info, trace, warn};
+
+/// Exit code of the `report` command when a storage threshold is crossed.
+pub const ALERT_EXIT: u8 = 3;
+
+/// Storage limit for a folder.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Threshold {
+    /// Path of the folder, or "Total" for the whole Document Center.
+    pub folder: String,
+    /// Threshold as set in the config file, e.g. "40 GB" or "90%".
+    pub setting: String,
+    /// Threshold in KB.
+    pub kb: f64,
+}
+
+/// Storage thresholds read from the settings of a profile.
+#[derive(Clone, Debug, Default)]
+pub struct Thresholds {
+    records: Vec<Threshold>,
+}
+
+impl Thresholds {
+    /// Reads the thresholds in `settings`.  Thresholds ending in "%" are a share of the
+    /// `storage_quota` setting, and return [`LinkError::SettingError`] if the quota is not set.
+    pub fn from_settings(settings: &Settings) -> LinkResult<Self> {
+        let mut records = Vec::new();
+        for (folder, setting) in &settings.thresholds {
+            let kb = match setting.trim().strip_suffix('%') {
+                Some(percent) => {
+                    let percent =
+                        percent
+                            .trim()
+                            .parse::<f64>()
+                            .map_err(|_| LinkError::AlertError {
+                                value: format!(
+                                    "threshold {} for {} is not a percent",
+                                    setting, folder
+                                ),
+                            })?;
+                    quota_kb(&settings.require("storage_quota")?)? * percent / 100.
+                }
+                None => quota_kb(setting)?,
+            };
+            trace!("Threshold for {}: {} KB.", folder, kb);
+            records.push(Threshold {
+                folder: folder.clone(),
+                setting: setting.clone(),
+                kb,
+            });
+        }
+        Ok(Thresholds { records })
+    }
+
+    /// The `records` field holds the thresholds.  This function returns a reference to the
+    /// field.
+    pub fn records_ref(&self) -> &Vec<Threshold> {
+        &self.records
+    }
+
+    /// Returns an alert for each folder in `sizes` at or above its threshold.  Thresholds for
+    /// folders not in `sizes` are skipped.
+    pub fn check(&self, sizes: &FolderSizes) -> LinkResult<Vec<StorageAlert>> {
+        let mut alerts = Vec::new();
+        for threshold in &self.records {
+            let size = sizes
+                .records_ref()
+                .iter()
+                .find(|s| s.folder() == threshold.folder);
+            let size = match size {
+                Some(size) => size,
+                None => {
+                    trace!("No size for {} in the report.", threshold.folder);
+                    continue;
+                }
+            };
+            if size.size() >= threshold.kb {
+                alerts.push(StorageAlert::new(threshold, size.size())?);
+            }
+        }
+        Ok(alerts)
+    }
+}
+
+/// Folder at or above its storage threshold.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StorageAlert {
+    /// Path of the folder, or "Total" for the whole Document Center.
+    pub folder: String,
+    /// Storage in readable units.
+    pub size: String,
+    /// Storage in KB.
+    pub kb: f64,
+    /// Threshold as set in the config file.
+    pub threshold: String,
+    /// Threshold in KB.
+    pub threshold_kb: f64,
+    /// Storage as a percent of the threshold.
+    pub percent: f64,
+}
+
+impl StorageAlert {
+    /// Creates a `StorageAlert` for a folder holding `kb` of storage against `threshold`.
+    pub fn new(threshold: &Threshold, kb: f64) -> LinkResult<Self> {
+        let size = byte_unit::Byte::from_unit(kb, byte_unit::ByteUnit::KB)?;
+        let percent = match threshold.kb > 0. {
+            true => (kb / threshold.kb * 10000.).round() / 100.,
+            false => 0.,
+        };
+        Ok(StorageAlert {
+            folder: threshold.folder.clone(),
+            size: size.get_appropriate_unit(false).to_string(),
+            kb,
+            threshold: threshold.setting.clone(),
+            threshold_kb: threshold.kb,
+            percent,
+        })
+    }
+}
+
+/// Summary of the thresholds crossed in a storage report, written to the alert file and the
+/// alert command.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AlertSummary {
+    /// Date of the report run.
+    pub date: String,
+    /// Name of the profile.
+    pub profile: String,
+    /// Folders at or above their thresholds.
+    pub alerts: Vec<StorageAlert>,
+}
+
+/// Sends `alerts` to the hooks in `settings`.  With alerts, writes an [`AlertSummary`] to the
+/// `alert_file` and runs the `alert_command` with the summary on its standard input and the
+/// count of alerts in `LINKBUILDER_ALERTS`.  A command that cannot be run, does not read its
+/// input or fails is logged rather than returned as an error, so the alert still reaches the exit
+/// code.  Without alerts, removes
+/// the `alert_file` left by an earlier run.
+pub fn notify(alerts: &[StorageAlert], settings: &Settings) -> LinkResult<()> {
+    if alerts.is_empty() {
+        if let Some(path) = settings
+            .alert_file
+            .as_ref()
+            .filter(|p| Path::new(p).is_file())
+        {
+            std::fs::remove_file(path)?;
+            info!("Storage below thresholds, removed {}.", path);
+        }
+        return Ok(());
+    }
+    let summary = AlertSummary {
+        date: crate::store::now(),
+        profile: settings.profile.clone(),
+        alerts: alerts.to_vec(),
+    };
+    let json = serde_json::to_string_pretty(&summary)?;
+    if let Some(path) = &settings.alert_file {
+        // Written to a temporary file and renamed, so a watcher never reads a partial summary.
+        let temp = format!("{}.tmp", path);
+        std::fs::write(&temp, &json)?;
+        std::fs::rename(&temp, path)?;
+        info!("Alert summary written to {}.", path);
+    }
+    if let Some(command) = &settings.alert_command {
+        trace!("Running alert command: {}", command);
+        let mut child = match shell(command)
+            .env("LINKBUILDER_ALERTS", alerts.len().to_string())
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                warn!("Could not run alert command: {}", e);
+                return Ok(());
+            }
+        };
+        // A command that does not read the summary closes the pipe early, which is not an error.
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Err(e) = stdin.write_all(json.as_bytes()) {
+                warn!("Could not send alert summary to alert command: {}", e);
+            }
+        }
+        match child.wait() {
+            Ok(status) if !status.success() => warn!("Alert command exited with {}.", status),
+            Ok(_) => {}
+            Err(e) => warn!("Could not wait for alert command: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Returns a command running `command` in the system shell.
+fn shell(command: &str) -> std::process::Command {
+    let mut cmd = match cfg!(windows) {
+        true => std::process::Command::new("cmd"),
+        false => std::process::Command::new("sh"),
+    };
+    match cfg!(windows) {
+        true => cmd.args(["/C", command]),
+        false => cmd.args(["-c", command]),
+    };
+    cmd
+}
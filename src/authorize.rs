@@ -1,7 +1,7 @@
 //! Authorizes users on CivicEngage and stores session tokens.
 //!
 //! # Example
-//! ```rust
+//! ```rust,no_run
 //! # use linkbuilder::prelude::{AuthorizeHeaders, AuthorizeInfo, AuthorizedUser, LinkError, LinkResult, User};
 //! # #[tokio::main]
 //! # async fn main() -> LinkResult<()> {
@@ -32,33 +32,37 @@
 //! # }
 use crate::error;
 use reqwest::header::{HeaderName, ACCEPT, CONTENT_TYPE};
-use serde::Deserialize;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tracing::{trace, warn};
 
-/// Struct containing user attributes for logging into CivicEngage.
-#[derive(Clone)]
+/// Struct containing user attributes for logging into CivicEngage.  The password is held in a
+/// [`SecretString`], which is zeroed on drop and redacted from `Debug` output.
+#[derive(Clone, Debug)]
 pub struct User {
     api_key: String,
     partition: String,
     name: String,
-    password: String,
+    password: SecretString,
     host: String,
 }
 
 impl User {
     /// Creates a UserBuilder struct, allowing incremental construction of a User type.
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> UserBuilder {
         UserBuilder::new()
     }
 }
 
 /// Struct UserBuilder contains optional fields for incrementally adding parameters to a User type.
+#[derive(Default)]
 pub struct UserBuilder {
     api_key: Option<String>,
     partition: Option<String>,
     name: Option<String>,
-    password: Option<String>,
+    password: Option<SecretString>,
     host: Option<String>,
 }
 
@@ -125,7 +129,7 @@ impl UserBuilder {
     ///     .password("Your password");
     /// ```
     pub fn password(&mut self, value: &str) -> &mut Self {
-        self.password = Some(value.to_string());
+        self.password = Some(SecretString::new(value.to_string()));
         self
     }
 
@@ -163,19 +167,19 @@ impl UserBuilder {
     /// ```
     pub fn build(&mut self) -> Result<User, error::LinkError> {
         let mut fields = Vec::new();
-        if self.api_key == None {
+        if self.api_key.is_none() {
             fields.push("api_key".to_string());
         }
-        if self.partition == None {
+        if self.partition.is_none() {
             fields.push("partition".to_string());
         }
-        if self.name == None {
+        if self.name.is_none() {
             fields.push("name".to_string());
         }
-        if self.password == None {
+        if self.password.is_none() {
             fields.push("password".to_string());
         }
-        if self.host == None {
+        if self.host.is_none() {
             fields.push("host".to_string());
         }
         if let Some(api_key) = &self.api_key {
@@ -208,18 +212,6 @@ impl UserBuilder {
     }
 }
 
-impl Default for UserBuilder {
-    fn default() -> Self {
-        UserBuilder {
-            api_key: None,
-            partition: None,
-            name: None,
-            password: None,
-            host: None,
-        }
-    }
-}
-
 /// Headers for authorizing a user on CivicEngage.
 pub struct AuthorizeHeaders {
     api_key: HeaderName,
@@ -303,7 +295,7 @@ impl AuthorizeInfo {
         let username = format!("{}@{}", self.user.name, self.user.host);
         let body = json!({
             "Username": username,
-            "Password": self.user.password
+            "Password": self.user.password.expose_secret()
         });
         let res = client
             .post(url)
@@ -314,34 +306,39 @@ impl AuthorizeInfo {
             .body(body.to_string())
             .send()
             .await?;
-        match &res.status() {
-            &reqwest::StatusCode::OK => Ok(res.json::<AuthResponse>().await?),
-            _ => {
-                warn!("Status: {}", res.status());
-                Err(error::LinkError::AuthError)
+        match res.status() {
+            reqwest::StatusCode::OK => res.json::<AuthResponse>().await?.validate(),
+            status => {
+                warn!("Status: {}", status);
+                let text = res.text().await?;
+                match serde_json::from_str::<AuthResponse>(&text) {
+                    Ok(response) => Err(response.failure()),
+                    Err(_) => Err(error::LinkError::AuthError),
+                }
             }
         }
     }
 }
 
 /// Struct for holding authorization responses from CivicEngage. Returned by
-/// [`AuthorizeInfo::authorize`].
-#[derive(Clone, Deserialize, Debug)]
-#[serde(rename_all = "PascalCase")]
+/// [`AuthorizeInfo::authorize`].  Failed logins may leave fields empty, so missing or null
+/// fields read as empty values.
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(rename_all = "PascalCase", default)]
 pub struct AuthResponse {
-    additional_info: String,
+    additional_info: Option<String>,
     success: bool,
     #[serde(rename(deserialize = "APIKey"))]
-    api_key: String,
-    user_id: i32,
-    message: String,
+    api_key: Option<String>,
+    user_id: Option<i32>,
+    message: Option<String>,
 }
 
 impl AuthResponse {
     /// The `additional_info` field holds the additionalInfo content from the response.  This
     /// function returns the value of the field.
     pub fn info(&self) -> String {
-        self.additional_info.clone()
+        self.additional_info.clone().unwrap_or_default()
     }
     /// The `success` field is a bool indicating if the response was a success.  This
     /// function returns the value of the field.
@@ -351,24 +348,75 @@ impl AuthResponse {
     /// The `api_key` field holds the session id value of the response.  This
     /// function returns the value of the field.
     pub fn api_key(&self) -> String {
-        self.api_key.clone()
+        self.api_key.clone().unwrap_or_default()
     }
     /// The `id` field holds the user id.  This function returns the value of the field.
     pub fn id(&self) -> i32 {
-        self.user_id
+        self.user_id.unwrap_or_default()
     }
     /// The `message` field holds the message associated with the response.  This function returns the value of the field.
     pub fn message(&self) -> String {
-        self.message.clone()
+        self.message.clone().unwrap_or_default()
+    }
+
+    /// Returns the response if the login succeeded with a session key, or the error matching
+    /// the failure message otherwise.  See [`AuthResponse::failure`].
+    pub fn validate(self) -> Result<Self, error::LinkError> {
+        if self.success && !self.api_key().is_empty() {
+            Ok(self)
+        } else {
+            Err(self.failure())
+        }
+    }
+
+    /// Maps the message of a failed login to an error variant.  Messages that do not match a
+    /// known failure return [`error::LinkError::LoginError`] with the message from the server.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use linkbuilder::prelude::LinkError;
+    /// # use linkbuilder::authorize::AuthResponse;
+    /// let response: AuthResponse = serde_json::from_str(
+    ///     r#"{"Success": false, "Message": "Invalid username or password.", "APIKey": null}"#,
+    /// ).unwrap();
+    /// assert!(matches!(response.validate(), Err(LinkError::BadPassword { .. })));
+    ///
+    /// let response: AuthResponse =
+    ///     serde_json::from_str(r#"{"Success": false, "Message": "Account is locked."}"#).unwrap();
+    /// assert!(matches!(response.failure(), LinkError::AccountLocked { .. }));
+    /// ```
+    pub fn failure(&self) -> error::LinkError {
+        let message = self.message();
+        let text = format!("{} {}", message, self.info()).to_lowercase();
+        if text.contains("lock") {
+            error::LinkError::AccountLocked { message }
+        } else if text.contains("partition") {
+            error::LinkError::BadPartition { message }
+        } else if text.contains("api key") || text.contains("apikey") {
+            error::LinkError::BadApiKey { message }
+        } else if text.contains("password")
+            || text.contains("username")
+            || text.contains("credential")
+        {
+            error::LinkError::BadPassword { message }
+        } else if message.is_empty() {
+            error::LinkError::LoginError {
+                message: "No session key returned.".to_owned(),
+            }
+        } else {
+            error::LinkError::LoginError { message }
+        }
     }
 }
 
 /// Struct holding credentials for authorized users on CivicEngage.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct AuthorizedUser {
     api_key: String,
     partition: String,
     user_api_key: String,
+    user_id: i32,
 }
 
 impl AuthorizedUser {
@@ -376,7 +424,7 @@ impl AuthorizedUser {
     ///
     /// # Example
     ///
-    /// ```rust
+    /// ```rust,no_run
     /// # use linkbuilder::prelude::{AuthorizeHeaders, AuthorizeInfo, AuthorizedUser, LinkError, LinkResult, User};
     /// # #[tokio::main]
     /// # async fn main() -> LinkResult<()> {
@@ -405,7 +453,8 @@ impl AuthorizedUser {
         AuthorizedUser {
             api_key: user.api_key.clone(),
             partition: user.partition.clone(),
-            user_api_key: auth.api_key.clone(),
+            user_api_key: auth.api_key(),
+            user_id: auth.id(),
         }
     }
 
@@ -426,4 +475,10 @@ impl AuthorizedUser {
     pub fn user_api_key(&self) -> String {
         self.user_api_key.clone()
     }
+
+    /// The `user_id` field holds the user id from the [`AuthResponse`].  This functions returns
+    /// the value of the field.
+    pub fn user_id(&self) -> i32 {
+        self.user_id
+    }
 }
@@ -0,0 +1,317 @@
+//! Layered settings with named profiles.
+//!
+//! Settings for connecting to CivicEngage come from three layers, each overriding the one
+//! before: a profile in the config file, environment variables, and command line flags.  The
+//! config file is TOML with one table per profile:
+//!
+//! ```toml
+//! default_profile = "prod"
+//!
+//! [profiles.prod]
+//! host = "grantspassoregon.gov"
+//! partition = "1234"
+//! api_key = "..."
+//! username = "gis"
+//!
+//! [profiles.staging]
+//! host = "grantspassoregon.gov"
+//! site = "https://staging.grantspassoregon.gov"
+//! partition = "5678"
+//! ```
+//!
+//! Each setting can also be set with an environment variable named `LINKBUILDER_` followed by
+//! the key in upper case, e.g. `LINKBUILDER_API_KEY`, or by the unprefixed name used by earlier
+//! versions, e.g. `API_KEY`.  The user name and host are read only from `LINKBUILDER_USERNAME`
+//! and `LINKBUILDER_HOST`, since the system sets `USERNAME` and `HOST` on some platforms.  The
+//! endpoints for authentication, folders and documents are derived from the `site`, which
+//! defaults to `https://www.{host}`, unless set explicitly.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::config::{Config, Settings};
+//! # fn main() -> linkbuilder::prelude::LinkResult<()> {
+//! let config = Config::from_toml(
+//!     r#"
+//!     default_profile = "prod"
+//!     [profiles.prod]
+//!     host = "grantspassoregon.gov"
+//!     partition = "1234"
+//!     "#,
+//! )?;
+//! let mut settings = config.profile(None)?;
+//! assert_eq!(
+//!     settings.document_url()?,
+//!     "https://www.grantspassoregon.gov/api/DocumentCenter/v1/Document"
+//! );
+//! let mut flags = Settings::default();
+//! flags.partition = Some("5678".to_owned());
+//! settings.merge(&flags);
+//! assert_eq!(settings.partition.as_deref(), Some("5678"));
+//! assert!(config.profile(Some("staging")).is_err());
+//! # Ok(())
+//! # }
+//! ```
+use crate::credential::Credentials;
+use crate::prelude::*;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::trace;
+
+/// Name of the config file.
+pub const CONFIG_FILE: &str = "linkbuilder.toml";
+
+/// Settings for connecting to CivicEngage.  Every field is optional so that settings can be
+/// layered; required fields are checked when they are read.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Settings {
+    /// User domain on CivicEngage, e.g. "grantspassoregon.gov".
+    pub host: Option<String>,
+    /// Base url of the site, defaulting to `https://www.{host}`.
+    pub site: Option<String>,
+    /// API key issued by CivicEngage.
+    pub api_key: Option<String>,
+    /// Partition number issued by CivicEngage.
+    pub partition: Option<String>,
+    /// User name, without the domain.
+    pub username: Option<String>,
+    /// User password.  Prefer a credential store to a password in the config file; see
+    /// [`crate::credential`].
+    pub password: Option<SecretString>,
+    /// Credential store for the password when not set directly, one of "keyring" or "file".
+    pub credentials: Option<String>,
+    /// Authentication endpoint, overriding the endpoint derived from the site.
+    pub authenticate: Option<String>,
+    /// Folder endpoint, overriding the endpoint derived from the site.
+    pub folder: Option<String>,
+    /// Document endpoint, overriding the endpoint derived from the site.
+    pub document: Option<String>,
+    /// Minutes to reuse a cached session before logging in again.  See [`crate::session`].
+    pub session_minutes: Option<u64>,
+    /// Storage quota on CivicEngage, such as "100 GB", for projecting storage growth.  See
+    /// [`crate::history`].
+    pub storage_quota: Option<String>,
+    /// Storage thresholds by folder path, or "Total" for the whole Document Center.  See
+    /// [`crate::alert`].
+    #[serde(default)]
+    pub thresholds: BTreeMap<String, String>,
+    /// Path of the file the alert summary is written to when a storage threshold is crossed.
+    pub alert_file: Option<String>,
+    /// Command run in the system shell when a storage threshold is crossed.
+    pub alert_command: Option<String>,
+    /// Name of the profile the settings were read from.
+    #[serde(skip)]
+    pub profile: String,
+}
+
+impl Settings {
+    /// Reads settings from environment variables.
+    pub fn from_env() -> Self {
+        Settings {
+            host: env("HOST"),
+            site: env("SITE"),
+            api_key: env("API_KEY"),
+            partition: env("PARTITION"),
+            username: env("USERNAME"),
+            password: env("PASSWORD").map(SecretString::new),
+            credentials: env("CREDENTIALS"),
+            authenticate: env("AUTHENTICATE"),
+            folder: env("FOLDER"),
+            document: env("DOCUMENT"),
+            session_minutes: env("SESSION_MINUTES").and_then(|v| v.parse().ok()),
+            storage_quota: env("STORAGE_QUOTA"),
+            thresholds: BTreeMap::new(),
+            alert_file: env("ALERT_FILE"),
+            alert_command: env("ALERT_COMMAND"),
+            profile: String::new(),
+        }
+    }
+
+    /// Overrides settings with the values set in `other`.
+    pub fn merge(&mut self, other: &Settings) -> &mut Self {
+        fn layer<T: Clone>(value: &mut Option<T>, other: &Option<T>) {
+            if other.is_some() {
+                value.clone_from(other);
+            }
+        }
+        layer(&mut self.host, &other.host);
+        layer(&mut self.site, &other.site);
+        layer(&mut self.api_key, &other.api_key);
+        layer(&mut self.partition, &other.partition);
+        layer(&mut self.username, &other.username);
+        layer(&mut self.password, &other.password);
+        layer(&mut self.credentials, &other.credentials);
+        layer(&mut self.authenticate, &other.authenticate);
+        layer(&mut self.folder, &other.folder);
+        layer(&mut self.document, &other.document);
+        layer(&mut self.session_minutes, &other.session_minutes);
+        layer(&mut self.storage_quota, &other.storage_quota);
+        if !other.thresholds.is_empty() {
+            self.thresholds.clone_from(&other.thresholds);
+        }
+        layer(&mut self.alert_file, &other.alert_file);
+        layer(&mut self.alert_command, &other.alert_command);
+        self
+    }
+
+    /// Returns the value of the setting `key`, or an error naming the missing key.
+    pub fn require(&self, key: &str) -> LinkResult<String> {
+        let value = match key {
+            "host" => &self.host,
+            "site" => &self.site,
+            "api_key" => &self.api_key,
+            "partition" => &self.partition,
+            "username" => &self.username,
+            "credentials" => &self.credentials,
+            "authenticate" => &self.authenticate,
+            "folder" => &self.folder,
+            "document" => &self.document,
+            "storage_quota" => &self.storage_quota,
+            "alert_file" => &self.alert_file,
+            "alert_command" => &self.alert_command,
+            _ => &None,
+        };
+        value.clone().ok_or_else(|| LinkError::SettingError {
+            key: key.to_owned(),
+            env: format!("LINKBUILDER_{}", key.to_uppercase()),
+            profile: self.profile.clone(),
+        })
+    }
+
+    /// Returns the base url of the site, from the `site` setting or derived from the `host`.
+    pub fn site(&self) -> LinkResult<String> {
+        match &self.site {
+            Some(site) => Ok(site.trim_end_matches('/').to_owned()),
+            None => {
+                let host = self.require("host")?;
+                if host.starts_with("www.") {
+                    Ok(format!("https://{}", host))
+                } else {
+                    Ok(format!("https://www.{}", host))
+                }
+            }
+        }
+    }
+
+    /// Returns the authentication endpoint.
+    pub fn authenticate_url(&self) -> LinkResult<String> {
+        self.endpoint(&self.authenticate, "Authentication/v1/Authenticate")
+    }
+
+    /// Returns the folder endpoint of the Document Center.
+    pub fn folder_url(&self) -> LinkResult<String> {
+        self.endpoint(&self.folder, "DocumentCenter/v1/Folder")
+    }
+
+    /// Returns the document endpoint of the Document Center.
+    pub fn document_url(&self) -> LinkResult<String> {
+        self.endpoint(&self.document, "DocumentCenter/v1/Document")
+    }
+
+    fn endpoint(&self, value: &Option<String>, path: &str) -> LinkResult<String> {
+        match value {
+            Some(url) => Ok(url.clone()),
+            None => Ok(format!("{}/api/{}", self.site()?, path)),
+        }
+    }
+
+    /// Builds a [`User`] from the settings, reading the user name and password from
+    /// `credentials`.
+    pub fn user(&self, credentials: &Credentials) -> LinkResult<User> {
+        User::new()
+            .api_key(&self.require("api_key")?)
+            .partition(&self.require("partition")?)
+            .name(&credentials.username)
+            .password(credentials.password.expose_secret())
+            .host(&self.require("host")?)
+            .build()
+    }
+}
+
+/// Settings read only with the `LINKBUILDER_` prefix, because the operating system sets
+/// variables of the same name, such as `USERNAME` on Windows.
+const PREFIX_ONLY: [&str; 2] = ["USERNAME", "HOST"];
+
+/// Reads the setting `key` from the environment, preferring the `LINKBUILDER_` prefix.
+fn env(key: &str) -> Option<String> {
+    std::env::var(format!("LINKBUILDER_{}", key))
+        .or_else(|e| match PREFIX_ONLY.contains(&key) {
+            true => Err(e),
+            false => std::env::var(key),
+        })
+        .ok()
+        .filter(|v| !v.is_empty())
+}
+
+/// Contents of the config file.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Config {
+    /// Profile used when no profile is named.
+    pub default_profile: Option<String>,
+    /// Settings for each named profile.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Settings>,
+}
+
+impl Config {
+    /// Parses a `Config` from the TOML text `value`.
+    pub fn from_toml(value: &str) -> LinkResult<Self> {
+        Ok(toml::from_str(value)?)
+    }
+
+    /// Reads the config file at `path`.  When `path` is `None`, reads `linkbuilder.toml` from
+    /// the working directory or the user config directory, and returns an empty `Config` if
+    /// neither exists.
+    pub fn load(path: Option<&Path>) -> LinkResult<Self> {
+        let path = match path {
+            Some(path) => Some(path.to_path_buf()),
+            None => Self::default_path(),
+        };
+        match path {
+            Some(path) => {
+                trace!("Reading config from {}.", path.display());
+                Self::from_toml(&std::fs::read_to_string(path)?)
+            }
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Returns the path of the first config file found, in the working directory or the user
+    /// config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        let local = PathBuf::from(CONFIG_FILE);
+        if local.is_file() {
+            return Some(local);
+        }
+        dirs::config_dir()
+            .map(|d| d.join("linkbuilder").join(CONFIG_FILE))
+            .filter(|p| p.is_file())
+    }
+
+    /// Returns the settings in the profile `name`, or the default profile if `name` is `None`.
+    /// The default profile is read from `LINKBUILDER_PROFILE`, then from `default_profile`.
+    /// Returns an error if a profile is named but not found.  Settings from the environment are
+    /// layered over the profile.
+    pub fn profile(&self, name: Option<&str>) -> LinkResult<Settings> {
+        let name = name
+            .map(|n| n.to_owned())
+            .or_else(|| env("PROFILE"))
+            .or_else(|| self.default_profile.clone());
+        let mut settings = match &name {
+            Some(name) => match self.profiles.get(name) {
+                Some(profile) => profile.clone(),
+                None => {
+                    return Err(LinkError::ProfileError {
+                        value: name.clone(),
+                    })
+                }
+            },
+            None => Settings::default(),
+        };
+        settings.merge(&Settings::from_env());
+        settings.profile = name.unwrap_or_else(|| "default".to_owned());
+        Ok(settings)
+    }
+}
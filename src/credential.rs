@@ -0,0 +1,412 @@
+//! Storage for CivicEngage login credentials.
+//!
+//! A [`CredentialProvider`] loads, stores and clears the user name and password for a profile.
+//! Three providers are available:
+//!
+//! * [`EnvProvider`] reads the `username` and `password` settings from the config file and the
+//!   environment, and cannot store credentials.
+//! * [`FileProvider`] keeps the credentials in a file encrypted with a passphrase, using a key
+//!   derived with Argon2 and the ChaCha20-Poly1305 cipher.
+//! * [`KeyringProvider`] keeps the credentials in the OS keyring: the Secret Service on Linux
+//!   and the BSDs, the Keychain on macOS and the Credential Manager on Windows.
+//!
+//! The `credentials` setting selects the provider; see [`provider`].  Passwords are held in a
+//! [`SecretString`], which is zeroed on drop and redacted from `Debug` output.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::credential::{CredentialProvider, Credentials, FileProvider};
+//! # use secrecy::{ExposeSecret, SecretString};
+//! # fn main() -> linkbuilder::prelude::LinkResult<()> {
+//! let path = std::env::temp_dir().join("linkbuilder_credential_example");
+//! let provider = FileProvider::new(&path, SecretString::new("passphrase".to_owned()));
+//! provider.store(&Credentials::new("gis", "hunter2"))?;
+//! let loaded = provider.load()?.expect("Credentials stored.");
+//! assert_eq!(loaded.username, "gis");
+//! assert_eq!(loaded.password.expose_secret(), "hunter2");
+//! assert!(!format!("{:?}", loaded).contains("hunter2"));
+//!
+//! let wrong = FileProvider::new(&path, SecretString::new("wrong".to_owned()));
+//! assert!(wrong.load().is_err());
+//! provider.clear()?;
+//! assert!(provider.load()?.is_none());
+//! # Ok(())
+//! # }
+//! ```
+use crate::prelude::*;
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use data_encoding::BASE64;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::trace;
+use zeroize::Zeroize;
+
+/// Service name for credentials stored in the OS keyring.
+pub const SERVICE: &str = "linkbuilder";
+
+/// User name and password for logging into CivicEngage.
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    /// User name, without the domain.
+    pub username: String,
+    /// User password.
+    pub password: SecretString,
+}
+
+impl Credentials {
+    /// Creates new `Credentials` from a user name and password.
+    pub fn new(username: &str, password: &str) -> Self {
+        Credentials {
+            username: username.to_owned(),
+            password: SecretString::new(password.to_owned()),
+        }
+    }
+
+    fn to_json(&self) -> LinkResult<String> {
+        let stored = StoredCredentials {
+            username: self.username.clone(),
+            password: self.password.expose_secret().clone(),
+        };
+        Ok(serde_json::to_string(&stored)?)
+    }
+
+    fn from_json(value: &str) -> LinkResult<Self> {
+        let stored: StoredCredentials = serde_json::from_str(value)?;
+        Ok(Credentials::new(&stored.username, &stored.password))
+    }
+}
+
+/// Plain text form of [`Credentials`] for serialization, zeroed on drop.
+#[derive(Deserialize, Serialize)]
+struct StoredCredentials {
+    username: String,
+    password: String,
+}
+
+impl Drop for StoredCredentials {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+/// Stores for [`Credentials`] that can be selected with the `--store` flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum CredentialStore {
+    /// The OS keyring.
+    Keyring,
+    /// A file encrypted with a passphrase.
+    File,
+}
+
+impl CredentialStore {
+    /// Returns the value of the `credentials` setting for the store.
+    pub fn setting(&self) -> &'static str {
+        match self {
+            CredentialStore::Keyring => "keyring",
+            CredentialStore::File => "file",
+        }
+    }
+}
+
+/// Loads, stores and clears [`Credentials`] for a profile.
+pub trait CredentialProvider {
+    /// Name of the provider, for log messages.
+    fn name(&self) -> &str;
+    /// Returns the stored credentials, or `None` if no credentials are stored.
+    fn load(&self) -> LinkResult<Option<Credentials>>;
+    /// Stores `credentials`, replacing any stored before.
+    fn store(&self, credentials: &Credentials) -> LinkResult<()>;
+    /// Removes the stored credentials.  Succeeds if none are stored.
+    fn clear(&self) -> LinkResult<()>;
+}
+
+/// Reads credentials from the `username` and `password` settings.
+#[derive(Clone, Debug)]
+pub struct EnvProvider {
+    username: Option<String>,
+    password: Option<SecretString>,
+}
+
+impl EnvProvider {
+    /// Creates a new `EnvProvider` from `settings`.
+    pub fn new(settings: &Settings) -> Self {
+        EnvProvider {
+            username: settings.username.clone(),
+            password: settings.password.clone(),
+        }
+    }
+}
+
+impl CredentialProvider for EnvProvider {
+    fn name(&self) -> &str {
+        "environment"
+    }
+
+    fn load(&self) -> LinkResult<Option<Credentials>> {
+        match (&self.username, &self.password) {
+            (Some(username), Some(password)) => Ok(Some(Credentials {
+                username: username.clone(),
+                password: password.clone(),
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    fn store(&self, _credentials: &Credentials) -> LinkResult<()> {
+        Err(LinkError::CredentialError {
+            value: "Credentials in the environment are read-only.".to_owned(),
+        })
+    }
+
+    fn clear(&self) -> LinkResult<()> {
+        Err(LinkError::CredentialError {
+            value: "Credentials in the environment are read-only.".to_owned(),
+        })
+    }
+}
+
+/// Encrypted form of [`Credentials`] written by the [`FileProvider`].
+#[derive(Deserialize, Serialize)]
+struct EncryptedCredentials {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Keeps credentials in a file encrypted with a passphrase.
+#[derive(Clone, Debug)]
+pub struct FileProvider {
+    path: PathBuf,
+    passphrase: SecretString,
+}
+
+impl FileProvider {
+    /// Creates a new `FileProvider` for the file at `path`, encrypted with `passphrase`.
+    pub fn new<P: AsRef<Path>>(path: P, passphrase: SecretString) -> Self {
+        FileProvider {
+            path: path.as_ref().to_path_buf(),
+            passphrase,
+        }
+    }
+
+    /// Returns the default path of the credential file for `profile`, in the user config
+    /// directory.
+    pub fn default_path(profile: &str) -> LinkResult<PathBuf> {
+        let dir = dirs::config_dir().ok_or(LinkError::CredentialError {
+            value: "No user config directory found.".to_owned(),
+        })?;
+        Ok(dir
+            .join("linkbuilder")
+            .join(format!("{}.credentials", profile)))
+    }
+
+    fn cipher(&self, salt: &[u8]) -> LinkResult<ChaCha20Poly1305> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(self.passphrase.expose_secret().as_bytes(), salt, &mut key)
+            .map_err(|e| LinkError::CredentialError {
+                value: e.to_string(),
+            })?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        key.zeroize();
+        Ok(cipher)
+    }
+}
+
+impl CredentialProvider for FileProvider {
+    fn name(&self) -> &str {
+        "encrypted file"
+    }
+
+    fn load(&self) -> LinkResult<Option<Credentials>> {
+        if !self.path.is_file() {
+            return Ok(None);
+        }
+        trace!("Reading credentials from {}.", self.path.display());
+        let file: EncryptedCredentials =
+            serde_json::from_str(&std::fs::read_to_string(&self.path)?)?;
+        let decode = |value: &str| {
+            BASE64
+                .decode(value.as_bytes())
+                .map_err(|_| LinkError::CredentialError {
+                    value: format!("Credential file {} is corrupt.", self.path.display()),
+                })
+        };
+        let salt = decode(&file.salt)?;
+        let nonce = decode(&file.nonce)?;
+        let ciphertext = decode(&file.ciphertext)?;
+        let mut plaintext = self
+            .cipher(&salt)?
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| LinkError::CredentialError {
+                value: "Could not decrypt credentials. Check the passphrase.".to_owned(),
+            })?;
+        let credentials = Credentials::from_json(&String::from_utf8_lossy(&plaintext));
+        plaintext.zeroize();
+        Ok(Some(credentials?))
+    }
+
+    fn store(&self, credentials: &Credentials) -> LinkResult<()> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut plaintext = credentials.to_json()?;
+        let ciphertext = self
+            .cipher(&salt)?
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| LinkError::CredentialError {
+                value: "Could not encrypt credentials.".to_owned(),
+            })?;
+        plaintext.zeroize();
+        let file = EncryptedCredentials {
+            salt: BASE64.encode(&salt),
+            nonce: BASE64.encode(&nonce),
+            ciphertext: BASE64.encode(&ciphertext),
+        };
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string(&file)?)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        trace!("Credentials written to {}.", self.path.display());
+        Ok(())
+    }
+
+    fn clear(&self) -> LinkResult<()> {
+        if self.path.is_file() {
+            std::fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps credentials in the OS keyring.
+#[derive(Clone, Debug)]
+pub struct KeyringProvider {
+    account: String,
+}
+
+impl KeyringProvider {
+    /// Creates a new `KeyringProvider` for the credentials of `profile`.
+    pub fn new(profile: &str) -> Self {
+        KeyringProvider {
+            account: profile.to_owned(),
+        }
+    }
+
+    /// Returns the keyring entry for the profile.  Returns [`LinkError::CredentialError`] on
+    /// platforms without an OS keyring, where the keyring library would fall back to a store
+    /// held in memory and lose the credentials when the program exits.
+    fn entry(&self) -> LinkResult<keyring::Entry> {
+        if !cfg!(any(
+            target_os = "linux",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "windows"
+        )) {
+            return Err(LinkError::CredentialError {
+                value: "No OS keyring on this platform, use the file store instead.".to_owned(),
+            });
+        }
+        Ok(keyring::Entry::new(SERVICE, &self.account)?)
+    }
+}
+
+impl CredentialProvider for KeyringProvider {
+    fn name(&self) -> &str {
+        "keyring"
+    }
+
+    fn load(&self) -> LinkResult<Option<Credentials>> {
+        match self.entry()?.get_password() {
+            Ok(mut value) => {
+                let credentials = Credentials::from_json(&value);
+                value.zeroize();
+                Ok(Some(credentials?))
+            }
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn store(&self, credentials: &Credentials) -> LinkResult<()> {
+        let mut value = credentials.to_json()?;
+        let result = self.entry()?.set_password(&value);
+        value.zeroize();
+        Ok(result?)
+    }
+
+    fn clear(&self) -> LinkResult<()> {
+        match self.entry()?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Reads the passphrase for the credential file from `LINKBUILDER_PASSPHRASE`, or prompts for
+/// it on the terminal.
+pub fn passphrase() -> LinkResult<SecretString> {
+    match std::env::var("LINKBUILDER_PASSPHRASE") {
+        Ok(value) => Ok(SecretString::new(value)),
+        Err(_) => Ok(SecretString::new(rpassword::prompt_password(
+            "Credential file passphrase: ",
+        )?)),
+    }
+}
+
+/// Returns the provider for `store`, for the profile in `settings`.
+pub fn store_provider(
+    store: CredentialStore,
+    settings: &Settings,
+) -> LinkResult<Box<dyn CredentialProvider>> {
+    match store {
+        CredentialStore::Keyring => Ok(Box::new(KeyringProvider::new(&settings.profile))),
+        CredentialStore::File => Ok(Box::new(FileProvider::new(
+            FileProvider::default_path(&settings.profile)?,
+            passphrase()?,
+        ))),
+    }
+}
+
+/// Returns the provider selected by the `credentials` setting: "keyring", "file", or the
+/// environment when unset.
+pub fn provider(settings: &Settings) -> LinkResult<Box<dyn CredentialProvider>> {
+    match settings.credentials.as_deref() {
+        None | Some("env") => Ok(Box::new(EnvProvider::new(settings))),
+        Some("keyring") => store_provider(CredentialStore::Keyring, settings),
+        Some("file") => store_provider(CredentialStore::File, settings),
+        Some(other) => Err(LinkError::CredentialError {
+            value: format!("Unknown credential store {}.", other),
+        }),
+    }
+}
+
+/// Returns the credentials for the profile in `settings`.  A password set in the config file,
+/// the environment or on the command line takes precedence over the credential store.
+pub fn resolve(settings: &Settings) -> LinkResult<Credentials> {
+    if let Some(credentials) = EnvProvider::new(settings).load()? {
+        return Ok(credentials);
+    }
+    let provider = provider(settings)?;
+    trace!("Loading credentials from {}.", provider.name());
+    match provider.load()? {
+        Some(credentials) => Ok(credentials),
+        None => Err(LinkError::SettingError {
+            key: "password".to_owned(),
+            env: "LINKBUILDER_PASSWORD".to_owned(),
+            profile: settings.profile.clone(),
+        }),
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! # Examples
 //!
-//! ```rust
+//! ```rust,no_run
 //! # use linkbuilder::prelude::{AuthorizeHeaders, AuthorizeInfo, AuthorizedUser, DocInfo, DocumentHeaders, Documents, DocQuery, FileNames, Folders, LinkError, LinkResult, User};
 //! # #[tokio::main]
 //! # async fn main() -> LinkResult<()> {
@@ -51,14 +51,19 @@
 //! }
 //! # Ok(())
 //! # }
+use crate::journal::{Journal, JournalEntry};
+use crate::output::LinkExport;
 use crate::prelude::*;
+use crate::scheme;
+use crate::store::{CivicEngageStore, DocumentStore};
 use data_encoding::BASE64;
 use indicatif::ProgressBar;
 use reqwest::header::{HeaderName, ACCEPT, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::io::Read;
+use std::sync::{Arc, Mutex, OnceLock};
 use tracing::{info, trace, warn};
 
 /// Data type for Document responses from the Document Center on CivicEngage.
@@ -78,7 +83,7 @@ pub struct Document {
     start_date: Option<String>,
     end_date: Option<String>,
     file_type: Option<String>,
-    #[serde(rename(deserialize = "URL"))]
+    #[serde(rename(deserialize = "URL"), alias = "Url")]
     url: Option<String>,
     alt_text: Option<String>,
     folder_id: Option<i32>,
@@ -93,17 +98,22 @@ pub struct Document {
     last_modified_on: Option<String>,
     show_archives: Option<bool>,
     show_in_rss_feed: Option<bool>,
+    /// Fields returned by CivicEngage that are not listed above, sent back unchanged on update.
+    #[serde(flatten)]
+    extra: BTreeMap<String, Value>,
 }
 
 impl Document {
-    /// Upload a document to Document Center on CivicEngage.
+    /// Upload a document to Document Center on CivicEngage, reading the file from `path`.  The
+    /// document is placed in the folder with id `folder_id`, or the folder with id `id` if
+    /// `folder_id` is empty.
     pub async fn upload(
         &self,
         path: std::path::PathBuf,
         info: &DocInfo,
         user: &AuthorizedUser,
         publish: bool,
-    ) -> LinkResult<()> {
+    ) -> LinkResult<String> {
         let mut status = "Draft".to_string();
         if publish {
             status = "Published".to_string();
@@ -118,7 +128,7 @@ impl Document {
             "Name": self.name,
             "FileName": format!("{}.pdf", self.name),
             "File": format!("{}", enc),
-            "FolderId": self.id,
+            "FolderId": self.folder_id.unwrap_or(self.id),
             "Status": status,
             "ConvertToPdf": "false",
             "IsVisible": "false",
@@ -133,9 +143,21 @@ impl Document {
             .body(body.to_string())
             .send()
             .await?;
-        match &res.status() {
-            &reqwest::StatusCode::OK => Ok(res.json().await?),
-            &reqwest::StatusCode::CREATED => Ok(res.json().await?),
+        match res.status() {
+            reqwest::StatusCode::OK | reqwest::StatusCode::CREATED => {
+                let result = res.text().await?;
+                // The id of the new document is not known, so the entry records no document.
+                if let Some(journal) = info.journal_ref() {
+                    let mut entry =
+                        JournalEntry::new(journal.run_id_ref(), user.user_id(), "upload");
+                    entry.name = Some(self.name.clone());
+                    entry.folder_id = Some(self.folder_id.unwrap_or(self.id));
+                    entry.result = result.clone();
+                    entry.success = true;
+                    journal.record(&entry)?;
+                }
+                Ok(result)
+            }
             _ => {
                 info!("Response: {:?}", res.text().await?);
                 Err(LinkError::AuthError)
@@ -145,7 +167,10 @@ impl Document {
 
     /// Update document in Document Center on CivicEngage. Called by [`Documents::update()`].
     /// The `command` field takes a string of value "draft" or "archive", and will set the status
-    /// of the document to "Draft" or "Archived" respectively.
+    /// of the document to "Draft" or "Archived" respectively.  Only the changed fields are sent,
+    /// and the update is refused with [`LinkError::ConflictError`] if the document changed since
+    /// it was read.  The command "restore" sets the fields changed since back to their values in
+    /// this document.  See [`Document::patch()`].
     pub async fn update(
         &self,
         info: &DocInfo,
@@ -155,40 +180,134 @@ impl Document {
         trace!("Doc name: {}", self.name());
         trace!("Doc id: {}", self.id());
         trace!("Doc url: {:?}", self.url_ref());
+        let doc = self.apply(command);
+        let patch = match command {
+            "restore" => self.patch(command, &Self::get(info, user, self.id).await?)?,
+            _ => self.patch(command, self)?,
+        };
+        self.send(info, user, command, &patch, doc).await
+    }
+
+    /// Move the document to the folder with id `folder_id` on the Document Center.  The
+    /// document keeps its id and url, so published links stay valid.  The move is refused with
+    /// [`LinkError::ConflictError`] if the document changed since it was read.
+    pub async fn move_to(
+        &self,
+        info: &DocInfo,
+        user: &AuthorizedUser,
+        folder_id: i32,
+    ) -> LinkResult<String> {
+        trace!("Moving {} to folder {}.", self.id(), folder_id);
         let mut doc = self.clone();
+        doc.folder_id = Some(folder_id);
+        let patch = DocumentPatch::diff(self, &doc)?;
+        self.send(info, user, "move", &patch, doc).await
+    }
 
-        match command {
-            "archive" => {
-                doc.is_archived = Some(true);
+    /// Sends `patch` to the Document Center and records it in the journal as `action`.  The
+    /// patch is refused with [`LinkError::ConflictError`] if the document changed since it was
+    /// read, unless the patch is unchecked.
+    pub async fn edit(
+        &self,
+        info: &DocInfo,
+        user: &AuthorizedUser,
+        action: &str,
+        patch: &DocumentPatch,
+    ) -> LinkResult<String> {
+        let after = patch.apply_to(self, &crate::store::now())?;
+        self.send(info, user, action, patch, after).await
+    }
+
+    /// Sends `patch` and records `action` in the journal, with `after` as the document after the
+    /// change.  Empty patches are not sent.  Returns [`LinkError::UpdateRejected`] if CivicEngage
+    /// rejects the patch, or the error from [`status_error`] for a rate limit or an expired
+    /// session.
+    async fn send(
+        &self,
+        info: &DocInfo,
+        user: &AuthorizedUser,
+        action: &str,
+        patch: &DocumentPatch,
+        after: Document,
+    ) -> LinkResult<String> {
+        if patch.is_empty() {
+            let result = format!("Document {} unchanged.", self.id());
+            self.journal(info, user, action, Some(after), &result, true)?;
+            return Ok(result);
+        }
+
+        let res = patch.put(info, user).await?;
+        match res.status() {
+            reqwest::StatusCode::OK => {
+                let result: String = res.json().await?;
+                self.journal(info, user, action, Some(after), &result, true)?;
+                Ok(result)
             }
-            "draft" => {
-                doc.status = Some(10);
+            status => {
+                let error = match status {
+                    reqwest::StatusCode::TOO_MANY_REQUESTS | reqwest::StatusCode::UNAUTHORIZED => {
+                        Some(status_error(&res))
+                    }
+                    _ => None,
+                };
+                let result = res.text().await?;
+                self.journal(info, user, action, None, &result, false)?;
+                Err(error.unwrap_or(LinkError::UpdateRejected {
+                    id: self.id(),
+                    status: status.as_u16(),
+                    message: result,
+                }))
             }
-            _ => {}
         }
+    }
 
-        let doc = serde_json::to_string(&doc)?;
-
-        let client = reqwest::Client::new();
-        trace!("Client created for update.");
-        let endpoint = format!("{}/{}", info.url_ref(), self.id());
+    /// Returns the changes `command` makes to the document.  `current` is the document as the
+    /// store holds it now, read only by "restore", which returns the changes from `current` back
+    /// to this document without checking for newer edits.
+    pub fn patch(&self, command: &str, current: &Document) -> LinkResult<DocumentPatch> {
+        match command {
+            "restore" => {
+                let mut patch = DocumentPatch::diff(current, self)?;
+                patch.unchecked();
+                Ok(patch)
+            }
+            _ => DocumentPatch::diff(self, &self.apply(command)),
+        }
+    }
 
-        let res = client
-            .put(endpoint)
-            .header(CONTENT_TYPE, "application/json")
+    /// Reads the document with id `id` from the document endpoint in `info`.
+    pub async fn get(info: &DocInfo, user: &AuthorizedUser, id: i32) -> LinkResult<Self> {
+        let res = reqwest::Client::new()
+            .get(format!("{}/{}", info.url_ref(), id))
             .header(ACCEPT, "application/json")
             .header(info.headers().api_key(), user.api_key())
             .header(info.headers().partition(), user.partition())
             .header(info.headers().user_api_key(), user.user_api_key())
-            .body(doc)
             .send()
             .await?;
-        match &res.status() {
-            &reqwest::StatusCode::OK => Ok(res.json().await?),
-            _ => Ok(res.text().await?),
+        match res.status() {
+            reqwest::StatusCode::OK => Ok(res.json::<Document>().await?),
+            _ => Err(status_error(&res)),
         }
     }
 
+    /// Returns a copy of the document with the changes for `command` applied.  The command "draft"
+    /// sets the status to "Draft" and "archive" marks the document as archived.  Any other value
+    /// returns the document unchanged.
+    pub fn apply(&self, command: &str) -> Self {
+        let mut doc = self.clone();
+        match command {
+            "archive" => {
+                doc.is_archived = Some(true);
+            }
+            "draft" => {
+                doc.status = Some(10);
+            }
+            _ => {}
+        }
+        doc
+    }
+
     /// Delete document from Document Center on CivicEngage.  Called by [`Documents::delete()`].
     pub async fn delete(&self, info: &DocInfo, user: &AuthorizedUser) -> LinkResult<String> {
         let client = reqwest::Client::new();
@@ -203,14 +322,39 @@ impl Document {
             .header(info.headers().user_api_key(), user.user_api_key())
             .send()
             .await?;
-        match &res.status() {
-            &reqwest::StatusCode::OK => Ok(res.json().await?),
-            _ => {
+        let success = res.status() == reqwest::StatusCode::OK;
+        let result: String = match success {
+            true => res.json().await?,
+            false => {
                 // info!("Response: {:?}", res.text().await?);
                 // Err(error::LinkError::AuthError)
-                Ok(res.text().await?)
+                res.text().await?
             }
+        };
+        self.journal(info, user, "delete", None, &result, success)?;
+        Ok(result)
+    }
+
+    /// Records `action` on the document in the journal attached to `info`, if any.  `success`
+    /// records whether CivicEngage accepted the change.
+    fn journal(
+        &self,
+        info: &DocInfo,
+        user: &AuthorizedUser,
+        action: &str,
+        after: Option<Document>,
+        result: &str,
+        success: bool,
+    ) -> LinkResult<()> {
+        if let Some(journal) = info.journal_ref() {
+            let mut entry =
+                JournalEntry::new(journal.run_id_ref(), user.user_id(), action).document(self);
+            entry.after = after;
+            entry.result = result.to_owned();
+            entry.success = success;
+            journal.record(&entry)?;
         }
+        Ok(())
     }
 
     /// The `id` field represents the folder id on the Document Center.  This function returns the
@@ -228,7 +372,7 @@ impl Document {
     /// The `file_size` field represents the document file size on the Document Center in KB.  This function returns the
     /// cloned value of the field.
     pub fn file_size(&self) -> Option<f64> {
-        self.file_size.clone()
+        self.file_size
     }
 
     /// The `status_ref` field represents the document status on the Document Center.  This function returns the
@@ -255,15 +399,132 @@ impl Document {
         &self.is_archived
     }
 
+    /// The `is_visible` field represents whether the document is listed publicly on the Document Center.  This function returns a reference to the
+    /// value of the field.
+    pub fn is_visible_ref(&self) -> &Option<bool> {
+        &self.is_visible
+    }
+
+    /// The `file_name` field holds the name of the uploaded file.  This function returns a
+    /// reference to the field.
+    pub fn file_name_ref(&self) -> &Option<String> {
+        &self.file_name
+    }
+
+    /// The `description` field holds the description of the document.  This function returns a
+    /// reference to the field.
+    pub fn description_ref(&self) -> &Option<String> {
+        &self.description
+    }
+
+    /// The `alt_text` field holds the alternate text of the document, read by screen readers.
+    /// This function returns a reference to the field.
+    pub fn alt_text_ref(&self) -> &Option<String> {
+        &self.alt_text
+    }
+
+    /// The `file_type` field holds the type of the uploaded file, such as "pdf".  This function
+    /// returns a reference to the field.
+    pub fn file_type_ref(&self) -> &Option<String> {
+        &self.file_type
+    }
+
+    /// The `created_date` field holds the date the document was created.  This function returns
+    /// a reference to the field.
+    pub fn created_date_ref(&self) -> &Option<String> {
+        &self.created_date
+    }
+
+    /// The `created_by` field holds the id of the user who created the document.  This function
+    /// returns a reference to the field.
+    pub fn created_by_ref(&self) -> &Option<i32> {
+        &self.created_by
+    }
+
+    /// The `file_uploaded_date` field holds the date the file was last uploaded.  This function
+    /// returns a reference to the field.
+    pub fn file_uploaded_date_ref(&self) -> &Option<String> {
+        &self.file_uploaded_date
+    }
+
+    /// The `file_uploaded_by` field holds the id of the user who last uploaded the file.  This
+    /// function returns a reference to the field.
+    pub fn file_uploaded_by_ref(&self) -> &Option<i32> {
+        &self.file_uploaded_by
+    }
+
+    /// The `start_date` field holds the date the document is first shown.  This function returns
+    /// a reference to the field.
+    pub fn start_date_ref(&self) -> &Option<String> {
+        &self.start_date
+    }
+
+    /// The `end_date` field holds the date the document is last shown.  This function returns a
+    /// reference to the field.
+    pub fn end_date_ref(&self) -> &Option<String> {
+        &self.end_date
+    }
+
+    /// The `last_modified_by` field holds the id of the user who last changed the document.  This
+    /// function returns a reference to the field.
+    pub fn last_modified_by_ref(&self) -> &Option<i32> {
+        &self.last_modified_by
+    }
+
+    /// The `last_modified_on` field holds the date the document was last changed.  This function
+    /// returns a reference to the field.
+    pub fn last_modified_on_ref(&self) -> &Option<String> {
+        &self.last_modified_on
+    }
+
+    /// The `extra` field holds fields returned by CivicEngage that the `Document` type does not
+    /// name.  They are kept so that updates send the full record back.  This function returns a
+    /// reference to the field.
+    ///
+    /// ```rust
+    /// # use linkbuilder::document::Document;
+    /// # use linkbuilder::prelude::*;
+    /// # fn main() -> LinkResult<()> {
+    /// let doc: Document = serde_json::from_value(serde_json::json!({
+    ///     "Id": 1,
+    ///     "Name": "Plat 1",
+    ///     "Status": 30,
+    ///     "Tags": ["plat"],
+    /// }))?;
+    /// assert_eq!(doc.extra_ref()["Tags"], serde_json::json!(["plat"]));
+    /// let draft = serde_json::to_value(doc.apply("draft"))?;
+    /// assert_eq!(draft["Tags"], serde_json::json!(["plat"]));
+    /// assert_eq!(draft["Status"], 10);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn extra_ref(&self) -> &BTreeMap<String, Value> {
+        &self.extra
+    }
+
+    /// The `folder_id` field represents the id of the folder holding the document.  This function
+    /// returns a reference to the value of the field.
+    pub fn folder_id_ref(&self) -> &Option<i32> {
+        &self.folder_id
+    }
+
     /// The `rss_feed_ref` field represents whether the document on the Document Center is archived.  This function returns a reference to the
     /// value of the field.
     pub fn rss_feed_ref(&self) -> &Option<bool> {
         &self.show_in_rss_feed
     }
+
+    /// Returns the date the file was uploaded, or the date the document was created if the
+    /// upload date is missing.
+    pub fn date(&self) -> Option<String> {
+        self.file_uploaded_date
+            .clone()
+            .or_else(|| self.created_date.clone())
+    }
 }
 
 /// Holds a Documents response from the Document Center on CivicEngage.
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct Documents {
     current_page: Option<i32>,
@@ -284,7 +545,7 @@ impl Documents {
     ///
     /// # Example
     ///
-    /// ```rust
+    /// ```rust,no_run
     /// # use linkbuilder::prelude::{AuthorizeHeaders, AuthorizeInfo, AuthorizedUser, DocInfo, DocumentHeaders, Documents, DocQuery, FileNames, Folders, LinkError, LinkResult, User};
     /// # #[tokio::main]
     /// # async fn main() -> LinkResult<()> {
@@ -355,6 +616,30 @@ impl Documents {
         Ok(res)
     }
 
+    /// Moves each document to the folder with id `folder_id`, keeping document ids and urls.
+    /// Calls [`Document::move_to()`].  Returns the responses from CivicEngage.
+    pub async fn move_to(
+        &self,
+        info: &DocInfo,
+        user: &AuthorizedUser,
+        folder_id: i32,
+    ) -> LinkResult<Vec<String>> {
+        let mut res = Vec::new();
+        if let Some(docs) = self.source_ref() {
+            let style = indicatif::ProgressStyle::with_template(
+                "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {'Moving files.'}",
+            )
+            .unwrap();
+            let bar = ProgressBar::new(docs.len() as u64);
+            bar.set_style(style);
+            for doc in docs {
+                res.push(doc.move_to(info, user, folder_id).await?);
+                bar.inc(1);
+            }
+        }
+        Ok(res)
+    }
+
     /// Sends a search request to the Document Center using the query parameters from `info`.
     /// Calls [`DocInfo::query()`], which calls [`DocQuery::query()`].
     pub async fn query(info: &DocInfo, user: &AuthorizedUser) -> LinkResult<Self> {
@@ -367,9 +652,16 @@ impl Documents {
             .header(info.headers.clone().user_api_key(), user.user_api_key())
             .send()
             .await?;
-        match &res.status() {
-            &reqwest::StatusCode::OK => Ok(res.json::<Documents>().await?),
-            _ => Err(LinkError::AuthError),
+        match res.status() {
+            reqwest::StatusCode::OK => {
+                let docs = res.json::<Documents>().await?;
+                schema_drift(
+                    "Document",
+                    docs.source.iter().flatten().flat_map(|d| d.extra.keys()),
+                );
+                Ok(docs)
+            }
+            _ => Err(status_error(&res)),
         }
     }
 
@@ -382,13 +674,13 @@ impl Documents {
     /// The `page_size` field represents the size of the current page.  This function returns the cloned value
     /// of the field.
     pub fn page_size(&self) -> Option<i32> {
-        self.page_size.clone()
+        self.page_size
     }
 
     /// The `total_count` field represents the total count of items in the paginated list.  This function returns the cloned value
     /// of the field.
     pub fn total_count(&self) -> Option<i32> {
-        self.total_count.clone()
+        self.total_count
     }
 
     /// The `total_pages` field represents the page count of the paginated list.  This function returns a reference
@@ -432,6 +724,55 @@ impl Documents {
         &self.has_next_page
     }
 
+    /// Returns a copy of `Documents` holding only the documents with ids in `ids`.  If `ids` is
+    /// empty, every document is kept.  Returns [`LinkError::DocumentError`] naming the ids that
+    /// match no document.
+    pub fn select(&self, ids: &[i32]) -> LinkResult<Self> {
+        if ids.is_empty() {
+            return Ok(self.clone());
+        }
+        let docs = self.retain(|d| ids.contains(&d.id));
+        let found = docs
+            .source_ref()
+            .iter()
+            .flatten()
+            .map(|d| d.id)
+            .collect::<Vec<i32>>();
+        let missing = ids
+            .iter()
+            .filter(|id| !found.contains(id))
+            .map(|id| id.to_string())
+            .collect::<Vec<String>>();
+        match missing.is_empty() {
+            true => Ok(docs),
+            false => Err(LinkError::DocumentError {
+                value: missing.join(", "),
+            }),
+        }
+    }
+
+    /// Returns a copy of `Documents` holding only the documents for which `f` returns true.
+    pub fn retain<F: Fn(&Document) -> bool>(&self, f: F) -> Self {
+        let mut docs = self.clone();
+        if let Some(source) = &mut docs.source {
+            source.retain(f);
+            docs.total_count = Some(source.len() as i32);
+        }
+        docs
+    }
+
+    /// Returns the documents that are published and not archived, the documents the public can
+    /// reach.  Links are exported only for these.
+    pub fn listed(&self) -> Self {
+        Documents::from(
+            self.source()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|d| d.is_archived != Some(true) && d.status != Some(10))
+                .collect::<Vec<Document>>(),
+        )
+    }
+
     /// Loops through documents in `Documents` to calculate the total size in KB.
     pub fn total_size(&self) -> f64 {
         let mut size = 0.;
@@ -451,7 +792,7 @@ impl Documents {
     ///
     /// # Example
     ///
-    /// ```rust
+    /// ```rust,no_run
     /// # use linkbuilder::prelude::{AuthorizeHeaders, AuthorizeInfo, AuthorizedUser, DocInfo, DocumentHeaders, Documents, DocQuery, FileNames, Folders, LinkError, LinkResult, User};
     /// # #[tokio::main]
     /// # async fn main() -> LinkResult<()> {
@@ -518,6 +859,49 @@ impl Documents {
     }
 }
 
+/// Logs a warning the first time a field named in `fields` is seen on a `kind` record, since a
+/// new field means CivicEngage changed the records it returns.  The fields are kept in the
+/// `extra` map of the record.
+fn schema_drift<'a>(kind: &str, fields: impl Iterator<Item = &'a String>) {
+    static SEEN: OnceLock<Mutex<BTreeSet<String>>> = OnceLock::new();
+    let mut seen = SEEN
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let new = fields
+        .filter(|f| seen.insert(format!("{}.{}", kind, f)))
+        .cloned()
+        .collect::<BTreeSet<String>>();
+    if !new.is_empty() {
+        warn!(
+            "CivicEngage returned unknown {} fields: {}.",
+            kind,
+            new.into_iter().collect::<Vec<String>>().join(", ")
+        );
+    }
+}
+
+/// Maps a failed response from the Document Center to an error.  Responses with status 429
+/// return [`LinkError::RateLimited`], and other responses return [`LinkError::AuthError`].
+pub(crate) fn status_error(res: &reqwest::Response) -> LinkError {
+    match res.status() {
+        reqwest::StatusCode::TOO_MANY_REQUESTS => {
+            let retry_after = res
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default();
+            warn!("Rate limited, retry after {} seconds.", retry_after);
+            LinkError::RateLimited { retry_after }
+        }
+        status => {
+            warn!("Status: {}", status);
+            LinkError::AuthError
+        }
+    }
+}
+
 /// Holds headers for calls to the Document endpoint on CivicEngage.
 #[derive(Clone, Debug)]
 pub struct DocumentHeaders {
@@ -655,17 +1039,7 @@ impl DocQuery {
         if let Some(arg) = self.expand.clone() {
             args.push(format!("%24expand={}", arg));
         }
-        let mut query = "?".to_string();
-        let mut i = 0;
-        for arg in args {
-            if i == 0 {
-                query.push_str(&arg);
-            } else {
-                query.push_str(&format!("&{}", arg));
-            }
-            i += 1;
-        }
-        query
+        format!("?{}", args.join("&"))
     }
 }
 
@@ -675,6 +1049,7 @@ pub struct DocInfo {
     headers: DocumentHeaders,
     query: DocQuery,
     url: String,
+    journal: Option<Journal>,
 }
 
 impl DocInfo {
@@ -685,9 +1060,22 @@ impl DocInfo {
             headers: headers.clone(),
             query: query.clone(),
             url: url.to_owned(),
+            journal: None,
         }
     }
 
+    /// Records changes made with this `DocInfo` in the [`Journal`] `value`.
+    pub fn journal(&mut self, value: &Journal) -> &mut Self {
+        self.journal = Some(value.clone());
+        self
+    }
+
+    /// The `journal` field holds the [`Journal`] recording changes made with this `DocInfo`.  This
+    /// function returns a reference to the field.
+    pub fn journal_ref(&self) -> &Option<Journal> {
+        &self.journal
+    }
+
     /// The `headers` field contains the [`DocumentHeaders`].  This function returns the cloned
     /// value of the field.
     pub fn headers(&self) -> DocumentHeaders {
@@ -706,49 +1094,120 @@ impl DocInfo {
     }
 }
 
-/// Holds a HashMap of file names and file paths, used to gather active links from files stored in
-/// the Document Center for tranfer to the GIS layers.
-#[derive(Debug)]
+/// Link to a single document on the Document Center, with the document id and the date used to
+/// order documents that share a key.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DocumentLink {
+    id: i32,
+    name: String,
+    url: String,
+    date: Option<String>,
+}
+
+impl DocumentLink {
+    /// Creates a new `DocumentLink` for the document `id` named `name` at url `url`.  The `date`
+    /// is the upload date of the file, if known.
+    pub fn new(id: i32, name: &str, url: &str, date: Option<String>) -> Self {
+        DocumentLink {
+            id,
+            name: name.to_owned(),
+            url: url.to_owned(),
+            date,
+        }
+    }
+
+    /// Creates a `DocumentLink` from a [`Document`].  Returns `None` if the document has no url.
+    pub fn from_document(doc: &Document) -> Option<Self> {
+        let date = doc.date();
+        doc.url_ref()
+            .as_ref()
+            .map(|url| DocumentLink::new(doc.id(), &doc.name, url, date))
+    }
+
+    /// The `id` field represents the document id on the Document Center.  This function returns
+    /// the value of the field.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// The `name` field represents the document name on the Document Center.  This function
+    /// returns a reference to the field.
+    pub fn name_ref(&self) -> &String {
+        &self.name
+    }
+
+    /// The `url` field represents the document url on the Document Center.  This function returns
+    /// a reference to the field.
+    pub fn url_ref(&self) -> &String {
+        &self.url
+    }
+
+    /// The `date` field represents the date the file was uploaded, or created if the upload date
+    /// is missing.  This function returns a reference to the field.
+    pub fn date_ref(&self) -> &Option<String> {
+        &self.date
+    }
+}
+
+/// Holds a HashMap of file names and the documents with that name, used to gather active links
+/// from files stored in the Document Center for tranfer to the GIS layers.  Every document is
+/// kept, so that documents sharing a name or a key are not dropped.
+#[derive(Debug, Default)]
 pub struct DocumentLinks {
-    links: HashMap<String, std::path::PathBuf>,
+    links: HashMap<String, Vec<DocumentLink>>,
 }
 
 impl DocumentLinks {
-    /// Creates a new `DocumentLinks` from a HashMap of file names and file urls.
-    pub fn new(links: HashMap<String, std::path::PathBuf>) -> Self {
+    /// Creates a new `DocumentLinks` from a HashMap of file names and document links.
+    pub fn new(links: HashMap<String, Vec<DocumentLink>>) -> Self {
         DocumentLinks { links }
     }
 
-    /// The `links` field contains a HashMap of files names and file paths.  This function returns a reference
+    /// The `links` field contains a HashMap of files names and document links.  This function returns a reference
     /// to the field.
-    pub fn ref_links(&self) -> &HashMap<String, std::path::PathBuf> {
+    pub fn ref_links(&self) -> &HashMap<String, Vec<DocumentLink>> {
         &self.links
     }
+
+    /// Adds the document `link` under the file name `name`.
+    pub fn insert(&mut self, name: &str, link: DocumentLink) {
+        self.links.entry(name.to_owned()).or_default().push(link);
+    }
 }
 
-impl Default for DocumentLinks {
-    fn default() -> Self {
-        let links = HashMap::<String, std::path::PathBuf>::new();
-        DocumentLinks { links }
+impl From<Vec<Document>> for Documents {
+    fn from(docs: Vec<Document>) -> Self {
+        let count = docs.len() as i32;
+        Documents {
+            current_page: Some(1),
+            page_size: Some(count),
+            total_count: Some(count),
+            total_pages: Some(1),
+            source: Some(docs),
+            sort_by: None,
+            filter: None,
+            has_previous_page: Some(false),
+            has_next_page: Some(false),
+        }
     }
 }
 
 impl From<&Documents> for DocumentLinks {
     fn from(docs: &Documents) -> Self {
-        let mut links = HashMap::<String, std::path::PathBuf>::new();
+        let mut links = DocumentLinks::default();
         if let Some(documents) = docs.source_ref() {
             for doc in documents {
-                if let Some(url) = doc.url_ref() {
-                    links.insert(doc.name(), url.into());
+                if let Some(link) = DocumentLink::from_document(doc) {
+                    links.insert(&doc.name(), link);
                 }
             }
         }
-        DocumentLinks::new(links)
+        links
     }
 }
 
 /// Data type for a paginated list of type [`Folder`] response from the Document Center on CivicEngage.
-#[derive(Deserialize, Debug, Default, Clone)]
+#[derive(Deserialize, Debug, Default, Clone, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Folders {
     current_page: Option<i32>,
@@ -774,9 +1233,16 @@ impl Folders {
             .header(info.headers().user_api_key(), user.user_api_key())
             .send()
             .await?;
-        match &res.status() {
-            &reqwest::StatusCode::OK => Ok(res.json::<Folders>().await?),
-            _ => Err(LinkError::AuthError),
+        match res.status() {
+            reqwest::StatusCode::OK => {
+                let folders = res.json::<Folders>().await?;
+                schema_drift(
+                    "Folder",
+                    folders.source.iter().flatten().flat_map(|f| f.extra.keys()),
+                );
+                Ok(folders)
+            }
+            _ => Err(status_error(&res)),
         }
     }
 
@@ -789,13 +1255,13 @@ impl Folders {
     /// The `page_size` field represents the size of the current page.  This function returns the cloned value
     /// of the field.
     pub fn page_size(&self) -> Option<i32> {
-        self.page_size.clone()
+        self.page_size
     }
 
     /// The `total_count` field represents the total count of items in the paginated list.  This function returns the cloned value
     /// of the field.
     pub fn total_count(&self) -> Option<i32> {
-        self.total_count.clone()
+        self.total_count
     }
 
     /// The `total_pages` field represents the page count of the paginated list.  This function returns a reference
@@ -852,10 +1318,107 @@ impl Folders {
         }
         id
     }
+
+    /// Returns the name of the folder in `Folders` with id `id`, or `None` if absent.
+    pub fn get_name(&self, id: i32) -> Option<String> {
+        self.source
+            .iter()
+            .flatten()
+            .find(|f| f.id == Some(id))
+            .map(|f| f.name.clone())
+    }
+
+    /// Returns the path of the folder with id `id`, the names of the folder and its parent
+    /// folders joined with "/", e.g. "GIS/Plats/Plats 1990-1999".
+    pub fn path(&self, id: i32) -> String {
+        let folders = self.source.clone().unwrap_or_default();
+        let mut names = Vec::new();
+        let mut next = Some(id);
+        // Parent ids are followed at most once each, in case of a cycle.
+        let mut seen = BTreeSet::new();
+        while let Some(id) = next.filter(|id| seen.insert(*id)) {
+            match folders.iter().find(|f| f.id == Some(id)) {
+                Some(folder) => {
+                    names.push(folder.name.clone());
+                    next = folder.parent_id;
+                }
+                None => next = None,
+            }
+        }
+        names.reverse();
+        names.join("/")
+    }
+
+    /// Returns the ids of the folder with id `id` and every folder below it, including archived
+    /// folders.
+    pub fn subtree(&self, id: i32) -> Vec<i32> {
+        let folders = self.source.clone().unwrap_or_default();
+        let mut ids = vec![id];
+        let mut i = 0;
+        while i < ids.len() {
+            for folder in &folders {
+                if let Some(child) = folder.id {
+                    if folder.parent_id == Some(ids[i]) && !ids.contains(&child) {
+                        ids.push(child);
+                    }
+                }
+            }
+            i += 1;
+        }
+        ids
+    }
+
+    /// Returns the active folders in `Folders` without a parent folder in `Folders`.
+    pub fn roots(&self) -> Vec<Folder> {
+        let mut roots = Vec::new();
+        if let Some(folders) = &self.source {
+            let ids = folders.iter().filter_map(|f| f.id).collect::<Vec<i32>>();
+            for folder in folders {
+                let orphan = match folder.parent_id {
+                    Some(parent) => !ids.contains(&parent),
+                    None => true,
+                };
+                if orphan && folder.is_archived != Some(true) {
+                    roots.push(folder.clone());
+                }
+            }
+        }
+        roots.sort_by(|a, b| a.name.cmp(&b.name));
+        roots
+    }
+
+    /// Returns the active folders in `Folders` whose parent folder id is `id`.  Used to read
+    /// nested folder layouts such as the project folders under "As-Builts".
+    pub fn children(&self, id: i32) -> Vec<Folder> {
+        let mut children = Vec::new();
+        if let Some(folders) = &self.source {
+            for folder in folders {
+                if folder.parent_id == Some(id) && folder.is_archived != Some(true) {
+                    children.push(folder.clone());
+                }
+            }
+        }
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        children
+    }
+}
+
+impl From<Vec<Folder>> for Folders {
+    fn from(folders: Vec<Folder>) -> Self {
+        let count = folders.len() as i32;
+        Folders {
+            current_page: Some(1),
+            page_size: Some(count),
+            total_count: Some(count),
+            total_pages: Some(1),
+            source: Some(folders),
+            ..Default::default()
+        }
+    }
 }
 
 /// Data type for Folder responses from the Document Center on CivicEngage.
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Folder {
     id: Option<i32>,
@@ -863,7 +1426,7 @@ pub struct Folder {
     status: Option<i32>,
     path: Option<String>,
     name: String,
-    #[serde(rename(deserialize = "ParentID"))]
+    #[serde(rename(deserialize = "ParentID"), alias = "ParentId")]
     parent_id: Option<i32>,
     created_date: Option<String>,
     created_by: Option<i32>,
@@ -875,17 +1438,20 @@ pub struct Folder {
     update_integration_hub: Option<bool>,
     archived_by: Option<i32>,
     archived_reason: Option<i32>,
-    #[serde(rename(deserialize = "ArchivedFolderID"))]
+    #[serde(rename(deserialize = "ArchivedFolderID"), alias = "ArchivedFolderId")]
     archived_folder_id: Option<i32>,
     total_folder_size: Option<i32>,
     children_exist: Option<bool>,
-    #[serde(rename(deserialize = "URL"))]
+    #[serde(rename(deserialize = "URL"), alias = "Url")]
     url: Option<String>,
     folder_root: Option<i32>,
     department_header_id: Option<i32>,
     show_archives: Option<bool>,
     permissions: Option<Vec<String>>,
     item_count: Option<i32>,
+    /// Fields returned by CivicEngage that are not listed above.
+    #[serde(flatten)]
+    extra: BTreeMap<String, Value>,
 }
 
 impl Folder {
@@ -895,6 +1461,18 @@ impl Folder {
         &self.id
     }
 
+    /// The `extra` field holds fields returned by CivicEngage that the `Folder` type does not
+    /// name.  This function returns a reference to the field.
+    pub fn extra_ref(&self) -> &BTreeMap<String, Value> {
+        &self.extra
+    }
+
+    /// The `name` field represents the name of a `Folder`.  This function returns a reference
+    /// to the field.
+    pub fn name_ref(&self) -> &String {
+        &self.name
+    }
+
     /// The `description` field represents the description of a `Folder`.  This function returns a reference
     /// to the field.
     pub fn description_ref(&self) -> &Option<String> {
@@ -1039,11 +1617,14 @@ impl Folder {
 #[derive(Default)]
 pub struct LinkUpdaterBuilder {
     folders: Option<Folders>,
+    store: Option<Arc<dyn DocumentStore>>,
     headers: Option<DocumentHeaders>,
     args: Option<DocQuery>,
     url: Option<String>,
     user: Option<AuthorizedUser>,
     output: Option<String>,
+    host: Option<String>,
+    strategy: Option<LinkStrategy>,
 }
 
 impl LinkUpdaterBuilder {
@@ -1059,6 +1640,14 @@ impl LinkUpdaterBuilder {
         self
     }
 
+    /// The `store()` function sets the value of the `store` field to `value`.  Documents are read
+    /// from this [`DocumentStore`].  If not set, documents are read from CivicEngage using the
+    /// `headers`, `args`, `url` and `user` fields.
+    pub fn store(&mut self, value: Arc<dyn DocumentStore>) -> &mut Self {
+        self.store = Some(value);
+        self
+    }
+
     /// The `headers()` function sets the value of the `headers` field to `value`.
     pub fn headers(&mut self, value: &DocumentHeaders) -> &mut Self {
         self.headers = Some(value.clone());
@@ -1097,79 +1686,137 @@ impl LinkUpdaterBuilder {
         }
     }
 
+    /// The `host()` function sets the value of the `host` field to `value`.  Links are written to
+    /// this domain.  If not set, the host of the `url` endpoint is used, or the default host of
+    /// [`LinkPolicy`] if `url` is not set either.
+    pub fn host(&mut self, value: &str) -> &mut Self {
+        self.host = Some(value.into());
+        self
+    }
+
+    /// The `strategy()` function sets the value of the `strategy` field to `value`.  Determines
+    /// how keys linked to more than one document are exported.  If not set, links point to the
+    /// most recent document.
+    pub fn strategy(&mut self, value: LinkStrategy) -> &mut Self {
+        self.strategy = Some(value);
+        self
+    }
+
     /// The `build()` function returns a complete [`LinkUpdater`] struct if all the fields have
-    /// been set.
+    /// been set.  Either the `store` field, or the `headers`, `args`, `url` and `user` fields must
+    /// be set.
     pub fn build(&self) -> LinkResult<LinkUpdater> {
-        if let Some(folders) = self.folders.clone() {
-            if let Some(headers) = self.headers.clone() {
-                if let Some(args) = self.args.clone() {
-                    if let Some(url) = self.url.clone() {
-                        if let Some(user) = self.user.clone() {
-                            if let Some(output) = self.output.clone() {
-                                Ok(LinkUpdater {
-                                    folders,
-                                    headers,
-                                    args,
-                                    url,
-                                    user,
-                                    output,
-                                })
-                            } else {
-                                Err(LinkError::BuildError)
-                            }
-                        } else {
-                            Err(LinkError::BuildError)
-                        }
-                    } else {
-                        Err(LinkError::BuildError)
-                    }
-                } else {
-                    Err(LinkError::BuildError)
+        let folders = self.folders.clone().ok_or(LinkError::BuildError)?;
+        let output = self.output.clone().ok_or(LinkError::BuildError)?;
+        let store: Arc<dyn DocumentStore> = match &self.store {
+            Some(store) => store.clone(),
+            None => match (&self.headers, &self.args, &self.url, &self.user) {
+                (Some(headers), Some(args), Some(url), Some(user)) => {
+                    let mut store = CivicEngageStore::new(url, user);
+                    store.headers(headers).query(args);
+                    Arc::new(store)
                 }
-            } else {
-                Err(LinkError::BuildError)
-            }
-        } else {
-            Err(LinkError::BuildError)
-        }
+                _ => return Err(LinkError::BuildError),
+            },
+        };
+        let policy = match (&self.host, &self.url) {
+            (Some(host), _) => LinkPolicy::new(host),
+            (None, Some(url)) => LinkPolicy::from_url(url)?,
+            (None, None) => LinkPolicy::default(),
+        };
+        Ok(LinkUpdater {
+            folders,
+            store,
+            output,
+            policy,
+            strategy: self.strategy.unwrap_or_default(),
+        })
     }
 }
 
 /// The `LinkUpdater` struct holds required fields for updating link files.
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Debug)]
 pub struct LinkUpdater {
     folders: Folders,
-    headers: DocumentHeaders,
-    args: DocQuery,
-    url: String,
-    user: AuthorizedUser,
+    store: Arc<dyn DocumentStore>,
     output: String,
+    policy: LinkPolicy,
+    strategy: LinkStrategy,
 }
 
 impl LinkUpdater {
     /// The `new()` method creates an empty [`LinkUpdaterBuilder`].  Set the empty fields and
     /// call [`LinkUpdaterBuilder::build()`] to create a new `LinkUpdater`.
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> LinkUpdaterBuilder {
         LinkUpdaterBuilder::new()
     }
 
     /// The `get_links()` method searches for links in folder `folder` and outputs a link file.
-    pub async fn get_links(&self, folder: &str, file: &str) -> LinkResult<()> {
+    /// Link keys are parsed from document names using the [`scheme::NameScheme`] for the folder,
+    /// and keys with several documents are written using the [`LinkStrategy`] of the updater.
+    /// For categories with a nested layout, documents are read from the subfolders of `folder`.
+    /// Returns a summary of the files written, or `None` if the folder was not found.
+    pub async fn get_links(&self, folder: &str, file: &str) -> LinkResult<Option<LinkExport>> {
         if let Some(id) = self.folders.get_id(folder) {
             trace!("Folder id: {:?}", id);
-            trace!("Specify folder for search.");
-            let mut args = self.args.clone();
-            args.filter(&format!("FolderId eq {}", id));
-            let doc_info = DocInfo::new(&self.headers, &args, &self.url);
-            let docs = Documents::query(&doc_info, &self.user).await?;
-            let links = DocumentLinks::from(&docs);
-            let mut linked = WebLinks::from(&links);
-            let link_path = format!("{}/{}.csv", self.output, file);
-            linked.to_csv(&link_path)?;
-            info!("Links printed to {}", &link_path);
+            let scheme = scheme::for_folder(folder);
+            let links = if scheme.nested() {
+                let docs = self.query_folder(id).await?;
+                let mut links = DocumentLinks::from(&docs);
+                for child in self.folders.children(id) {
+                    if let Some(child_id) = child.id_ref() {
+                        trace!("Reading subfolder {}.", child.name_ref());
+                        let docs = self.query_folder(*child_id).await?;
+                        for (name, items) in DocumentLinks::from(&docs).ref_links() {
+                            for item in items {
+                                links.insert(
+                                    &format!("{}/{}", child.name_ref(), name),
+                                    item.clone(),
+                                );
+                            }
+                        }
+                    }
+                }
+                links
+            } else {
+                let docs = self.query_folder(id).await?;
+                DocumentLinks::from(&docs)
+            };
+            let keyed = KeyedLinks::from_links(&links, &self.policy, scheme.as_ref())?;
+            let mut files = Vec::new();
+            for path in keyed.to_csv(self.strategy, &self.output, file)? {
+                info!("Links printed to {}", path.display());
+                files.push(path.display().to_string());
+            }
+            let mut issues = scheme::NameIssues::from_links(&links, scheme.as_ref());
+            if !issues.records_ref().is_empty() {
+                let issue_path = format!("{}/{}_nonconforming.csv", self.output, file);
+                issues.to_csv(&issue_path)?;
+                warn!(
+                    "{} names in {} do not conform, printed to {}",
+                    issues.records_ref().len(),
+                    folder,
+                    &issue_path
+                );
+                files.push(issue_path);
+            }
+            Ok(Some(LinkExport {
+                folder: folder.to_owned(),
+                keys: keyed.ref_links().len(),
+                nonconforming: issues.records_ref().len(),
+                files: files.join(";"),
+            }))
         } else {
             warn!("Folder name {} not found.", folder);
+            Ok(None)
         }
-        Ok(())
+    }
+
+    /// Queries the documents in the folder with folder id `id`, leaving out archived and draft
+    /// documents.  See [`Documents::listed()`].
+    async fn query_folder(&self, id: i32) -> LinkResult<Documents> {
+        trace!("Specify folder for search.");
+        Ok(self.store.documents(Some(id)).await?.listed())
     }
 }
@@ -19,20 +19,225 @@ pub enum LinkError {
     /// Error returned by the reqwest library.
     #[error("HTTP request error.")]
     HttpError(#[from] reqwest::Error),
+    /// Error returned by the hyper library.  See [`crate::mock::MockServer`].
+    #[cfg(feature = "test-support")]
+    #[error("HTTP server error.")]
+    ServerError(#[from] hyper::Error),
     /// Error returned by the std::io module.
     #[error("Input/output error from std.")]
     Io(#[from] std::io::Error),
     /// Error returned by the std::env module.
     #[error("Could not read environmental variables from .env.")]
     EnvError(#[from] std::env::VarError),
+    /// A required setting was not found in the profile, the environment or the command line.
+    /// See [`crate::config::Settings::require`].
+    #[error("Setting {key} not found for profile {profile}. Set {key} in the config file or the {env} environment variable.")]
+    SettingError {
+        /// Key of the missing setting.
+        key: String,
+        /// Environment variable for the setting.
+        env: String,
+        /// Name of the profile.
+        profile: String,
+    },
+    /// The profile named was not found in the config file.  See
+    /// [`crate::config::Config::profile`].
+    #[error("Profile {value} not found in config file.")]
+    ProfileError {
+        /// Name of the profile.
+        value: String,
+    },
+    /// Credentials could not be loaded, stored or cleared.  See [`crate::credential`].
+    #[error("Credential error: {value}")]
+    CredentialError {
+        /// Description of the failure.
+        value: String,
+    },
+    /// Error returned by the keyring library.  See [`crate::credential::KeyringProvider`].
+    #[error("Keyring error.")]
+    KeyringError(#[from] keyring::Error),
+    /// Error returned by the toml library.  See [`crate::config::Config::from_toml`].
+    #[error("Could not read config file.")]
+    TomlError(#[from] toml::de::Error),
+    /// No active folder on the Document Center matches the name provided.
+    #[error("Folder {value} not found on the Document Center.")]
+    FolderError {
+        /// Name of the folder.
+        value: String,
+    },
+    /// No document matches the ids provided.  See [`crate::document::Documents::select`].
+    #[error("Documents not found: {value}.")]
+    DocumentError {
+        /// Ids of the documents not found.
+        value: String,
+    },
     /// Local error returned by the authorize module.  See [`crate::authorize::AuthorizeInfo::authorize`].
     #[error("Authorization failed.")]
     AuthError,
+    /// CivicEngage refused the request because too many requests were sent.
+    #[error("Rate limited by CivicEngage, retry after {retry_after} seconds.")]
+    RateLimited {
+        /// Seconds to wait before retrying, from the `Retry-After` header.
+        retry_after: u64,
+    },
+    /// CivicEngage rejected the user name or password.  See
+    /// [`crate::authorize::AuthResponse::failure`].
+    #[error("Login failed: {message}\nCheck the user name and password for the profile, or run `linkbuilder login` to store new credentials.")]
+    BadPassword {
+        /// Message from the server.
+        message: String,
+    },
+    /// The CivicEngage account is locked.
+    #[error("Login failed: {message}\nThe account is locked. Ask a CivicEngage administrator to unlock it, and wait before trying again to avoid further lockouts.")]
+    AccountLocked {
+        /// Message from the server.
+        message: String,
+    },
+    /// CivicEngage rejected the API key.
+    #[error("Login failed: {message}\nCheck the api_key setting for the profile against the key issued by CivicEngage.")]
+    BadApiKey {
+        /// Message from the server.
+        message: String,
+    },
+    /// CivicEngage rejected the partition number.
+    #[error("Login failed: {message}\nCheck the partition setting for the profile. Staging and production sites use different partitions.")]
+    BadPartition {
+        /// Message from the server.
+        message: String,
+    },
+    /// Login failed for a reason not matched to another variant.
+    #[error("Login failed: {message}")]
+    LoginError {
+        /// Message from the server.
+        message: String,
+    },
     // #[error("Bad file name {0:?}.")]
     // FileNameError(std::ffi::OsString),
     /// Error returned by the serde_json library.  See [`crate::document::Document::update`].
     #[error("Conversion to JSON failed.")]
     JsonError(#[from] serde_json::Error),
+    /// Error returned by the url library.  See [`crate::export::LinkPolicy::canonical`].
+    #[error("Could not parse url.")]
+    UrlError(#[from] url::ParseError),
+    /// Error returned by the csv library.
+    #[error("Could not read or write csv.")]
+    CsvError(#[from] csv::Error),
+    /// A required column was missing from an input file.  See
+    /// [`crate::health::LinkChecks::from_attributes`].
+    #[error("Field {value} not found in input.")]
+    FieldError {
+        /// Name of the missing field.
+        value: String,
+    },
+    /// Error returned by the regex library.  See [`crate::purge::PurgeFilter::name`].
+    #[error("Invalid regular expression.")]
+    RegexError(#[from] regex::Error),
+    /// A date was not in the form `YYYY-MM-DD`.  See [`crate::purge::PurgeFilter::before`].
+    #[error("{value} is not a date in the form YYYY-MM-DD.")]
+    DateError {
+        /// Value provided.
+        value: String,
+    },
+    /// A purge or bulk update selected more documents than allowed.  See
+    /// [`crate::purge::PurgePlan::check_max`].
+    #[error("Selection of {count} documents exceeds the maximum of {max}.")]
+    PurgeLimit {
+        /// Count of documents selected.
+        count: usize,
+        /// Maximum count allowed.
+        max: usize,
+    },
+    /// A document could not be downloaded before deletion.  See
+    /// [`crate::purge::PurgePlan::backup`].
+    #[error("Backup of document {id} failed with status {status}.")]
+    BackupError {
+        /// Document id.
+        id: i32,
+        /// HTTP status code of the response.
+        status: u16,
+    },
+    /// No entries in the journal match the run id.  See [`crate::journal::Journal::undo`].
+    #[error("No journal entries found for run {value}.")]
+    RunError {
+        /// Run id requested.
+        value: String,
+    },
+    /// The changes recorded under the run id were already reversed.  See
+    /// [`crate::journal::Journal::undo`].
+    #[error("Run {value} was already undone.")]
+    AlreadyUndone {
+        /// Run id requested.
+        value: String,
+    },
+    /// A command that only works against a local store was run without one.  See
+    /// [`crate::store::LocalStore`].
+    #[error("The {value} command requires a local store, pass --local <DIR>.")]
+    LocalError {
+        /// Name of the command.
+        value: String,
+    },
+    /// A recorded fixture no longer parses into the type read from its response.  See
+    /// [`crate::fixture::verify`].
+    #[cfg(feature = "test-support")]
+    #[error("Fixture {path} does not parse: {message}")]
+    FixtureError {
+        /// Path to the fixture file.
+        path: String,
+        /// Error from the parser.
+        message: String,
+    },
+    /// A document changed on the Document Center after it was read, so an update was refused.
+    /// See [`crate::patch::DocumentPatch::check`].
+    #[error("Document {id} was modified on {found}, after it was read (last modified {expected}), not updating.")]
+    ConflictError {
+        /// Id of the document.
+        id: i32,
+        /// Last modified date when the document was read.
+        expected: String,
+        /// Last modified date now.
+        found: String,
+    },
+    /// CivicEngage refused an update to a document.  See [`crate::document::Document::update`].
+    #[error("Update of document {id} rejected with status {status}: {message}")]
+    UpdateRejected {
+        /// Id of the document.
+        id: i32,
+        /// HTTP status of the response.
+        status: u16,
+        /// Body of the response.
+        message: String,
+    },
+    /// A retention rule or the date it is evaluated on is invalid.  See
+    /// [`crate::retention::RetentionRules::from_toml`].
+    #[error("Invalid retention rules: {value}.")]
+    RetentionError {
+        /// Description of the problem.
+        value: String,
+    },
+    /// A row of the storage history could not be read.  See
+    /// [`crate::history::StorageHistory::trend`].
+    #[error("Could not read storage history: {value}.")]
+    HistoryError {
+        /// Description of the problem.
+        value: String,
+    },
+    /// A storage threshold in the config file is invalid.  See
+    /// [`crate::alert::Thresholds::from_settings`].
+    #[error("Invalid storage threshold: {value}.")]
+    AlertError {
+        /// Description of the problem.
+        value: String,
+    },
+    /// A storage report found folders at or above their thresholds.  See
+    /// [`crate::alert::Thresholds::check`].
+    #[error("Storage thresholds crossed: {count}.")]
+    ThresholdError {
+        /// Count of thresholds crossed.
+        count: usize,
+    },
+    /// The user declined to confirm a destructive action.
+    #[error("Cancelled by user.")]
+    Cancelled,
     /// Error returned by the byte_unit library. See [`crate::report::ReportItem::new()']
     #[error("Byte conversion failed.")]
     ByteError(#[from] byte_unit::ByteError),
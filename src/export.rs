@@ -1,21 +1,138 @@
-use crate::{document, utils};
+use crate::{document, error, health, scheme, utils};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::warn;
+use url::Url;
+
+/// Host of the City of Grants Pass website, used when no other host is configured.
+pub const DEFAULT_HOST: &str = "www.grantspassoregon.gov";
+
+/// Rules for converting the `URL` strings returned by CivicEngage into stable links.  Links are
+/// forced to https on the configured `host`, and the path is rewritten to the form
+/// `/DocumentCenter/View/{id}/{slug}`, dropping any query string or fragment.
+///
+/// # Example
+///
+/// ```rust
+/// # use linkbuilder::export::LinkPolicy;
+/// # fn main() -> linkbuilder::prelude::LinkResult<()> {
+/// let policy = LinkPolicy::new("www.grantspassoregon.gov");
+/// let link = policy.canonical("/DocumentCenter/View/31368/GPLogo_450W-PNG", "GPLogo_450W")?;
+/// assert_eq!(
+///     link.as_str(),
+///     "https://www.grantspassoregon.gov/DocumentCenter/View/31368/GPLogo_450W-PNG"
+/// );
+/// let link = policy.canonical("http://grantspassoregon.gov/DocumentCenter/View/42?bidId=", "Plat 12")?;
+/// assert_eq!(
+///     link.as_str(),
+///     "https://www.grantspassoregon.gov/DocumentCenter/View/42/Plat-12"
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LinkPolicy {
+    host: String,
+}
+
+impl LinkPolicy {
+    /// Creates a new `LinkPolicy` that writes links to the domain `host`.
+    pub fn new(host: &str) -> Self {
+        LinkPolicy {
+            host: host.to_owned(),
+        }
+    }
+
+    /// Creates a new `LinkPolicy` using the host of the endpoint `url`, such as the Document
+    /// endpoint on CivicEngage.
+    pub fn from_url(url: &str) -> error::LinkResult<Self> {
+        let url = Url::parse(url)?;
+        match url.host_str() {
+            Some(host) => Ok(LinkPolicy::new(host)),
+            None => Err(error::LinkError::UrlError(url::ParseError::EmptyHost)),
+        }
+    }
+
+    /// The `host` field holds the domain for canonical links.  This function returns a reference
+    /// to the field.
+    pub fn host_ref(&self) -> &String {
+        &self.host
+    }
+
+    /// Converts the `link` to a document named `name` into its canonical form.  Relative links
+    /// are resolved against the configured host.  If the link does not end in a slug, one is
+    /// derived from `name`.
+    pub fn canonical(&self, link: &str, name: &str) -> error::LinkResult<Url> {
+        let base = Url::parse(&format!("https://{}/", self.host))?;
+        let parsed = base.join(link.trim())?;
+        let mut url = base.clone();
+        match health::document_id(parsed.path()) {
+            Some(id) => {
+                let slug = parsed
+                    .path_segments()
+                    .and_then(|mut segments| {
+                        segments.find(|s| s.parse::<i32>() == Ok(id))?;
+                        segments.next()
+                    })
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.to_owned())
+                    .unwrap_or_else(|| slug(name));
+                url.set_path(&format!("/DocumentCenter/View/{}/{}", id, slug));
+            }
+            None => url.set_path(parsed.path()),
+        }
+        Ok(url)
+    }
+}
+
+impl Default for LinkPolicy {
+    fn default() -> Self {
+        LinkPolicy::new(DEFAULT_HOST)
+    }
+}
+
+/// Converts a document name into the slug form used by CivicEngage, replacing characters other
+/// than letters, numbers, hyphens and underscores with a hyphen.
+pub fn slug(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
 
 /// Struct for holding web links to documents using a key `field`.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct WebLink {
     field: String,
-    web_link: std::path::PathBuf,
+    web_link: Url,
 }
 
 impl WebLink {
-    /// Creates a `WebLink` from an identifier `field` and path `link`.  Called by [`WebLinks::from()`]
-    pub fn new(field: &str, link: &std::path::PathBuf) -> Self {
+    /// Creates a `WebLink` from an identifier `field` and url `link`.  Called by [`WebLinks::from_links()`]
+    pub fn new(field: &str, link: &Url) -> Self {
         WebLink {
             field: field.to_owned(),
             web_link: link.to_owned(),
         }
     }
+
+    /// The `field` field holds the identifier for the link.  This function returns a reference to
+    /// the field.
+    pub fn field_ref(&self) -> &String {
+        &self.field
+    }
+
+    /// The `web_link` field holds the url of the linked document.  This function returns a
+    /// reference to the field.
+    pub fn web_link_ref(&self) -> &Url {
+        &self.web_link
+    }
 }
 
 /// Holds a vector of [`WebLink`] objects.
@@ -25,6 +142,56 @@ pub struct WebLinks {
 }
 
 impl WebLinks {
+    /// Creates `WebLinks` from the links in `doc`, converting each link to canonical form using
+    /// `policy`.  The `field` of each record is a key parsed from the document name by `scheme`,
+    /// and documents with several keys produce one record per key.  Documents with names that do
+    /// not conform to `scheme` are left out; see [`scheme::NameIssues`].  Records are sorted by
+    /// field and link so that exports are deterministic.
+    pub fn from_links(
+        doc: &document::DocumentLinks,
+        policy: &LinkPolicy,
+        scheme: &dyn scheme::NameScheme,
+    ) -> error::LinkResult<Self> {
+        let mut records = Vec::new();
+        for (k, v) in doc.ref_links() {
+            for item in v {
+                let url = policy.canonical(item.url_ref(), k)?;
+                for key in scheme.keys(k) {
+                    records.push(WebLink::new(&key, &url));
+                }
+            }
+        }
+        let mut links = WebLinks { records };
+        links.sort();
+        Ok(links)
+    }
+
+    /// Reads a links file previously written by [`WebLinks::to_csv()`] from path `title`.
+    pub fn from_csv<P: AsRef<std::path::Path>>(title: P) -> Result<Self, csv::Error> {
+        let mut records = Vec::new();
+        let mut rdr = csv::Reader::from_path(title)?;
+        for result in rdr.deserialize() {
+            let record: WebLink = result?;
+            records.push(record);
+        }
+        Ok(WebLinks { records })
+    }
+
+    /// The `records` field holds a vector of [`WebLink`] objects.  This function returns a
+    /// reference to the field.
+    pub fn records_ref(&self) -> &Vec<WebLink> {
+        &self.records
+    }
+
+    /// Sorts records by field, then by link.
+    pub fn sort(&mut self) {
+        self.records.sort_by(|a, b| {
+            a.field
+                .cmp(&b.field)
+                .then_with(|| a.web_link.as_str().cmp(b.web_link.as_str()))
+        });
+    }
+
     /// Outputs values to csv file at path `title`.
     pub fn to_csv<P: AsRef<std::path::Path>>(&mut self, title: P) -> Result<(), std::io::Error> {
         utils::to_csv(&mut self.records, title)?;
@@ -34,8 +201,215 @@ impl WebLinks {
 
 impl From<&document::DocumentLinks> for WebLinks {
     fn from(doc: &document::DocumentLinks) -> Self {
-        let records = doc
-            .ref_links()
+        let policy = LinkPolicy::default();
+        let mut records = Vec::new();
+        for (k, v) in doc.ref_links() {
+            for item in v {
+                match policy.canonical(item.url_ref(), k) {
+                    Ok(url) => records.push(WebLink::new(k, &url)),
+                    Err(_) => warn!("Could not parse link {} for {}.", item.url_ref(), k),
+                }
+            }
+        }
+        let mut links = WebLinks { records };
+        links.sort();
+        links
+    }
+}
+
+/// Strategies for exporting keys that link to more than one document.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+pub enum LinkStrategy {
+    /// Link to the earliest document by upload date.
+    First,
+    /// Link to the most recent document by upload date.
+    #[default]
+    Latest,
+    /// Join the links to all documents into a single field, separated by semicolons.
+    Joined,
+    /// Write the link to each document in a numbered column, `link_1`, `link_2` and so on.
+    Numbered,
+    /// Link to the most recent document, and write every document to a separate related table
+    /// with one row per key and document.
+    Related,
+}
+
+/// Row in the related table of documents for a key.  See [`LinkStrategy::Related`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LinkedDocument {
+    field: String,
+    document_id: i32,
+    name: String,
+    web_link: Url,
+    date: Option<String>,
+}
+
+impl LinkedDocument {
+    /// The `web_link` field holds the canonical url of the document.  This function returns a
+    /// reference to the field.
+    pub fn web_link_ref(&self) -> &Url {
+        &self.web_link
+    }
+
+    /// The `date` field holds the upload date of the document.  This function returns a reference
+    /// to the field.
+    pub fn date_ref(&self) -> &Option<String> {
+        &self.date
+    }
+}
+
+/// Row of links joined into a single field.  See [`LinkStrategy::Joined`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct JoinedLink {
+    field: String,
+    web_link: String,
+}
+
+/// Holds every document linked to each GIS key, ordered by key.  The documents for a key are
+/// ordered by upload date, then by document id.
+///
+/// # Example
+///
+/// ```rust
+/// # use linkbuilder::document::{DocumentLink, DocumentLinks};
+/// # use linkbuilder::export::{KeyedLinks, LinkPolicy};
+/// # use linkbuilder::scheme::SubdivisionScheme;
+/// # fn main() -> linkbuilder::prelude::LinkResult<()> {
+/// let mut links = DocumentLinks::default();
+/// let sheets = [(12, "Redwood Ph 1 Sheet 1", "2021-03-01"), (13, "Redwood Ph 1 Sheet 2", "2021-03-02")];
+/// for (id, name, date) in sheets {
+///     let url = format!("/DocumentCenter/View/{}", id);
+///     links.insert(name, DocumentLink::new(id, name, &url, Some(date.to_owned())));
+/// }
+/// let keyed = KeyedLinks::from_links(&links, &LinkPolicy::default(), &SubdivisionScheme::default())?;
+/// let docs = &keyed.ref_links()["REDWOOD PHASE 1"];
+/// assert_eq!(docs.len(), 2);
+/// assert_eq!(
+///     keyed.latest()["REDWOOD PHASE 1"].as_str(),
+///     "https://www.grantspassoregon.gov/DocumentCenter/View/13/Redwood-Ph-1-Sheet-2"
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct KeyedLinks {
+    links: BTreeMap<String, Vec<LinkedDocument>>,
+}
+
+impl KeyedLinks {
+    /// Groups the documents in `doc` by the keys parsed from their names with `scheme`,
+    /// converting each link to canonical form using `policy`.  Documents with names that do not
+    /// conform to `scheme` are left out; see [`scheme::NameIssues`].
+    pub fn from_links(
+        doc: &document::DocumentLinks,
+        policy: &LinkPolicy,
+        scheme: &dyn scheme::NameScheme,
+    ) -> error::LinkResult<Self> {
+        let mut links = BTreeMap::<String, Vec<LinkedDocument>>::new();
+        for (name, items) in doc.ref_links() {
+            let keys = scheme.keys(name);
+            for item in items {
+                let web_link = policy.canonical(item.url_ref(), name)?;
+                for key in &keys {
+                    links.entry(key.clone()).or_default().push(LinkedDocument {
+                        field: key.clone(),
+                        document_id: item.id(),
+                        name: item.name_ref().clone(),
+                        web_link: web_link.clone(),
+                        date: item.date_ref().clone(),
+                    });
+                }
+            }
+        }
+        for docs in links.values_mut() {
+            docs.sort_by(|a, b| {
+                a.date
+                    .cmp(&b.date)
+                    .then_with(|| a.document_id.cmp(&b.document_id))
+            });
+            docs.dedup_by_key(|d| d.document_id);
+        }
+        Ok(KeyedLinks { links })
+    }
+
+    /// The `links` field holds the documents for each key.  This function returns a reference to
+    /// the field.
+    pub fn ref_links(&self) -> &BTreeMap<String, Vec<LinkedDocument>> {
+        &self.links
+    }
+
+    /// Returns the link to the earliest document for each key.
+    pub fn first(&self) -> BTreeMap<String, Url> {
+        self.links
+            .iter()
+            .filter_map(|(k, v)| v.first().map(|d| (k.clone(), d.web_link.clone())))
+            .collect()
+    }
+
+    /// Returns the link to the most recent document for each key.
+    pub fn latest(&self) -> BTreeMap<String, Url> {
+        self.links
+            .iter()
+            .filter_map(|(k, v)| v.last().map(|d| (k.clone(), d.web_link.clone())))
+            .collect()
+    }
+
+    /// Writes the links to csv in the directory `output`, using `strategy` for keys with more
+    /// than one document.  The links file is named `{file}.csv`, and the related table for
+    /// [`LinkStrategy::Related`] is named `{file}_related.csv`.  Returns the paths written.
+    pub fn to_csv<P: AsRef<std::path::Path>>(
+        &self,
+        strategy: LinkStrategy,
+        output: P,
+        file: &str,
+    ) -> error::LinkResult<Vec<std::path::PathBuf>> {
+        let link_path = output.as_ref().join(format!("{}.csv", file));
+        let mut paths = vec![link_path.clone()];
+        match strategy {
+            LinkStrategy::First => self.to_web_links(self.first()).to_csv(&link_path)?,
+            LinkStrategy::Latest => self.to_web_links(self.latest()).to_csv(&link_path)?,
+            LinkStrategy::Joined => {
+                let mut records = self
+                    .links
+                    .iter()
+                    .map(|(k, v)| JoinedLink {
+                        field: k.clone(),
+                        web_link: v
+                            .iter()
+                            .map(|d| d.web_link.as_str())
+                            .collect::<Vec<&str>>()
+                            .join(";"),
+                    })
+                    .collect::<Vec<JoinedLink>>();
+                utils::to_csv(&mut records, &link_path)?;
+            }
+            LinkStrategy::Numbered => {
+                let width = self.links.values().map(|v| v.len()).max().unwrap_or(0);
+                let mut wtr = csv::Writer::from_path(&link_path)?;
+                let mut header = vec!["field".to_owned()];
+                header.extend((1..=width).map(|i| format!("link_{}", i)));
+                wtr.write_record(&header)?;
+                for (k, v) in &self.links {
+                    let mut row = vec![k.clone()];
+                    row.extend(v.iter().map(|d| d.web_link.to_string()));
+                    row.resize(width + 1, String::new());
+                    wtr.write_record(&row)?;
+                }
+                wtr.flush()?;
+            }
+            LinkStrategy::Related => {
+                self.to_web_links(self.latest()).to_csv(&link_path)?;
+                let related_path = output.as_ref().join(format!("{}_related.csv", file));
+                let mut records = self.links.values().flatten().cloned().collect::<Vec<_>>();
+                utils::to_csv(&mut records, &related_path)?;
+                paths.push(related_path);
+            }
+        }
+        Ok(paths)
+    }
+
+    fn to_web_links(&self, links: BTreeMap<String, Url>) -> WebLinks {
+        let records = links
             .iter()
             .map(|(k, v)| WebLink::new(k, v))
             .collect::<Vec<WebLink>>();
@@ -1,3 +1,5 @@
+use crate::journal::JournalEntry;
+use crate::store::DocumentStore;
 use crate::{authorize, document, error};
 use data_encoding::BASE64;
 use indicatif::ProgressBar;
@@ -22,11 +24,15 @@ impl FileNames {
     }
 
     /// Reads files from a local directory specified by `path` into a `FileNames` struct.
+    /// Subdirectories are skipped; see [`FileNames::directories()`].
     pub fn from_path<P: AsRef<std::path::Path>>(path: P) -> Result<Self, error::LinkError> {
         let files = fs::read_dir(path)?;
         let mut names = HashMap::new();
         for file in files {
             let file_path = file?.path();
+            if file_path.is_dir() {
+                continue;
+            }
             let file_stem = file_path.file_stem();
             if let Some(name) = file_stem {
                 let name = name.to_owned().into_string();
@@ -38,6 +44,25 @@ impl FileNames {
         Ok(FileNames { names })
     }
 
+    /// Returns the names and paths of the subdirectories in the local directory `path`, sorted by
+    /// name.  Used to sync nested folder layouts, where each subdirectory holds the documents for
+    /// one project.
+    pub fn directories<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> Result<Vec<(String, std::path::PathBuf)>, error::LinkError> {
+        let mut dirs = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let dir_path = entry?.path();
+            if dir_path.is_dir() {
+                if let Some(name) = dir_path.file_name().and_then(|n| n.to_str()) {
+                    dirs.push((name.to_owned(), dir_path.clone()));
+                }
+            }
+        }
+        dirs.sort();
+        Ok(dirs)
+    }
+
     /// Returns the set of key:value pairs in `FileNames` where the key (the file name) is not present
     /// in `links`.  Used to determine which files on the local folder have not been uploaded to
     /// the destination folder on the CivicEngage Document Center.
@@ -103,22 +128,56 @@ impl FileNames {
                 .send()
                 .await?;
             bar.inc(1);
-            match &res.status() {
-                &reqwest::StatusCode::OK => {
-                    rec.push(res.json().await?);
+            let success = matches!(
+                res.status(),
+                reqwest::StatusCode::OK | reqwest::StatusCode::CREATED
+            );
+            let result = match success {
+                true => {
+                    let result: String = res.json().await?;
+                    rec.push(result.clone());
+                    result
                 }
-                &reqwest::StatusCode::CREATED => {
-                    rec.push(res.json().await?);
-                }
-                _ => {
-                    warn!("Response: {:?}", res.text().await?);
+                false => {
+                    let result = res.text().await?;
+                    warn!("Response: {:?}", result);
+                    result
                 }
+            };
+            if let Some(journal) = info.journal_ref() {
+                let mut entry = JournalEntry::new(journal.run_id_ref(), user.user_id(), "upload");
+                entry.name = Some(name.clone());
+                entry.folder_id = Some(id);
+                entry.result = result;
+                entry.success = success;
+                journal.record(&entry)?;
             }
         }
 
         Ok(rec)
     }
 
+    /// Upload files in `FileNames` to the folder with id `id` in `store`, with status
+    /// "Published".  Calls [`DocumentStore::upload()`].  Returns the responses from the store.
+    pub async fn upload_to(
+        &self,
+        store: &dyn DocumentStore,
+        id: i32,
+    ) -> Result<Vec<String>, error::LinkError> {
+        let mut rec = Vec::new();
+        let style = indicatif::ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {'Uploading files.'}",
+        )
+        .unwrap();
+        let bar = ProgressBar::new(self.names().len() as u64);
+        bar.set_style(style);
+        for (name, path) in self.names() {
+            rec.push(store.upload(&name, &path, id, true).await?);
+            bar.inc(1);
+        }
+        Ok(rec)
+    }
+
     /// The `names` field holds a HashMap of file names and file paths.  This function returns the
     /// cloned value of the field.
     pub fn names(&self) -> HashMap<String, std::path::PathBuf> {
@@ -128,6 +187,14 @@ impl FileNames {
 
 impl From<&document::DocumentLinks> for FileNames {
     fn from(links: &document::DocumentLinks) -> Self {
-        FileNames::new(links.ref_links().clone())
+        let names = links
+            .ref_links()
+            .iter()
+            .filter_map(|(k, v)| {
+                v.first()
+                    .map(|l| (k.clone(), std::path::PathBuf::from(l.url_ref())))
+            })
+            .collect::<HashMap<String, std::path::PathBuf>>();
+        FileNames::new(names)
     }
 }
@@ -0,0 +1,451 @@
+//! Records responses from CivicEngage to fixture files and replays them.
+//!
+//! A [`FixtureServer`] stands between the program and CivicEngage on a local port.  In record
+//! mode it forwards each request to the site and writes the request and response to a
+//! [`Fixture`] file in a directory, numbered in the order received.  Session keys, passwords and
+//! uploaded file contents are scrubbed before writing, so fixtures can be checked in.  In replay
+//! mode it answers requests from the fixture files without a connection, either in the order
+//! recorded or by matching the method, path and query of each request.  See [`ReplayMode`].
+//!
+//! From the command line, pass `--record <DIR>` to record a run and `--replay <DIR>` to replay
+//! it.  The module and both flags are built only with the `test-support` feature.  Use [`verify`] to check that recorded responses still parse into [`AuthResponse`],
+//! [`Folders`] and [`Documents`], so that changes to the fields CivicEngage returns are caught in
+//! tests.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::fixture::{FixtureServer, ReplayMode};
+//! # use linkbuilder::mock::MockServer;
+//! # use linkbuilder::prelude::*;
+//! # #[tokio::main]
+//! # async fn main() -> LinkResult<()> {
+//! let dir = std::env::temp_dir().join("linkbuilder_fixture_example");
+//! # let _ = std::fs::remove_dir_all(&dir);
+//! let server = MockServer::start()?;
+//! server.add_folder("Plats", None);
+//!
+//! // Record a login and a folder query.
+//! let recorder = FixtureServer::record(&server.site(), &dir)?;
+//! let mut settings = server.settings();
+//! settings.site = Some(recorder.site());
+//! let user = authorize_user(&settings).await?;
+//! let store = CivicEngageStore::from_settings(&settings, &user)?;
+//! store.folders().await?;
+//! drop(recorder);
+//! drop(server);
+//! assert_eq!(linkbuilder::fixture::verify(&dir)?, 2);
+//!
+//! // Replay them without the server.
+//! let replayer = FixtureServer::replay(&dir, ReplayMode::Sequence)?;
+//! settings.site = Some(replayer.site());
+//! let user = authorize_user(&settings).await?;
+//! let store = CivicEngageStore::from_settings(&settings, &user)?;
+//! assert!(store.folders().await?.get_id("Plats").is_some());
+//! # std::fs::remove_dir_all(&dir)?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::authorize::AuthResponse;
+use crate::mock::{reply, spawn};
+use crate::prelude::*;
+use data_encoding::BASE64;
+use hyper::{Body, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tracing::{info, trace, warn};
+
+/// Value written in place of secrets in fixtures.
+pub const REDACTED: &str = "[redacted]";
+
+/// Fields holding secrets, replaced with [`REDACTED`] in request and response bodies.
+const SECRET_FIELDS: [&str; 4] = ["Password", "APIKey", "ApiKey", "UserApiKey"];
+
+/// Fields holding file contents, replaced with a byte count in request bodies.
+const FILE_FIELDS: [&str; 1] = ["File"];
+
+/// Response headers kept in fixtures.
+const KEPT_HEADERS: [&str; 3] = ["content-type", "content-disposition", "retry-after"];
+
+/// A request to CivicEngage and its response.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Fixture {
+    /// HTTP method of the request.
+    pub method: String,
+    /// Path of the request, without the query string.
+    pub path: String,
+    /// Query string of the request, if any.
+    pub query: Option<String>,
+    /// Body of the request, with secrets scrubbed.  Empty if the body was not JSON.
+    pub request: Option<Value>,
+    /// HTTP status code of the response.
+    pub status: u16,
+    /// Response headers kept from the response.
+    pub headers: BTreeMap<String, String>,
+    /// Body of the response, with secrets scrubbed.  Bodies that are not JSON are held as a
+    /// base64 string.
+    pub body: Value,
+    /// True if `body` holds base64 encoded bytes.
+    #[serde(default)]
+    pub binary: bool,
+}
+
+impl Fixture {
+    /// Reads the fixture file at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> LinkResult<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Reads every fixture file in `dir`, in the order recorded.
+    pub fn read_dir<P: AsRef<Path>>(dir: P) -> LinkResult<Vec<(PathBuf, Self)>> {
+        let mut paths = std::fs::read_dir(dir)?
+            .filter_map(|f| f.ok())
+            .map(|f| f.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "json"))
+            .collect::<Vec<PathBuf>>();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|p| Self::read(&p).map(|f| (p, f)))
+            .collect()
+    }
+
+    /// Returns the bytes of the response body.
+    pub fn body_bytes(&self) -> Vec<u8> {
+        match (&self.body, self.binary) {
+            (Value::String(body), true) => BASE64.decode(body.as_bytes()).unwrap_or_default(),
+            (body, _) => body.to_string().into_bytes(),
+        }
+    }
+
+    /// Returns true if the fixture answers a request with `method`, `path` and `query`.
+    pub fn matches(&self, method: &str, path: &str, query: &Option<String>) -> bool {
+        self.method == method && self.path == path && &self.query == query
+    }
+
+    /// Builds the response held by the fixture.
+    fn response(&self) -> Response<Body> {
+        let mut res =
+            Response::builder().status(StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK));
+        for (name, value) in &self.headers {
+            res = res.header(name, value);
+        }
+        res.body(Body::from(self.body_bytes())).unwrap_or_default()
+    }
+}
+
+/// Replaces secrets in `value` with [`REDACTED`], and file contents with a byte count.
+pub fn scrub(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                if SECRET_FIELDS.contains(&key.as_str()) && !item.is_null() {
+                    *item = json!(REDACTED);
+                } else if FILE_FIELDS.contains(&key.as_str()) {
+                    if let Some(file) = item.as_str() {
+                        *item = json!(format!("[{} base64 characters]", file.len()));
+                    }
+                } else {
+                    scrub(item);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(scrub),
+        _ => {}
+    }
+}
+
+/// Order in which a replaying [`FixtureServer`] answers requests.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum ReplayMode {
+    /// Answer each request with the next fixture, refusing requests that do not match it.
+    Sequence,
+    /// Answer each request with the first unused fixture matching its method, path and query,
+    /// reusing the last match once every match is used.
+    #[default]
+    Match,
+}
+
+/// Fixtures served by a replaying [`FixtureServer`].
+#[derive(Debug)]
+struct Replay {
+    mode: ReplayMode,
+    fixtures: Vec<(Fixture, bool)>,
+    next: usize,
+}
+
+impl Replay {
+    /// Returns the response to a request with `method`, `path` and `query`.
+    fn respond(&mut self, method: &str, path: &str, query: &Option<String>) -> Response<Body> {
+        let found = match self.mode {
+            ReplayMode::Sequence => {
+                let next = self.next;
+                match self.fixtures.get(next) {
+                    Some((fixture, _)) if fixture.matches(method, path, query) => {
+                        self.next += 1;
+                        Some(next)
+                    }
+                    Some((fixture, _)) => {
+                        warn!(
+                            "Fixture {} is {} {}, not {} {}.",
+                            next, fixture.method, fixture.path, method, path
+                        );
+                        None
+                    }
+                    None => None,
+                }
+            }
+            ReplayMode::Match => {
+                let matches = self
+                    .fixtures
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (f, _))| f.matches(method, path, query))
+                    .map(|(i, (_, used))| (i, *used))
+                    .collect::<Vec<(usize, bool)>>();
+                matches
+                    .iter()
+                    .find(|(_, used)| !used)
+                    .or(matches.last())
+                    .map(|(i, _)| *i)
+            }
+        };
+        match found {
+            Some(i) => {
+                self.fixtures[i].1 = true;
+                trace!("Replaying fixture {} for {} {}.", i, method, path);
+                self.fixtures[i].0.response()
+            }
+            None => {
+                warn!("No fixture for {} {}.", method, path);
+                reply(
+                    StatusCode::NOT_FOUND,
+                    json!({ "Message": format!("No fixture for {} {}.", method, path) }),
+                )
+            }
+        }
+    }
+}
+
+/// Local server recording responses from CivicEngage to fixtures, or replaying them.  The server
+/// stops when dropped.
+#[derive(Debug)]
+pub struct FixtureServer {
+    addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl FixtureServer {
+    /// Starts a `FixtureServer` forwarding requests to the site `upstream`, e.g.
+    /// `https://www.grantspassoregon.gov`, and recording them to fixture files in `dir`.  Must be
+    /// called from within a tokio runtime.
+    pub fn record<P: AsRef<Path>>(upstream: &str, dir: P) -> LinkResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        let count = Fixture::read_dir(&dir)?.len();
+        let upstream = upstream.trim_end_matches('/').to_owned();
+        let client = reqwest::Client::new();
+        let next = Arc::new(Mutex::new(count));
+        let (addr, shutdown) = spawn(move |req| {
+            forward(
+                client.clone(),
+                upstream.clone(),
+                dir.clone(),
+                next.clone(),
+                req,
+            )
+        })?;
+        info!("Recording fixtures through {}.", addr);
+        Ok(FixtureServer {
+            addr,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Starts a `FixtureServer` answering requests from the fixture files in `dir` in the order
+    /// given by `mode`.  Must be called from within a tokio runtime.
+    pub fn replay<P: AsRef<Path>>(dir: P, mode: ReplayMode) -> LinkResult<Self> {
+        let fixtures = Fixture::read_dir(&dir)?
+            .into_iter()
+            .map(|(_, f)| (f, false))
+            .collect::<Vec<(Fixture, bool)>>();
+        info!(
+            "Replaying {} fixtures from {}.",
+            fixtures.len(),
+            dir.as_ref().display()
+        );
+        let replay = Arc::new(Mutex::new(Replay {
+            mode,
+            fixtures,
+            next: 0,
+        }));
+        let (addr, shutdown) = spawn(move |req: Request<Body>| {
+            let replay = replay.clone();
+            async move {
+                let query = req.uri().query().map(|q| q.to_owned());
+                let mut replay = replay.lock().unwrap_or_else(|e| e.into_inner());
+                Ok::<_, Infallible>(replay.respond(req.method().as_str(), req.uri().path(), &query))
+            }
+        })?;
+        Ok(FixtureServer {
+            addr,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Returns the base url of the server, to use as the `site` setting.
+    pub fn site(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Drop for FixtureServer {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            tx.send(()).ok();
+        }
+    }
+}
+
+/// Forwards `req` to `upstream` and records the exchange as the next fixture in `dir`.
+async fn forward(
+    client: reqwest::Client,
+    upstream: String,
+    dir: PathBuf,
+    next: Arc<Mutex<usize>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    match exchange(&client, &upstream, &dir, &next, req).await {
+        Ok(res) => Ok(res),
+        Err(e) => {
+            warn!("Recording failed: {}", e);
+            Ok(reply(
+                StatusCode::BAD_GATEWAY,
+                json!({ "Message": e.to_string() }),
+            ))
+        }
+    }
+}
+
+/// Sends `req` to `upstream`, writes the fixture and returns the response.
+async fn exchange(
+    client: &reqwest::Client,
+    upstream: &str,
+    dir: &Path,
+    next: &Mutex<usize>,
+    req: Request<Body>,
+) -> LinkResult<Response<Body>> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_owned();
+    let query = req.uri().query().map(|q| q.to_owned());
+    let mut headers = req.headers().clone();
+    headers.remove(hyper::header::HOST);
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let url = match &query {
+        Some(query) => format!("{}{}?{}", upstream, path, query),
+        None => format!("{}{}", upstream, path),
+    };
+    trace!("Forwarding {} {}.", method, url);
+    let res = client
+        .request(method.clone(), &url)
+        .headers(headers)
+        .body(body.to_vec())
+        .send()
+        .await?;
+    let status = res.status();
+    let kept = res
+        .headers()
+        .iter()
+        .filter(|(name, _)| KEPT_HEADERS.contains(&name.as_str()))
+        .filter_map(|(name, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|v| (name.as_str().to_owned(), v.to_owned()))
+        })
+        .collect::<BTreeMap<String, String>>();
+    let data = res.bytes().await?;
+
+    let request = serde_json::from_slice::<Value>(&body).ok().map(|mut v| {
+        scrub(&mut v);
+        v
+    });
+    let (body, binary) = match serde_json::from_slice::<Value>(&data) {
+        Ok(mut value) => {
+            scrub(&mut value);
+            (value, false)
+        }
+        Err(_) => (json!(BASE64.encode(&data)), true),
+    };
+    let fixture = Fixture {
+        method: method.to_string(),
+        path: path.clone(),
+        query,
+        request,
+        status: status.as_u16(),
+        headers: kept,
+        body,
+        binary,
+    };
+    let index = {
+        let mut next = next.lock().unwrap_or_else(|e| e.into_inner());
+        *next += 1;
+        *next
+    };
+    let name = format!(
+        "{:04}_{}_{}.json",
+        index,
+        method.as_str().to_lowercase(),
+        crate::export::slug(path.trim_matches('/')).replace('-', "_")
+    );
+    std::fs::write(dir.join(&name), serde_json::to_string_pretty(&fixture)?)?;
+    trace!("Fixture written to {}.", name);
+    // The client receives the response as recorded, with secrets intact.
+    let mut response = Response::builder().status(status);
+    for (name, value) in &fixture.headers {
+        response = response.header(name, value);
+    }
+    Ok(response.body(Body::from(data)).unwrap_or_default())
+}
+
+/// Parses the successful responses in the fixture files in `dir` into the types the program
+/// reads them into: [`AuthResponse`] for logins, [`Folders`] for folder queries and
+/// [`Documents`] for document queries.  Returns the count of responses checked, or an error
+/// naming the first fixture that does not parse.
+pub fn verify<P: AsRef<Path>>(dir: P) -> LinkResult<usize> {
+    let mut count = 0;
+    for (path, fixture) in Fixture::read_dir(dir)? {
+        if fixture.status != 200 || fixture.binary {
+            continue;
+        }
+        let body = fixture.body.clone();
+        let result = match (fixture.method.as_str(), fixture.path.as_str()) {
+            ("POST", p) if p.ends_with("/Authentication/v1/Authenticate") => {
+                serde_json::from_value::<AuthResponse>(body).map(|_| ())
+            }
+            ("GET", p) if p.ends_with("/DocumentCenter/v1/Folder") => {
+                serde_json::from_value::<Folders>(body).map(|_| ())
+            }
+            ("GET", p) if p.ends_with("/DocumentCenter/v1/Document") => {
+                serde_json::from_value::<Documents>(body).map(|_| ())
+            }
+            _ => continue,
+        };
+        match result {
+            Ok(()) => count += 1,
+            Err(e) => {
+                return Err(LinkError::FixtureError {
+                    path: path.display().to_string(),
+                    message: e.to_string(),
+                })
+            }
+        }
+    }
+    Ok(count)
+}
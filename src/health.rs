@@ -0,0 +1,326 @@
+//! Checks web links exported to the GIS layers against the Document Center.
+//!
+//! Links break when documents on the Document Center are archived, set to draft or deleted.  The
+//! [`LinkChecker`] reads the document id out of each link, looks up the matching [`Document`] in
+//! the results of a [`Documents`] query, and optionally sends an HTTP HEAD request to the link.
+//! Links that fail a check are collected in [`BrokenLinks`] with the reason for the failure.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::health::{document_id, LinkCheck, LinkChecks};
+//! let link = "https://www.grantspassoregon.gov/DocumentCenter/View/31368/GPLogo_450W-PNG";
+//! assert_eq!(document_id(link), Some(31368));
+//! assert_eq!(document_id("https://www.grantspassoregon.gov/27/Departments"), None);
+//!
+//! let checks = LinkChecks::from(vec![LinkCheck::new("GPLogo", link)]);
+//! assert_eq!(checks.records_ref().len(), 1);
+//! ```
+use crate::document::Document;
+use crate::prelude::*;
+use crate::utils;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::{info, trace};
+
+/// Returns the Document Center id from a link of the form `/DocumentCenter/View/{id}/{slug}`.
+/// Returns `None` if the link does not point to a document on the Document Center.
+pub fn document_id(link: &str) -> Option<i32> {
+    let mut parts = link.split('/');
+    while let Some(part) = parts.next() {
+        if part.eq_ignore_ascii_case("DocumentCenter") {
+            if let Some(view) = parts.next() {
+                if view.eq_ignore_ascii_case("View") {
+                    return parts.next().and_then(|id| id.parse::<i32>().ok());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Reasons a web link can fail a health check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LinkFailure {
+    /// The link does not contain a Document Center id.
+    MalformedUrl,
+    /// No document with the link id was found on the Document Center.
+    MissingDocument,
+    /// The linked document is archived.
+    Archived,
+    /// The linked document has "Draft" status.
+    Draft,
+    /// The linked document is hidden from the public listing.
+    Hidden,
+    /// The HEAD request returned an unsuccessful status code.
+    HttpStatus(u16),
+    /// The HEAD request could not be completed.
+    HttpError(String),
+}
+
+impl std::fmt::Display for LinkFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkFailure::MalformedUrl => write!(f, "Link does not contain a document id."),
+            LinkFailure::MissingDocument => write!(f, "Document not found on Document Center."),
+            LinkFailure::Archived => write!(f, "Document is archived."),
+            LinkFailure::Draft => write!(f, "Document is in draft status."),
+            LinkFailure::Hidden => write!(f, "Document is not visible."),
+            LinkFailure::HttpStatus(code) => write!(f, "HEAD request returned status {}.", code),
+            LinkFailure::HttpError(msg) => write!(f, "HEAD request failed: {}", msg),
+        }
+    }
+}
+
+/// A web link to check, identified by the key value `field`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LinkCheck {
+    field: String,
+    web_link: String,
+}
+
+impl LinkCheck {
+    /// Creates a new `LinkCheck` from an identifier `field` and url `web_link`.
+    pub fn new(field: &str, web_link: &str) -> Self {
+        LinkCheck {
+            field: field.to_owned(),
+            web_link: web_link.to_owned(),
+        }
+    }
+
+    /// The `field` field holds the identifier for the link.  This function returns a reference to
+    /// the field.
+    pub fn field_ref(&self) -> &String {
+        &self.field
+    }
+
+    /// The `web_link` field holds the url to check.  This function returns a reference to the
+    /// field.
+    pub fn web_link_ref(&self) -> &String {
+        &self.web_link
+    }
+}
+
+/// Holds a vector of [`LinkCheck`] objects.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LinkChecks {
+    records: Vec<LinkCheck>,
+}
+
+impl LinkChecks {
+    /// Reads links from a GIS attribute table exported to csv at path `path`.  The column named
+    /// `key` identifies the feature, and the column named `link` holds the url.  Rows with an
+    /// empty link are skipped.
+    pub fn from_attributes<P: AsRef<std::path::Path>>(
+        path: P,
+        key: &str,
+        link: &str,
+    ) -> LinkResult<Self> {
+        let mut rdr = csv::Reader::from_path(path)?;
+        let headers = rdr.headers()?.clone();
+        let key_idx = headers
+            .iter()
+            .position(|h| h == key)
+            .ok_or(LinkError::FieldError {
+                value: key.to_owned(),
+            })?;
+        let link_idx = headers
+            .iter()
+            .position(|h| h == link)
+            .ok_or(LinkError::FieldError {
+                value: link.to_owned(),
+            })?;
+        let mut records = Vec::new();
+        for result in rdr.records() {
+            let row = result?;
+            if let (Some(field), Some(web_link)) = (row.get(key_idx), row.get(link_idx)) {
+                if !web_link.trim().is_empty() {
+                    records.push(LinkCheck::new(field, web_link.trim()));
+                }
+            }
+        }
+        Ok(LinkChecks { records })
+    }
+
+    /// The `records` field holds a vector of [`LinkCheck`] objects.  This function returns a
+    /// reference to the field.
+    pub fn records_ref(&self) -> &Vec<LinkCheck> {
+        &self.records
+    }
+}
+
+impl From<Vec<LinkCheck>> for LinkChecks {
+    fn from(records: Vec<LinkCheck>) -> Self {
+        LinkChecks { records }
+    }
+}
+
+impl From<&WebLinks> for LinkChecks {
+    fn from(links: &WebLinks) -> Self {
+        let records = links
+            .records_ref()
+            .iter()
+            .map(|l| LinkCheck::new(l.field_ref(), l.web_link_ref().as_str()))
+            .collect::<Vec<LinkCheck>>();
+        LinkChecks { records }
+    }
+}
+
+/// Row in the broken link report.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BrokenLink {
+    field: String,
+    web_link: String,
+    document_id: Option<i32>,
+    reason: String,
+}
+
+impl BrokenLink {
+    /// Creates a new `BrokenLink` from the link that failed and the reason for failure.
+    pub fn new(check: &LinkCheck, document_id: Option<i32>, reason: &LinkFailure) -> Self {
+        BrokenLink {
+            field: check.field.clone(),
+            web_link: check.web_link.clone(),
+            document_id,
+            reason: reason.to_string(),
+        }
+    }
+
+    /// The `reason` field describes why the link failed.  This function returns a reference to
+    /// the field.
+    pub fn reason_ref(&self) -> &String {
+        &self.reason
+    }
+}
+
+/// Holds a vector of [`BrokenLink`] objects.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BrokenLinks {
+    records: Vec<BrokenLink>,
+}
+
+impl BrokenLinks {
+    /// The `records` field holds a vector of [`BrokenLink`] objects.  This function returns a
+    /// reference to the field.
+    pub fn records_ref(&self) -> &Vec<BrokenLink> {
+        &self.records
+    }
+
+    /// Outputs the broken link report to csv at path `title`.
+    pub fn to_csv<P: AsRef<std::path::Path>>(&mut self, title: P) -> Result<(), std::io::Error> {
+        utils::to_csv(&mut self.records, title)?;
+        Ok(())
+    }
+}
+
+/// Checks web links against the documents returned by a [`Documents`] query.
+#[derive(Clone, Debug)]
+pub struct LinkChecker {
+    documents: HashMap<i32, Document>,
+    head: bool,
+    hidden: bool,
+}
+
+impl LinkChecker {
+    /// Creates a new `LinkChecker` from the results of a [`Documents`] query.  The query should
+    /// cover every folder the links point into, e.g. the full Document Center.
+    pub fn new(docs: &Documents) -> Self {
+        let mut documents = HashMap::new();
+        if let Some(items) = docs.source_ref() {
+            for item in items {
+                documents.insert(item.id(), item.clone());
+            }
+        }
+        LinkChecker {
+            documents,
+            head: false,
+            hidden: false,
+        }
+    }
+
+    /// When `value` is true, sends an HTTP HEAD request to each link that passes the document
+    /// checks.
+    pub fn head(&mut self, value: bool) -> &mut Self {
+        self.head = value;
+        self
+    }
+
+    /// When `value` is true, reports documents that are not visible in the public listing.  Files
+    /// uploaded by [`FileNames::upload()`] are not visible by default, so this check is off
+    /// unless requested.
+    pub fn hidden(&mut self, value: bool) -> &mut Self {
+        self.hidden = value;
+        self
+    }
+
+    /// Checks a single link against the document list, returning the document id and the
+    /// reason for failure, if any.
+    pub fn check_document(&self, link: &LinkCheck) -> (Option<i32>, Option<LinkFailure>) {
+        let id = match document_id(&link.web_link) {
+            Some(id) => id,
+            None => return (None, Some(LinkFailure::MalformedUrl)),
+        };
+        let failure = match self.documents.get(&id) {
+            None => Some(LinkFailure::MissingDocument),
+            Some(doc) => {
+                if doc.is_archived() == &Some(true) {
+                    Some(LinkFailure::Archived)
+                } else if doc.status_ref() == &Some(10) {
+                    Some(LinkFailure::Draft)
+                } else if self.hidden && doc.is_visible_ref() == &Some(false) {
+                    Some(LinkFailure::Hidden)
+                } else {
+                    None
+                }
+            }
+        };
+        (Some(id), failure)
+    }
+
+    /// Checks each link in `links`, returning the links that failed.
+    pub async fn check(&self, links: &LinkChecks) -> LinkResult<BrokenLinks> {
+        let client = reqwest::Client::new();
+        let style = indicatif::ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {'Checking links.'}",
+        )
+        .unwrap();
+        let bar = ProgressBar::new(links.records.len() as u64);
+        bar.set_style(style);
+        let mut records = Vec::new();
+        for link in &links.records {
+            let (id, mut failure) = self.check_document(link);
+            if failure.is_none() && self.head {
+                failure = Self::check_head(&client, &link.web_link).await;
+            }
+            if let Some(reason) = failure {
+                trace!("Link {} failed: {}", link.web_link, reason);
+                records.push(BrokenLink::new(link, id, &reason));
+            }
+            bar.inc(1);
+        }
+        info!(
+            "Broken links: {} of {} checked.",
+            records.len(),
+            links.records.len()
+        );
+        Ok(BrokenLinks { records })
+    }
+
+    async fn check_head(client: &reqwest::Client, link: &str) -> Option<LinkFailure> {
+        if !link.starts_with("http") {
+            trace!("Skipping HEAD request for relative link {}.", link);
+            return None;
+        }
+        match client.head(link).send().await {
+            Ok(res) => {
+                if res.status().is_success() {
+                    None
+                } else {
+                    Some(LinkFailure::HttpStatus(res.status().as_u16()))
+                }
+            }
+            Err(e) => Some(LinkFailure::HttpError(e.to_string())),
+        }
+    }
+}
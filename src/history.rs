@@ -0,0 +1,234 @@
+//! Storage history and growth trends.
+//!
+//! Each run of the storage report appends a snapshot of [`FolderSizes`] to a csv history, one
+//! row per folder with the date of the run.  [`StorageHistory::trend`] fits a line through the
+//! snapshots of each folder to estimate its growth per year and, given a storage quota,
+//! projects the date the total storage reaches the quota.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::history::StorageHistory;
+//! # use linkbuilder::prelude::*;
+//! # fn main() -> LinkResult<()> {
+//! let path = std::env::temp_dir().join("linkbuilder_history_example.csv");
+//! # let _ = std::fs::remove_file(&path);
+//! let snapshot = |total: f64| {
+//!     FolderSizes::from(vec![FolderSize::new("GIS", total / 2.), FolderSize::new("Total", total)])
+//! };
+//! StorageHistory::append(&path, Some("2025-01-01T00:00:00"), &snapshot(1_000_000.))?;
+//! StorageHistory::append(&path, Some("2026-01-01T00:00:00"), &snapshot(2_000_000.))?;
+//!
+//! let trend = StorageHistory::read(&path)?.trend(Some(4_000_000.))?;
+//! assert_eq!(trend[1].folder, "Total");
+//! assert_eq!(trend[1].snapshots, 2);
+//! assert_eq!(trend[1].quota_percent, Some(50.0));
+//! assert_eq!(trend[1].quota_date.as_deref(), Some("2028-01-01"));
+//! assert_eq!(trend[0].quota_date, None);
+//! # std::fs::remove_file(&path)?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::prelude::*;
+use crate::report::TOTAL;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::{info, trace};
+
+/// Name of the storage history file.
+pub const HISTORY_FILE: &str = "report_history.csv";
+
+/// Format of the dates in the history.
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Size of a folder on the date of a report run, a row of the storage history.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Snapshot {
+    /// Date of the report run.
+    pub date: String,
+    /// Path of the folder, or "Total" for the whole Document Center.
+    pub folder: String,
+    /// Storage in KB.
+    pub kb: f64,
+}
+
+/// Growth of the storage in a folder over the snapshots in the history.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StorageTrend {
+    /// Path of the folder, or "Total" for the whole Document Center.
+    pub folder: String,
+    /// Count of snapshots of the folder.
+    pub snapshots: usize,
+    /// Date of the first snapshot.
+    pub first_date: String,
+    /// Date of the last snapshot.
+    pub last_date: String,
+    /// Storage in the last snapshot, in readable units.
+    pub size: String,
+    /// Storage in the last snapshot, in KB.
+    pub kb: f64,
+    /// Change in storage from the first snapshot to the last, in KB.
+    pub growth_kb: f64,
+    /// Estimated growth per year, in readable units.
+    pub per_year: String,
+    /// Estimated growth per year, in KB.
+    pub kb_per_year: f64,
+    /// Share of the quota used in the last snapshot, from 0 to 100.  Set for the total only.
+    pub quota_percent: Option<f64>,
+    /// Date the total reached the quota, or the projected date it will at the estimated growth.
+    /// Set for the total only, when the quota is reached or the total is growing fast enough to
+    /// reach it within the range of dates supported.
+    pub quota_date: Option<String>,
+}
+
+/// Snapshots of folder sizes read from the storage history.
+#[derive(Clone, Debug, Default)]
+pub struct StorageHistory {
+    records: Vec<Snapshot>,
+}
+
+impl StorageHistory {
+    /// Reads the storage history at `path`.  Returns an empty history if the file does not
+    /// exist.
+    pub fn read<P: AsRef<Path>>(path: P) -> LinkResult<Self> {
+        if !path.as_ref().is_file() {
+            trace!("No storage history at {}.", path.as_ref().display());
+            return Ok(Self::default());
+        }
+        let mut rdr = csv::Reader::from_path(path)?;
+        let records = rdr
+            .deserialize::<Snapshot>()
+            .collect::<Result<Vec<Snapshot>, csv::Error>>()?;
+        Ok(StorageHistory { records })
+    }
+
+    /// Appends the folder sizes in `sizes` to the storage history at `path`, dated `date` in the
+    /// form `YYYY-MM-DDTHH:MM:SS`, or the current time if `date` is `None`.  Writes the header if
+    /// the file is new.
+    pub fn append<P: AsRef<Path>>(
+        path: P,
+        date: Option<&str>,
+        sizes: &FolderSizes,
+    ) -> LinkResult<()> {
+        let date = date.map_or_else(crate::store::now, |d| d.to_owned());
+        let exists = path.as_ref().is_file();
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_ref())?;
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(!exists)
+            .from_writer(file);
+        for record in sizes.records_ref() {
+            wtr.serialize(Snapshot {
+                date: date.clone(),
+                folder: record.folder().to_owned(),
+                kb: record.size(),
+            })?;
+        }
+        wtr.flush()?;
+        info!("Storage snapshot appended to {}.", path.as_ref().display());
+        Ok(())
+    }
+
+    /// The `records` field holds the snapshots in the history.  This function returns a
+    /// reference to the field.
+    pub fn records_ref(&self) -> &Vec<Snapshot> {
+        &self.records
+    }
+
+    /// Returns the growth of each folder in the history, with the total last.  Growth per year
+    /// is the slope of a least squares line through the snapshots, and is 0 for a folder with a
+    /// single snapshot.  With a `quota` in KB, the total is compared against it.
+    pub fn trend(&self, quota: Option<f64>) -> LinkResult<Vec<StorageTrend>> {
+        let mut series = BTreeMap::new();
+        for record in &self.records {
+            let date = NaiveDateTime::parse_from_str(&record.date, DATE_FORMAT).map_err(|_| {
+                LinkError::HistoryError {
+                    value: format!(
+                        "{} is not a date in the form YYYY-MM-DDTHH:MM:SS",
+                        record.date
+                    ),
+                }
+            })?;
+            series
+                .entry((record.folder == TOTAL, record.folder.clone()))
+                .or_insert_with(Vec::new)
+                .push((date, record.kb));
+        }
+        let mut trends = Vec::new();
+        for ((total, folder), mut points) in series {
+            points.sort_by_key(|p| p.0);
+            let (first, first_kb) = points[0];
+            let (last, last_kb) = points[points.len() - 1];
+            let per_day = slope(&points);
+            let mut trend = StorageTrend {
+                folder,
+                snapshots: points.len(),
+                first_date: first.format(DATE_FORMAT).to_string(),
+                last_date: last.format(DATE_FORMAT).to_string(),
+                size: readable(last_kb)?,
+                kb: last_kb,
+                growth_kb: last_kb - first_kb,
+                per_year: readable(per_day * 365.25)?,
+                kb_per_year: (per_day * 365.25).round(),
+                ..Default::default()
+            };
+            if let Some(quota) = quota.filter(|_| total) {
+                trend.quota_percent = Some((last_kb / quota * 10000.).round() / 100.);
+                trend.quota_date = match points.iter().find(|(_, kb)| *kb >= quota) {
+                    Some((date, _)) => Some(date.format("%Y-%m-%d").to_string()),
+                    // Projections past the range of dates chrono can hold are left out.
+                    None if per_day > 0. => {
+                        let days = ((quota - last_kb) / per_day).round() as i64;
+                        chrono::TimeDelta::try_days(days)
+                            .and_then(|delta| last.checked_add_signed(delta))
+                            .map(|date| date.format("%Y-%m-%d").to_string())
+                    }
+                    None => None,
+                };
+            }
+            trends.push(trend);
+        }
+        Ok(trends)
+    }
+}
+
+/// Parses the storage quota `value`, such as "100 GB", returning it in KB.
+pub fn quota_kb(value: &str) -> LinkResult<f64> {
+    Ok(byte_unit::Byte::from_str(value)?.get_bytes() as f64 / 1000.)
+}
+
+/// Returns the slope in KB per day of the least squares line through `points`, or 0 if the
+/// points span less than a day.
+fn slope(points: &[(NaiveDateTime, f64)]) -> f64 {
+    let start = points[0].0;
+    let xs = points
+        .iter()
+        .map(|(date, kb)| ((*date - start).num_seconds() as f64 / 86400., *kb))
+        .collect::<Vec<(f64, f64)>>();
+    let n = xs.len() as f64;
+    let mean_x = xs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = xs.iter().map(|(_, y)| y).sum::<f64>() / n;
+    if xs[xs.len() - 1].0 < 1. {
+        return 0.;
+    }
+    let var = xs.iter().map(|(x, _)| (x - mean_x).powi(2)).sum::<f64>();
+    xs.iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>()
+        / var
+}
+
+/// Returns `kb` in readable units, with a leading "-" if negative.
+fn readable(kb: f64) -> LinkResult<String> {
+    let size = byte_unit::Byte::from_unit(kb.abs(), byte_unit::ByteUnit::KB)?
+        .get_appropriate_unit(false)
+        .to_string();
+    match kb < 0. {
+        true => Ok(format!("-{}", size)),
+        false => Ok(size),
+    }
+}
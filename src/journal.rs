@@ -0,0 +1,323 @@
+//! Append-only record of changes made to the Document Center.
+//!
+//! Each call that uploads, updates or deletes a document writes a [`JournalEntry`] to a local
+//! journal file, one JSON object per line.  Entries written during a single run of the program
+//! share a run id, and [`Journal::undo`] uses the entries for a run id to reverse the changes that
+//! can be reversed: documents set to draft are published again, archived documents are
+//! unarchived, moved documents are returned to their folders, edited metadata is set back, and
+//! deleted documents are uploaded again from the backup taken before deletion.  The entries
+//! written while undoing a run record the run they reverse, so a run is not reversed twice.
+//!
+//! Attach a [`Journal`] to a [`DocInfo`] with [`DocInfo::journal`] to record the calls made
+//! with that `DocInfo`, or to a [`crate::store::DocumentStore`] such as
+//! [`crate::store::LocalStore::journal`] to record the calls made through the store.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::journal::{Journal, JournalEntry};
+//! # fn main() -> linkbuilder::prelude::LinkResult<()> {
+//! let path = std::env::temp_dir().join("linkbuilder_journal_example.jsonl");
+//! # let _ = std::fs::remove_file(&path);
+//! let journal = Journal::new(&path);
+//! let mut entry = JournalEntry::new(journal.run_id_ref(), 0, "upload");
+//! entry.result = "OK".to_owned();
+//! entry.success = true;
+//! journal.record(&entry)?;
+//! let entries = Journal::read(&path)?;
+//! assert_eq!(entries.len(), 1);
+//! assert_eq!(&entries[0].run_id, journal.run_id_ref());
+//! assert!(entries[0].success);
+//! # std::fs::remove_file(&path)?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::document::Document;
+use crate::prelude::*;
+use crate::store::DocumentStore;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, trace, warn};
+
+/// Returns the current time in seconds since the Unix epoch.
+pub fn timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// A single change made to the Document Center.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct JournalEntry {
+    /// Id shared by every entry written in the same run.
+    pub run_id: String,
+    /// Time of the change in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Id of the user who made the change.
+    pub user_id: i32,
+    /// Change made, one of "upload", "draft", "archive", "restore" or "delete".
+    pub action: String,
+    /// Id of the document changed.  Not known for uploads.
+    pub document_id: Option<i32>,
+    /// Name of the document changed.
+    pub name: Option<String>,
+    /// Id of the folder holding the document.
+    pub folder_id: Option<i32>,
+    /// Document metadata before the change.
+    pub before: Option<Document>,
+    /// Document metadata after the change.  Empty for deletes.
+    pub after: Option<Document>,
+    /// Response from CivicEngage.
+    pub result: String,
+    /// Whether CivicEngage accepted the change.  Failed changes are skipped by
+    /// [`Journal::undo`].  Entries written before the field was added read as accepted.
+    #[serde(default = "accepted")]
+    pub success: bool,
+    /// Directory holding a backup of the document, if one was taken.
+    pub backup: Option<PathBuf>,
+    /// Run id of the changes reversed by the entry, for entries written while undoing a run.
+    pub undoes: Option<String>,
+}
+
+impl JournalEntry {
+    /// Creates a new `JournalEntry` for `action` by the user with id `user_id`, stamped with the
+    /// current time.
+    pub fn new(run_id: &str, user_id: i32, action: &str) -> Self {
+        JournalEntry {
+            run_id: run_id.to_owned(),
+            timestamp: timestamp(),
+            user_id,
+            action: action.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the document fields of the entry from the metadata of `doc` before the change.
+    pub fn document(mut self, doc: &Document) -> Self {
+        self.document_id = Some(doc.id());
+        self.name = Some(doc.name());
+        self.folder_id = *doc.folder_id_ref();
+        self.before = Some(doc.clone());
+        self
+    }
+
+    /// Sets the result and success of the entry from the `response` to the call making the
+    /// change, logging a failed call.
+    pub fn finish(&mut self, response: LinkResult<String>) -> &mut Self {
+        match response {
+            Ok(result) => {
+                self.result = result;
+                self.success = true;
+            }
+            Err(e) => {
+                warn!("Could not {} {:?}: {}", self.action, self.name, e);
+                self.result = e.to_string();
+                self.success = false;
+            }
+        }
+        self
+    }
+}
+
+/// Writes [`JournalEntry`] records to a journal file.
+#[derive(Clone, Debug)]
+pub struct Journal {
+    path: PathBuf,
+    run_id: String,
+    backup: Option<PathBuf>,
+    undoes: Option<String>,
+}
+
+impl Journal {
+    /// Creates a new `Journal` writing to the file at `path`, with a new run id.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Journal {
+            path: path.as_ref().to_path_buf(),
+            run_id: format!("{}-{}", timestamp(), std::process::id()),
+            backup: None,
+            undoes: None,
+        }
+    }
+
+    /// Records `value` as the backup directory in the entries written from now on.
+    pub fn backup<P: AsRef<Path>>(&mut self, value: P) -> &mut Self {
+        self.backup = Some(value.as_ref().to_path_buf());
+        self
+    }
+
+    /// Records `value` as the run reversed by the entries written from now on.  Set before
+    /// passing the store to [`Journal::undo`], so that the run is not reversed twice.
+    pub fn undoes(&mut self, value: &str) -> &mut Self {
+        self.undoes = Some(value.to_owned());
+        self
+    }
+
+    /// The `path` field holds the path to the journal file.  This function returns a reference
+    /// to the field.
+    pub fn path_ref(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// The `run_id` field holds the id shared by entries written in this run.  This function
+    /// returns a reference to the field.
+    pub fn run_id_ref(&self) -> &String {
+        &self.run_id
+    }
+
+    /// Appends `entry` to the journal file, creating the file if it does not exist.
+    pub fn record(&self, entry: &JournalEntry) -> LinkResult<()> {
+        let mut entry = entry.clone();
+        if entry.backup.is_none() {
+            entry.backup = self.backup.clone();
+        }
+        if entry.undoes.is_none() {
+            entry.undoes = self.undoes.clone();
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        trace!("Journal entry recorded for run {}.", entry.run_id);
+        Ok(())
+    }
+
+    /// Reads every entry from the journal file at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> LinkResult<Vec<JournalEntry>> {
+        let file = std::fs::File::open(path)?;
+        let mut entries = Vec::new();
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Reverses the changes recorded in the journal file at `path` under `run_id`, newest first,
+    /// making the reversing calls against `store`.  Changes that failed are skipped, since there
+    /// is nothing to reverse.  A reversing call that fails is logged and the rest of the run is
+    /// still reversed.  Returns an entry for each change reversed, holding the response to the
+    /// reversing call, or the error for a reversing call that failed.
+    ///
+    /// Returns [`LinkError::AlreadyUndone`] if the journal shows a change of the run already
+    /// reversed.  Reversing calls are recorded as reversing the run when `store` is journaled
+    /// with a [`Journal`] marked by [`Journal::undoes`].
+    pub async fn undo<P: AsRef<Path>>(
+        path: P,
+        run_id: &str,
+        store: &dyn DocumentStore,
+    ) -> LinkResult<Vec<JournalEntry>> {
+        let entries = Self::read(path)?;
+        if entries
+            .iter()
+            .any(|e| e.success && e.undoes.as_deref() == Some(run_id))
+        {
+            return Err(LinkError::AlreadyUndone {
+                value: run_id.to_owned(),
+            });
+        }
+        let entries = entries
+            .into_iter()
+            .filter(|e| e.run_id == run_id)
+            .collect::<Vec<JournalEntry>>();
+        if entries.is_empty() {
+            return Err(LinkError::RunError {
+                value: run_id.to_owned(),
+            });
+        }
+        let entries = entries
+            .into_iter()
+            .filter(|e| {
+                if !e.success {
+                    info!("Skipping failed {} of {:?}.", e.action, e.name);
+                }
+                e.success
+            })
+            .collect::<Vec<JournalEntry>>();
+        // A document deleted in the run is uploaded again with its metadata from before the run,
+        // so earlier changes to the same document are not reversed separately.
+        let mut original = HashMap::new();
+        let mut deleted = HashSet::new();
+        for entry in &entries {
+            if let (Some(id), Some(before)) = (entry.document_id, &entry.before) {
+                original.entry(id).or_insert_with(|| before.clone());
+                if entry.action == "delete" {
+                    deleted.insert(id);
+                }
+            }
+        }
+        let mut undone = Vec::new();
+        for entry in entries.iter().rev() {
+            let before = match (&entry.before, entry.document_id) {
+                (Some(before), Some(id)) => original.get(&id).unwrap_or(before),
+                _ => {
+                    warn!("Cannot undo {} of {:?}.", entry.action, entry.name);
+                    continue;
+                }
+            };
+            let mut done = JournalEntry::new(run_id, store.user_id(), "restore").document(before);
+            done.undoes = Some(run_id.to_owned());
+            match entry.action.as_str() {
+                "draft" | "archive" | "move" | "metadata" if deleted.contains(&before.id()) => {
+                    trace!(
+                        "Skipping {} of deleted document {}.",
+                        entry.action,
+                        before.id()
+                    );
+                }
+                "draft" | "archive" | "move" | "metadata" => {
+                    info!("Restoring {}.", before.name());
+                    done.finish(store.update(before, "restore").await);
+                    undone.push(done);
+                }
+                "delete" => match (
+                    entry
+                        .backup
+                        .as_ref()
+                        .and_then(|b| backup_file(b, before.id())),
+                    before.folder_id_ref(),
+                ) {
+                    (Some(file), Some(folder_id)) => {
+                        info!("Uploading {} from {}.", before.name(), file.display());
+                        let publish = before.status_ref() == &Some(30);
+                        done.action = "upload".to_owned();
+                        done.finish(
+                            store
+                                .upload(&before.name(), &file, *folder_id, publish)
+                                .await,
+                        );
+                        undone.push(done);
+                    }
+                    (None, _) => warn!("No backup found for {}.", before.name()),
+                    (_, None) => warn!("No folder recorded for {}.", before.name()),
+                },
+                _ => warn!("Cannot undo {} of {}.", entry.action, before.name()),
+            }
+        }
+        Ok(undone)
+    }
+}
+
+/// Default for [`JournalEntry::success`] in entries written without the field.
+fn accepted() -> bool {
+    true
+}
+
+/// Returns the path of the file for document `id` in the backup directory `dir`.
+fn backup_file(dir: &Path, id: i32) -> Option<PathBuf> {
+    let prefix = format!("{}_", id);
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|f| f.ok())
+        .map(|f| f.path())
+        .find(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(&prefix))
+        })
+}
@@ -4,27 +4,59 @@
     html_logo_url = "https://www.grantspassoregon.gov/DocumentCenter/View/31368/GPLogo_450W-PNG"
 )]
 #![doc(html_playground_url = "https://play.rust-lang.org/")]
+pub mod alert;
 pub mod authorize;
+pub mod config;
+pub mod credential;
 pub mod document;
 pub mod error;
 /// Data types for exporting results to csv.
 pub mod export;
 /// Data types for reading file names from local folders.
 pub mod file;
+#[cfg(feature = "test-support")]
+pub mod fixture;
+pub mod health;
+pub mod history;
+pub mod journal;
+pub mod metadata;
+#[cfg(feature = "test-support")]
+pub mod mock;
+pub mod output;
+pub mod patch;
+pub mod purge;
+pub mod relocate;
 /// Reporting structure for storage on the CivicEngage Document Center.
 pub mod report;
+pub mod retention;
+pub mod scheme;
+pub mod session;
+pub mod store;
 /// Generic functions accessed by internal modules.
 pub mod utils;
 
 /// Select set of common library features.
 pub mod prelude {
     pub use crate::authorize::{AuthorizeHeaders, AuthorizeInfo, AuthorizedUser, User};
+    pub use crate::config::{Config, Settings};
+    pub use crate::credential::{CredentialProvider, Credentials};
     pub use crate::document::{
-        DocInfo, DocQuery, DocumentHeaders, DocumentLinks, Documents, Folder, Folders, LinkUpdater,
+        DocInfo, DocQuery, DocumentHeaders, DocumentLink, DocumentLinks, Documents, Folder,
+        Folders, LinkUpdater,
     };
     pub use crate::error::{LinkError, LinkResult};
-    pub use crate::export::WebLinks;
+    pub use crate::export::{KeyedLinks, LinkPolicy, LinkStrategy, WebLinks};
     pub use crate::file::FileNames;
-    pub use crate::report::{FolderSize, FolderSizes, ReportItems};
-    pub use crate::utils::load_user;
+    pub use crate::health::{BrokenLinks, LinkChecker, LinkChecks};
+    pub use crate::journal::Journal;
+    pub use crate::metadata::MetadataPlan;
+    pub use crate::output::OutputFormat;
+    pub use crate::patch::DocumentPatch;
+    pub use crate::purge::{PurgeFilter, PurgePlan};
+    pub use crate::relocate::MovePlan;
+    pub use crate::report::{FolderSize, FolderSizes, ReportItems, StorageReport};
+    pub use crate::retention::{RetentionPlan, RetentionRules};
+    pub use crate::scheme::{NameIssues, NameScheme};
+    pub use crate::store::{CivicEngageStore, DocumentStore, LocalStore};
+    pub use crate::utils::{authorize_user, load_user};
 }
@@ -1,242 +1,1253 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use linkbuilder::alert::{self, Thresholds};
+use linkbuilder::credential::{self, CredentialStore};
+#[cfg(feature = "test-support")]
+use linkbuilder::fixture::{FixtureServer, ReplayMode};
+use linkbuilder::history::{self, StorageHistory};
+use linkbuilder::metadata;
+use linkbuilder::output::{
+    self, ActionResult, DocumentSummary, FolderNode, FolderStats, SessionInfo, SyncResult,
+};
 use linkbuilder::prelude::*;
-use tracing::{info, trace};
+use linkbuilder::purge::DocStatus;
+use linkbuilder::relocate;
+use linkbuilder::report;
+use linkbuilder::retention;
+use linkbuilder::scheme;
+use linkbuilder::session::SessionCache;
+use linkbuilder::store::{delete_documents, update_documents};
+use std::sync::Arc;
+use tracing::{info, trace, warn};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    #[arg(short = 'c', long, help = CMD_HELP)]
-    command: String,
-    #[arg(short = 'p', long, help = "Parameter for command.")]
-    param: Option<String>,
-    #[arg(short = 's', long, help = "Source path.")]
-    source: Option<String>,
-    #[arg(short = 'o', long, help = "Output path.")]
-    output: Option<String>,
-}
-
-const CMD_HELP: &str = "
-Command to execute, including:
-* get_links -p <PATH> -> Read links from website and output links files to path.
-* sync_folder -s <LOCAL_FILE_PATH> -p <WEB_FOLDER_NAME> -> Copies local files to web folder if not already present.
-* report -o <PATH> -> Outputs a report of storage use for GIS on CivicEngage.
-* folder_count -p <WEB_FOLDER_NAME> -> Prints stats about a folder contents.
-* delete_folder_content -p <WEB_FOLDER_NAME> -> Deletes all contents from web folder.
-* inspect_folder -p <WEB_FOLDER_NAME> -> Prints stats about a folder.
-";
+    #[command(subcommand)]
+    command: Command,
+    /// Format of the results written to stdout.  Logs are written to stderr.
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+    /// Path to the journal recording changes to the Document Center.
+    #[arg(long, global = true, default_value = "journal.jsonl")]
+    journal: std::path::PathBuf,
+    /// Path to the config file.  Defaults to linkbuilder.toml in the working directory or the
+    /// user config directory.
+    #[arg(long, global = true)]
+    config: Option<std::path::PathBuf>,
+    /// Name of the profile in the config file.
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// User name, overriding the profile and environment.
+    #[arg(long, global = true)]
+    username: Option<String>,
+    /// Partition number, overriding the profile and environment.
+    #[arg(long, global = true)]
+    partition: Option<String>,
+    /// Directory of a local document store to use in place of CivicEngage.  Documents can be
+    /// staged and commands tried offline against the local store.
+    #[arg(long, global = true)]
+    local: Option<std::path::PathBuf>,
+    /// Directory to record responses from CivicEngage to, as fixture files with secrets
+    /// scrubbed.  The run logs in without the session cache, so the login is recorded too.
+    #[cfg(feature = "test-support")]
+    #[arg(long, global = true, conflicts_with_all = ["replay", "local"])]
+    record: Option<std::path::PathBuf>,
+    /// Directory of fixture files to answer requests from in place of CivicEngage.  See
+    /// `--record`.
+    #[cfg(feature = "test-support")]
+    #[arg(long, global = true, conflicts_with = "local")]
+    replay: Option<std::path::PathBuf>,
+    /// Order in which fixtures are replayed.
+    #[cfg(feature = "test-support")]
+    #[arg(long, global = true, value_enum, default_value_t = ReplayMode::Match)]
+    replay_mode: ReplayMode,
+}
+
+impl Cli {
+    /// Reads the settings for the selected profile, with command line flags layered on top.
+    fn settings(&self) -> LinkResult<Settings> {
+        let config = Config::load(self.config.as_deref())?;
+        let mut settings = config.profile(self.profile.as_deref())?;
+        let flags = Settings {
+            username: self.username.clone(),
+            partition: self.partition.clone(),
+            ..Default::default()
+        };
+        settings.merge(&flags);
+        Ok(settings)
+    }
+
+    /// Returns true if `--replay` is set.
+    #[cfg(feature = "test-support")]
+    fn replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// Returns false, since fixtures are replayed only with the `test-support` feature.
+    #[cfg(not(feature = "test-support"))]
+    fn replaying(&self) -> bool {
+        false
+    }
+
+    /// Starts a [`FixtureServer`] if `--record` or `--replay` is set, pointing `settings` at it.
+    /// Endpoint overrides are cleared so requests go through the server.  When replaying,
+    /// missing login settings are filled with placeholders, since the recorded login is replayed
+    /// whatever is sent.
+    #[cfg(feature = "test-support")]
+    fn fixtures(&self, settings: &mut Settings) -> LinkResult<Option<FixtureServer>> {
+        let server = match (&self.record, &self.replay) {
+            (Some(dir), _) => {
+                info!("Recording fixtures to {}.", dir.display());
+                FixtureServer::record(&settings.site()?, dir)?
+            }
+            (None, Some(dir)) => {
+                info!("Replaying fixtures from {}.", dir.display());
+                settings.api_key.get_or_insert_with(|| "replay".to_owned());
+                settings.partition.get_or_insert_with(|| "0".to_owned());
+                settings.username.get_or_insert_with(|| "replay".to_owned());
+                if settings.password.is_none() {
+                    settings.password = Some(secrecy::SecretString::new("replay".to_owned()));
+                }
+                FixtureServer::replay(dir, self.replay_mode)?
+            }
+            (None, None) => return Ok(None),
+        };
+        settings.site = Some(server.site());
+        settings.authenticate = None;
+        settings.folder = None;
+        settings.document = None;
+        Ok(Some(server))
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Read links from the website and output link files for the GIS layers.
+    Links {
+        /// Directory for the link files.
+        #[arg(short = 'o', long)]
+        output: String,
+        /// Export strategy for keys linked to several documents.
+        #[arg(long, value_enum, default_value_t = LinkStrategy::Latest)]
+        strategy: LinkStrategy,
+        /// Domain for exported links.  Defaults to the host of the DOCUMENT endpoint.
+        #[arg(long)]
+        host: Option<String>,
+    },
+    /// Copy local files to a web folder if not already present.
+    ///
+    /// For As-Builts, each local subdirectory is copied to the web subfolder of the same name.
+    Sync {
+        /// Local directory holding the files to upload.
+        #[arg(short = 's', long)]
+        source: std::path::PathBuf,
+        /// Name of the web folder on the Document Center.
+        #[arg(short = 'f', long)]
+        folder: String,
+    },
+    /// Output a report of storage use on CivicEngage.
+    ///
+    /// Lists the document count, storage and share of the total for each folder and the folders
+    /// below it, with breakdowns by file type and status, and the largest documents.  Each run
+    /// appends the folder sizes to the storage history.  If a folder reaches its storage
+    /// threshold in the profile, the alert hooks run and the command exits with code 3.
+    Report(ReportArgs),
+    /// Check links in a links file or GIS attribute table and report broken links.
+    Check(CheckArgs),
+    /// Inspect and manage web folders.
+    Folder {
+        #[command(subcommand)]
+        command: FolderCommand,
+    },
+    /// List and manage documents in a web folder.
+    Docs {
+        #[command(subcommand)]
+        command: DocsCommand,
+    },
+    /// Set document metadata from a csv manifest.
+    ///
+    /// The manifest has a `document` column holding document ids or names, and any of the columns
+    /// description, alt_text, start_date, end_date and is_visible.  Empty cells are left
+    /// unchanged.  The changes are listed against the current values before they are applied.
+    ApplyMetadata {
+        /// Csv manifest of the metadata to set.
+        #[arg(short = 's', long)]
+        source: std::path::PathBuf,
+        /// Name of the web folder holding the documents.  Looks up documents in every folder if
+        /// not set.
+        #[arg(short = 'f', long)]
+        folder: Option<String>,
+        /// List the changes without applying them.
+        #[arg(long)]
+        dry_run: bool,
+        /// Skip the confirmation prompt.
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
+    /// Archive or set to draft the documents due under retention rules.
+    ///
+    /// Rules for each folder select documents uploaded more than a number of years ago or with
+    /// an end date in the past.  The documents due are listed before the changes are applied.
+    /// Archived and draft documents are left out of the link files on the next `links` run.
+    Retention {
+        /// Retention rules file.
+        #[arg(short = 'r', long, default_value = retention::RETENTION_FILE)]
+        rules: std::path::PathBuf,
+        /// Date to evaluate the rules on (YYYY-MM-DD).  Defaults to today.
+        #[arg(long)]
+        as_of: Option<String>,
+        /// List the documents due without changing them.
+        #[arg(long)]
+        dry_run: bool,
+        /// Skip the confirmation prompt.
+        #[arg(short = 'y', long)]
+        yes: bool,
+    },
+    /// Reverse the changes made in a previous run.
+    ///
+    /// Drafted documents are published again, archived documents are unarchived, moved documents
+    /// are returned to their folders, and deleted documents are uploaded again from the purge
+    /// backup.  Run ids are recorded in the journal.
+    Undo {
+        /// Run id from the journal.
+        run_id: String,
+    },
+    /// Store the login credentials for the profile.
+    ///
+    /// Prompts for the password, checks it against CivicEngage and saves it in the credential
+    /// store.  Set `credentials` in the profile to the store name to use the saved credentials.
+    Login {
+        /// Credential store, defaulting to the `credentials` setting or the keyring.
+        #[arg(long, value_enum)]
+        store: Option<CredentialStore>,
+    },
+    /// Show or clear the cached login session.
+    Session {
+        #[command(subcommand)]
+        command: SessionCommand,
+    },
+    /// Remove the stored login credentials for the profile.
+    Logout {
+        /// Credential store, defaulting to the `credentials` setting or the keyring.
+        #[arg(long, value_enum)]
+        store: Option<CredentialStore>,
+    },
+}
+
+#[derive(Args)]
+#[command(args_conflicts_with_subcommands = true)]
+struct ReportArgs {
+    #[command(subcommand)]
+    command: Option<ReportCommand>,
+    /// Path for a copy of the report in csv.
+    #[arg(short = 'o', long)]
+    output: Option<std::path::PathBuf>,
+    /// Name of the web folder to report on.  Reports on every folder if not set.
+    #[arg(short = 'f', long)]
+    folder: Option<String>,
+    /// Count of the largest documents to list.
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+    /// Path to the storage history.
+    #[arg(long, default_value = history::HISTORY_FILE)]
+    history: std::path::PathBuf,
+}
+
+#[derive(Subcommand)]
+enum ReportCommand {
+    /// Show the growth of storage in each folder over the report runs in the storage history.
+    ///
+    /// With a storage quota, set by `--quota` or `storage_quota` in the profile, projects the
+    /// date the total storage reaches the quota.
+    Trend {
+        /// Path to the storage history.
+        #[arg(long, default_value = history::HISTORY_FILE)]
+        history: std::path::PathBuf,
+        /// Storage quota, such as "100 GB".
+        #[arg(long)]
+        quota: Option<String>,
+    },
+}
+
+#[derive(Args)]
+struct CheckArgs {
+    /// Links file or GIS attribute table in csv format.
+    #[arg(short = 's', long)]
+    source: std::path::PathBuf,
+    /// Path for a copy of the broken link report in csv.
+    #[arg(short = 'o', long)]
+    output: Option<std::path::PathBuf>,
+    /// Key field in a GIS attribute table.
+    #[arg(short = 'k', long, requires = "link")]
+    key: Option<String>,
+    /// Link field in a GIS attribute table.
+    #[arg(short = 'l', long, requires = "key")]
+    link: Option<String>,
+    /// Send HEAD requests to links that pass the document checks.
+    #[arg(long)]
+    head: bool,
+}
+
+#[derive(Subcommand)]
+enum FolderCommand {
+    /// Print stats about the contents of a folder.
+    Count {
+        /// Name of the web folder.
+        folder: String,
+    },
+    /// Print details about a folder.
+    Inspect {
+        /// Name of the web folder.
+        folder: String,
+    },
+    /// Create a folder in the local store given by `--local`.
+    Create {
+        /// Name of the new folder.
+        folder: String,
+        /// Name of the parent folder.  Creates the folder at the root if not set.
+        #[arg(short = 'p', long)]
+        parent: Option<String>,
+    },
+    /// Print the folder tree below a folder, or the whole Document Center.
+    Tree {
+        /// Name of the web folder at the root of the tree.
+        folder: Option<String>,
+    },
+    /// Delete the contents of a folder.
+    ///
+    /// Documents matching the filters are downloaded with their metadata to the backup directory,
+    /// then set to draft and deleted.  Asks for confirmation unless `--yes` is passed.
+    Purge(PurgeArgs),
+}
+
+#[derive(Subcommand)]
+enum SessionCommand {
+    /// Show the cached session for the profile.
+    Show,
+    /// Remove the cached session for the profile.
+    Clear,
+}
+
+#[derive(Args)]
+struct PurgeArgs {
+    /// Name of the web folder.
+    folder: String,
+    /// Skip the confirmation prompt.
+    #[arg(short = 'y', long)]
+    yes: bool,
+    /// Only delete documents with names matching this regular expression.
+    #[arg(long)]
+    name: Option<String>,
+    /// Only delete documents dated before this date (YYYY-MM-DD).
+    #[arg(long)]
+    before: Option<String>,
+    /// Only delete documents dated on or after this date (YYYY-MM-DD).
+    #[arg(long)]
+    after: Option<String>,
+    /// Only delete documents with this status.
+    #[arg(long, value_enum)]
+    status: Option<DocStatus>,
+    /// Refuse to delete more than this many documents.
+    #[arg(long, default_value_t = 100)]
+    max: usize,
+    /// Directory for the backup of deleted documents.
+    #[arg(short = 'b', long, default_value = "backup")]
+    backup: std::path::PathBuf,
+}
+
+#[derive(Args)]
+struct MoveArgs {
+    /// Name of the web folder holding the documents.  With `--mapping`, limits the documents
+    /// looked up to this folder.
+    #[arg(short = 'f', long, required_unless_present = "mapping")]
+    folder: Option<String>,
+    /// Name of the web folder to move the documents to.
+    #[arg(
+        short = 't',
+        long,
+        required_unless_present = "mapping",
+        conflicts_with = "mapping"
+    )]
+    to: Option<String>,
+    /// Csv file with a `document` column holding document ids or names and a `folder` column
+    /// holding the folder to move each to.
+    #[arg(short = 'm', long)]
+    mapping: Option<std::path::PathBuf>,
+    /// Document ids to move.  Moves every document matching the filter if not set.
+    #[arg(long = "id")]
+    ids: Vec<i32>,
+    /// Only move documents with names matching this regular expression.
+    #[arg(long)]
+    name: Option<String>,
+    /// Only move documents dated before this date (YYYY-MM-DD).
+    #[arg(long)]
+    before: Option<String>,
+    /// Only move documents dated on or after this date (YYYY-MM-DD).
+    #[arg(long)]
+    after: Option<String>,
+    /// Only move documents with this status.
+    #[arg(long, value_enum)]
+    status: Option<DocStatus>,
+    /// Skip the confirmation prompt.
+    #[arg(short = 'y', long)]
+    yes: bool,
+    /// Directory for the link diff, usually the directory of the link files.
+    #[arg(short = 'o', long, default_value = ".")]
+    output: std::path::PathBuf,
+}
+
+#[derive(Subcommand)]
+enum DocsCommand {
+    /// List the documents in a folder.
+    List {
+        /// Name of the web folder.
+        #[arg(short = 'f', long)]
+        folder: String,
+    },
+    /// Set the status of documents in a folder.
+    Update {
+        /// Name of the web folder.
+        #[arg(short = 'f', long)]
+        folder: String,
+        /// Status to apply.
+        #[arg(short = 'a', long, value_enum)]
+        action: UpdateAction,
+        /// Document ids to update.  Updates every document in the folder if not set.
+        #[arg(long = "id")]
+        ids: Vec<i32>,
+        /// Skip the confirmation prompt.
+        #[arg(short = 'y', long)]
+        yes: bool,
+        /// Refuse to update more than this many documents.
+        #[arg(long, default_value_t = 100)]
+        max: usize,
+    },
+    /// Move documents to another folder, keeping their ids and links.
+    ///
+    /// Select the documents in `--folder` to move to `--to` with the filter options, or list the
+    /// documents and folders in a csv with `--mapping`.  Moves are appended to moved_links.csv in
+    /// the output directory.
+    Move(MoveArgs),
+    /// Export the full metadata of the documents in a folder and its subfolders, or on the
+    /// whole Document Center.
+    Export {
+        /// Name of the web folder.  Exports every document if not set.
+        #[arg(short = 'f', long)]
+        folder: Option<String>,
+        /// Path for a copy of the export in csv.
+        #[arg(short = 'o', long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Delete documents in a folder.  Documents must have "Draft" status.
+    ///
+    /// The documents are downloaded with their metadata to the backup directory before they are
+    /// deleted.  Asks for confirmation unless `--yes` is passed.
+    Delete {
+        /// Name of the web folder.
+        #[arg(short = 'f', long)]
+        folder: String,
+        /// Document ids to delete.  Deletes every document in the folder if not set.
+        #[arg(long = "id")]
+        ids: Vec<i32>,
+        /// Skip the confirmation prompt.
+        #[arg(short = 'y', long)]
+        yes: bool,
+        /// Refuse to delete more than this many documents.
+        #[arg(long, default_value_t = 100)]
+        max: usize,
+        /// Directory for the backup of deleted documents.
+        #[arg(short = 'b', long, default_value = "backup")]
+        backup: std::path::PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum UpdateAction {
+    /// Set documents to "Draft" status.
+    Draft,
+    /// Archive documents.
+    Archive,
+}
+
+impl UpdateAction {
+    fn command(&self) -> &'static str {
+        match self {
+            UpdateAction::Draft => "draft",
+            UpdateAction::Archive => "archive",
+        }
+    }
+}
+
+/// Document store and folders shared by commands.
+struct Context {
+    store: Arc<dyn DocumentStore>,
+    host: Option<String>,
+    journal: Journal,
+    folders: Folders,
+    /// Server recording or replaying fixtures, held so that it runs until the command ends.
+    #[cfg(feature = "test-support")]
+    _fixtures: Option<FixtureServer>,
+}
+
+impl Context {
+    async fn load(cli: &Cli) -> LinkResult<Self> {
+        let journal = Journal::new(&cli.journal);
+        trace!("Run id: {}", journal.run_id_ref());
+        if let Some(dir) = &cli.local {
+            info!("Using local store at {}.", dir.display());
+            let mut store = LocalStore::new(dir);
+            store.journal(&journal);
+            let folders = store.folders().await?;
+            return Ok(Context {
+                store: Arc::new(store),
+                host: None,
+                journal,
+                folders,
+                #[cfg(feature = "test-support")]
+                _fixtures: None,
+            });
+        }
+
+        // Only fixtures change the settings, so they need no changes without the feature.
+        #[cfg_attr(not(feature = "test-support"), allow(unused_mut))]
+        let mut settings = cli.settings()?;
+        info!("Using profile {}.", settings.profile);
+        let host = match (cli.replaying(), settings.document_url()) {
+            (true, Err(_)) => None,
+            (_, url) => Some(LinkPolicy::from_url(&url?)?.host_ref().clone()),
+        };
+        #[cfg(feature = "test-support")]
+        if let Some(fixtures) = cli.fixtures(&mut settings)? {
+            // Fixtures hold a single login, so the session cache is bypassed.
+            let mut store =
+                CivicEngageStore::from_settings(&settings, &authorize_user(&settings).await?)?;
+            store.journal(&journal);
+            let folders = store.folders().await?;
+            return Ok(Context {
+                store: Arc::new(store),
+                host,
+                journal,
+                folders,
+                _fixtures: Some(fixtures),
+            });
+        }
+
+        // The folder query doubles as a check that a cached session is still accepted.
+        let cache = SessionCache::from_settings(&settings)?;
+        let cached = cache.load(&settings)?;
+        let auth_user = match &cached {
+            Some(session) => {
+                info!(
+                    "Reusing cached session for profile {}.",
+                    session.profile_ref()
+                );
+                session.user_ref().clone()
+            }
+            None => cache.refresh(&settings).await?,
+        };
+        let mut store = CivicEngageStore::from_settings(&settings, &auth_user)?;
+        store.journal(&journal);
+        trace!("Set up query data for folders.");
+        let folders = match store.folders().await {
+            Err(LinkError::AuthError) if cached.is_some() => {
+                warn!("Cached session rejected, logging in again.");
+                store.user(&cache.refresh(&settings).await?);
+                store.folders().await?
+            }
+            result => result?,
+        };
+
+        Ok(Context {
+            store: Arc::new(store),
+            host,
+            journal,
+            folders,
+            #[cfg(feature = "test-support")]
+            _fixtures: None,
+        })
+    }
+
+    fn folders(&self) -> LinkResult<Folders> {
+        Ok(self.folders.clone())
+    }
+
+    fn folder_id(&self, folders: &Folders, folder: &str) -> LinkResult<i32> {
+        folders.get_id(folder).ok_or(LinkError::FolderError {
+            value: folder.to_owned(),
+        })
+    }
+
+    /// Returns the documents in the folder with id `id`, or every document if `id` is `None`.
+    async fn documents(&self, id: Option<i32>) -> LinkResult<Documents> {
+        trace!("Querying documents.");
+        self.store.documents(id).await
+    }
+}
+
+/// Document categories with link files, and the name of the link file for each.
+const LINK_TARGETS: [(&str, &str); 9] = [
+    ("Address Notifications", "address_notification_links"),
+    ("Advance Finance Districts", "advance_finance_links"),
+    ("As-Builts", "as_built_links"),
+    (
+        "Deferred Development Agreements",
+        "deferred_development_links",
+    ),
+    ("Fee in Lieu", "fila_links"),
+    ("Plats", "plat_links"),
+    ("Service and Annexation", "service_annexation_links"),
+    ("Subdivisions", "subdivision_links"),
+    ("Unrecorded Parcels", "unrecorded_parcels_links"),
+];
 
 #[tokio::main]
-async fn main() -> LinkResult<()> {
+async fn main() -> std::process::ExitCode {
+    match run().await {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e @ LinkError::ThresholdError { .. }) => {
+            eprintln!("Warning: {}", e);
+            std::process::ExitCode::from(alert::ALERT_EXIT)
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> LinkResult<()> {
     dotenv::dotenv().ok();
     if let Ok(()) = tracing_subscriber::fmt()
         .with_max_level(tracing::Level::INFO)
+        .with_writer(std::io::stderr)
         .try_init()
     {};
     trace!("Subscriber initialized.");
-    let folder_url = std::env::var("FOLDER")?;
-    let doc_url = std::env::var("DOCUMENT")?;
-
-    let auth_user = load_user().await?;
-
-    trace!("Preparing document center headers.");
-    let doc_header = DocumentHeaders::default();
-    trace!("Setting query parameters for document center request.");
-    let mut args = DocQuery::new();
-    trace!("Returns all matches on server.");
-    args.inlinecount("allpages");
-
     let cli = Cli::parse();
-    match cli.command.as_str() {
-        "get_links" => {
-            let doc_info = DocInfo::new(&doc_header, &args, &folder_url);
-            trace!("Set up query data for folders.");
-            let folders = Folders::query(&doc_info, &auth_user).await?;
-            trace!("Search for docs in specified folder.");
-
-            let link_updater = LinkUpdater::new()
+    let format = cli.format;
+    match &cli.command {
+        Command::Login { store } => return login(&cli, *store).await,
+        Command::Logout { store } => {
+            let settings = cli.settings()?;
+            let store = credential_store(*store, &settings);
+            credential::store_provider(store, &settings)?.clear()?;
+            SessionCache::from_settings(&settings)?.clear()?;
+            info!("Credentials removed for profile {}.", settings.profile);
+            return Ok(());
+        }
+        Command::Session { command } => {
+            let settings = cli.settings()?;
+            let cache = SessionCache::from_settings(&settings)?;
+            match command {
+                SessionCommand::Show => {
+                    let info = cache
+                        .read()?
+                        .map(|s| SessionInfo::new(&s, &cache, &settings));
+                    match &info {
+                        Some(info) => output::write(format, info, std::slice::from_ref(info))?,
+                        None => info!("No cached session for profile {}.", settings.profile),
+                    }
+                }
+                SessionCommand::Clear => {
+                    if !cache.clear()? {
+                        info!("No cached session for profile {}.", settings.profile);
+                    }
+                }
+            }
+            return Ok(());
+        }
+        Command::Report(ReportArgs {
+            command: Some(ReportCommand::Trend { history, quota }),
+            ..
+        }) => {
+            let quota = match quota.clone().or(cli.settings()?.storage_quota) {
+                Some(quota) => Some(history::quota_kb(&quota)?),
+                None => None,
+            };
+            let trend = StorageHistory::read(history)?.trend(quota)?;
+            if trend.is_empty() {
+                info!("No snapshots in {}, run `report` first.", history.display());
+            }
+            output::write(format, &trend, &trend)?;
+            return Ok(());
+        }
+        Command::Folder {
+            command: FolderCommand::Create { folder, parent },
+        } => {
+            let dir = cli.local.as_ref().ok_or(LinkError::LocalError {
+                value: "folder create".to_owned(),
+            })?;
+            let store = LocalStore::new(dir);
+            let parent = match parent {
+                Some(name) => Some(store.folders().await?.get_id(name).ok_or(
+                    LinkError::FolderError {
+                        value: name.clone(),
+                    },
+                )?),
+                None => None,
+            };
+            let id = store.create_folder(folder, parent)?;
+            info!("Folder id: {}", id);
+            return Ok(());
+        }
+        _ => {}
+    }
+    let ctx = Context::load(&cli).await?;
+
+    match cli.command {
+        Command::Links {
+            output,
+            strategy,
+            host,
+        } => {
+            let folders = ctx.folders()?;
+            let mut builder = LinkUpdater::new();
+            builder
                 .folders(&folders)
-                .headers(&doc_header)
-                .args(&args)
-                .url(&doc_url)
-                .user(&auth_user)
-                .output(&cli.output)?
-                .build()?;
-            link_updater
-                .get_links("Advance Finance Districts", "advance_finance_links")
-                .await?;
-            link_updater
-                .get_links(
-                    "Deferred Development Agreements",
-                    "deferred_development_links",
-                )
-                .await?;
-            link_updater.get_links("Fee in Lieu", "fila_links").await?;
-            link_updater.get_links("Plats", "plat_links").await?;
-            link_updater
-                .get_links("Service and Annexation", "service_annexation_links")
-                .await?;
-            link_updater
-                .get_links("Unrecorded Parcels", "unrecorded_parcels_links")
-                .await?;
+                .store(ctx.store.clone())
+                .output(&Some(output))?
+                .strategy(strategy);
+            if let Some(host) = host.as_ref().or(ctx.host.as_ref()) {
+                builder.host(host);
+            }
+            let link_updater = builder.build()?;
+            let mut exports = Vec::new();
+            for (folder, file) in LINK_TARGETS {
+                if let Some(export) = link_updater.get_links(folder, file).await? {
+                    exports.push(export);
+                }
+            }
             info!("Links successfully updated.");
+            output::write(format, &exports, &exports)?;
         }
-        "sync_folder" => {
-            let doc_info = DocInfo::new(&doc_header, &args, &folder_url);
-            trace!("Set up query data for folders.");
-            let folders = Folders::query(&doc_info, &auth_user).await?;
-
-            trace!("Reading files in source directory.");
-            if let Some(path) = cli.source {
-                let names = FileNames::from_path(path)?;
-                trace!("Names read: {:?}", names.names().len());
-
-                trace!("Search for docs in specified folder.");
-                if let Some(folder) = &cli.param {
-                    if let Some(id) = folders.get_id(folder) {
-                        trace!("Folder id: {:?}", id);
-                        trace!("Specify folder for search.");
-                        args.filter(&format!("FolderId eq {}", id));
-                        let doc_info = DocInfo::new(&doc_header, &args, &doc_url);
-                        let docs = Documents::query(&doc_info, &auth_user).await?;
-
-                        if let Some(count) = docs.total_count() {
-                            info!("Total count of documents in folder: {}", count);
+        Command::Sync { source, folder } => {
+            let folders = ctx.folders()?;
+            let id = ctx.folder_id(&folders, &folder)?;
+            trace!("Folder id: {:?}", id);
+            let mut results = vec![sync_folder(&ctx, &source, id).await?];
+            if scheme::for_folder(&folder).nested() {
+                let children = folders.children(id);
+                for (name, dir) in FileNames::directories(&source)? {
+                    match children.iter().find(|c| c.name_ref() == &name) {
+                        Some(child) => {
+                            if let Some(child_id) = child.id_ref() {
+                                info!("Syncing subfolder {}.", name);
+                                results.push(sync_folder(&ctx, &dir, *child_id).await?);
+                            }
                         }
-                        info!("Total size of documents in folder: {}", docs.total_size());
-                        let links = DocumentLinks::from(&docs);
-                        info!("Links read: {:?}", links.ref_links().len());
-                        info!("Names found: {:?}", links.ref_links().keys());
-                        trace!("Comparing names of docs in web folder to names in local folder.");
-                        let diff = names.not_in(&links);
-                        info!("Local names not in web folder: {:?}", diff.names().len());
-                        let res = diff.upload(&doc_info, &auth_user, id).await?;
-                        info!("Files added to web folder: {:?}", res.len());
+                        None => warn!("Subfolder {} not found in {}.", name, folder),
                     }
                 }
-            } else {
-                info!("Source path not specified.")
             }
+            output::write(format, &results, &results)?;
         }
-        "report" => {
+        Command::Report(ref report) => {
             info!("Preparing report.");
-            let mut records = Vec::new();
-            let doc_info = DocInfo::new(&doc_header, &args, &doc_url);
-            let total = Documents::query(&doc_info, &auth_user).await?;
-            let folder_list = vec![
-                "GIS",
-                "Address Notifications",
-                "Advance Finance Districts",
-                "Deferred Development Agreements",
-                "Fee in Lieu",
-                "Images",
-                "Plats",
-                "Service and Annexation",
-                "Unrecorded Parcels",
-            ];
-            let doc_info = DocInfo::new(&doc_header, &args, &folder_url);
-            trace!("Set up query data for folders.");
-            let folders = Folders::query(&doc_info, &auth_user).await?;
-            for folder in folder_list {
-                if let Some(id) = folders.get_id(folder) {
-                    args.filter(&format!("FolderId eq {}", id));
-                    let doc_info = DocInfo::new(&doc_header, &args, &doc_url);
-                    let docs = Documents::query(&doc_info, &auth_user).await?;
-                    records.push(FolderSize::new(folder, docs.total_size()));
-                } else {
-                    info!("Could not find folder: {}.", folder);
-                }
-            }
-            let subtotal = FolderSizes::from(records.clone()).size();
-            records.push(FolderSize::new("Subtotal", subtotal));
-            records.push(FolderSize::new("Total", total.total_size()));
-            let sizes = FolderSizes::from(records);
-            if let Ok(mut report) = ReportItems::try_from(sizes) {
-                if let Some(path) = cli.output {
-                    report.to_csv(path.clone())?;
-                    info!("Report output to path: {}", path)
-                }
-            }
-        }
-        "folder_count" => {
-            let doc_info = DocInfo::new(&doc_header, &args, &folder_url);
-            trace!("Set up query data for folders.");
-            let folders = Folders::query(&doc_info, &auth_user).await?;
-
-            trace!("Search for docs in specified folder.");
-            if let Some(folder) = &cli.param {
-                if let Some(id) = folders.get_id(folder) {
+            let folders = ctx.folders()?;
+            let root = match &report.folder {
+                Some(folder) => Some(ctx.folder_id(&folders, folder)?),
+                None => None,
+            };
+            let docs = ctx.documents(None).await?;
+            let storage = StorageReport::new(&docs, &folders, root, report.top)?;
+            let mut sizes = FolderSizes::from(&storage);
+            if root.is_none() {
+                sizes.push(FolderSize::new(report::TOTAL, storage.total.kb));
+            }
+            StorageHistory::append(&report.history, None, &sizes)?;
+            let settings = cli.settings()?;
+            let alerts = Thresholds::from_settings(&settings)?.check(&sizes)?;
+            let mut items = ReportItems::from(&storage);
+            if let Some(path) = &report.output {
+                items.to_csv(path)?;
+                info!("Report output to path: {}", path.display())
+            }
+            output::write(format, &storage, items.records_ref())?;
+            // A report on one folder does not check every threshold, so it only adds alerts.
+            if root.is_none() || !alerts.is_empty() {
+                alert::notify(&alerts, &settings)?;
+            }
+            if !alerts.is_empty() {
+                eprint!(
+                    "{}",
+                    output::to_string(OutputFormat::Table, &alerts, &alerts)?
+                );
+                return Err(LinkError::ThresholdError {
+                    count: alerts.len(),
+                });
+            }
+        }
+        Command::Check(check) => {
+            trace!("Reading links from {}.", check.source.display());
+            let links = match (&check.key, &check.link) {
+                (Some(key), Some(link)) => LinkChecks::from_attributes(&check.source, key, link)?,
+                _ => LinkChecks::from(&WebLinks::from_csv(&check.source)?),
+            };
+            info!("Links read: {}", links.records_ref().len());
+            trace!("Querying all documents on the Document Center.");
+            let docs = ctx.documents(None).await?;
+            let mut report = LinkChecker::new(&docs)
+                .head(check.head)
+                .check(&links)
+                .await?;
+            if let Some(path) = &check.output {
+                report.to_csv(path)?;
+                info!("Broken link report output to path: {}", path.display());
+            }
+            output::write(format, report.records_ref(), report.records_ref())?;
+        }
+        Command::Folder { command } => {
+            let folders = ctx.folders()?;
+            match command {
+                FolderCommand::Count { folder } => {
+                    let id = ctx.folder_id(&folders, &folder)?;
                     info!("Folder id: {:?}", id);
-                    trace!("Specify folder for search.");
-                    args.filter(&format!("FolderId eq {}", id));
                     trace!("Querying documents in folder.");
-                    let doc_info = DocInfo::new(&doc_header, &args, &doc_url);
-                    let docs = Documents::query(&doc_info, &auth_user).await?;
-                    if let Some(count) = docs.total_count() {
-                        info!("Total count of documents in folder: {}", count);
-                    }
-                    info!("Total size of documents in folder: {}", docs.total_size());
-                    if let Some(items) = docs.source_ref() {
-                        for item in items {
-                            info!("Name: {}", item.name());
-                            if let Some(size) = item.file_size() {
-                                info!("Size: {}", size);
-                            }
-                            if let Some(path) = item.url_ref() {
-                                info!("Url: {}", path);
-                            }
+                    let docs = ctx.documents(Some(id)).await?;
+                    let stats = FolderStats {
+                        folder,
+                        id,
+                        count: docs.total_count(),
+                        size: docs.total_size(),
+                        documents: docs
+                            .source_ref()
+                            .iter()
+                            .flatten()
+                            .map(DocumentSummary::from)
+                            .collect(),
+                    };
+                    info!(
+                        "Total count of documents in folder: {}",
+                        stats.count.unwrap_or_default()
+                    );
+                    info!("Total size of documents in folder: {}", stats.size);
+                    output::write(format, &stats, &stats.documents)?;
+                }
+                FolderCommand::Inspect { folder } => {
+                    let id = ctx.folder_id(&folders, &folder)?;
+                    let folder = folders
+                        .source()
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|i| i.id_ref() == &Some(id))
+                        .collect::<Vec<Folder>>();
+                    let nodes = folder
+                        .iter()
+                        .map(|f| FolderNode::new(f, 0))
+                        .collect::<Vec<FolderNode>>();
+                    output::write(format, &folder, &nodes)?;
+                }
+                FolderCommand::Tree { folder } => {
+                    let roots = match &folder {
+                        Some(name) => {
+                            let id = ctx.folder_id(&folders, name)?;
+                            folders
+                                .source()
+                                .unwrap_or_default()
+                                .into_iter()
+                                .filter(|f| f.id_ref() == &Some(id))
+                                .collect::<Vec<Folder>>()
                         }
+                        None => folders.roots(),
+                    };
+                    let mut nodes = Vec::new();
+                    for root in roots {
+                        tree(&folders, &root, 0, &mut nodes);
                     }
-                } else {
-                    info!("Folder not present.");
+                    output::write(format, &nodes, &nodes)?;
                 }
-            };
-        }
-        "delete_folder_content" => {
-            let doc_info = DocInfo::new(&doc_header, &args, &folder_url);
-            trace!("Set up query data for folders.");
-            let folders = Folders::query(&doc_info, &auth_user).await?;
-
-            trace!("Search for docs in specified folder.");
-            if let Some(folder) = &cli.param {
-                if let Some(id) = folders.get_id(folder) {
+                FolderCommand::Create { .. } => {}
+                FolderCommand::Purge(purge) => {
+                    let id = ctx.folder_id(&folders, &purge.folder)?;
                     trace!("Folder id: {:?}", id);
-                    trace!("Specify folder for search.");
-                    args.filter(&format!("FolderId eq {}", id));
                     trace!("Querying documents in folder.");
-                    let doc_info = DocInfo::new(&doc_header, &args, &doc_url);
-                    let docs = Documents::query(&doc_info, &auth_user).await?;
-                    let res = docs.update(&doc_info, &auth_user, "draft").await?;
-                    trace!("Response: {:?}", res);
-                    let res = docs.delete(&doc_info, &auth_user).await?;
-                    trace!("Response: {:?}", res);
-                } else {
-                    info!("Folder not present.");
+                    let docs = ctx.documents(Some(id)).await?;
+                    let mut filter = PurgeFilter::new();
+                    if let Some(name) = &purge.name {
+                        filter.name(name)?;
+                    }
+                    if let Some(before) = &purge.before {
+                        filter.before(before)?;
+                    }
+                    if let Some(after) = &purge.after {
+                        filter.after(after)?;
+                    }
+                    if let Some(status) = purge.status {
+                        filter.status(status);
+                    }
+                    let plan = PurgePlan::new(&docs, &filter);
+                    let store = prepare_purge(
+                        &ctx,
+                        &plan,
+                        &purge.folder,
+                        purge.yes,
+                        purge.max,
+                        &purge.backup,
+                    )
+                    .await?;
+                    let res = plan.execute(store.as_ref()).await?;
+                    let results = action_results(plan.documents_ref(), "delete", &res);
+                    output::write(format, &results, &results)?;
                 }
-            };
+            }
         }
-        "inspect_folder" => {
-            let doc_info = DocInfo::new(&doc_header, &args, &folder_url);
-            trace!("Set up query data for folders.");
-            let folders = Folders::query(&doc_info, &auth_user).await?;
-
-            trace!("Search for docs in specified folder.");
-            if let Some(folder) = &cli.param {
-                if let Some(id) = folders.get_id(folder) {
-                    if let Some(items) = folders.source() {
-                        let folder = items
-                            .iter()
-                            .filter(|i| i.id_ref() == &Some(id))
-                            .collect::<Vec<&Folder>>();
-                        if !folder.is_empty() {
-                            info!("{:#?}", folder);
+        Command::Docs { command } => {
+            let folders = ctx.folders()?;
+            match command {
+                DocsCommand::List { folder } => {
+                    let id = ctx.folder_id(&folders, &folder)?;
+                    let docs = ctx.documents(Some(id)).await?;
+                    let summaries = docs
+                        .source_ref()
+                        .iter()
+                        .flatten()
+                        .map(DocumentSummary::from)
+                        .collect::<Vec<DocumentSummary>>();
+                    output::write(format, &summaries, &summaries)?;
+                }
+                DocsCommand::Update {
+                    folder,
+                    action,
+                    ids,
+                    yes,
+                    max,
+                } => {
+                    let id = ctx.folder_id(&folders, &folder)?;
+                    let docs = ctx.documents(Some(id)).await?.select(&ids)?;
+                    let count = docs.source_ref().as_ref().map_or(0, |d| d.len());
+                    info!("Documents selected to {}: {}.", action.command(), count);
+                    if count > max {
+                        return Err(LinkError::PurgeLimit { count, max });
+                    }
+                    if count > 0 && !yes {
+                        confirm(&format!(
+                            "Set {} documents in {} to {}?",
+                            count,
+                            folder,
+                            action.command()
+                        ))?;
+                    }
+                    let res = update_documents(ctx.store.as_ref(), &docs, action.command()).await?;
+                    info!("Documents updated: {}", res.len());
+                    let results = action_results(&docs, action.command(), &res);
+                    output::write(format, &results, &results)?;
+                }
+                DocsCommand::Move(args) => {
+                    let source = match &args.folder {
+                        Some(folder) => Some(ctx.folder_id(&folders, folder)?),
+                        None => None,
+                    };
+                    let docs = ctx.documents(source).await?.select(&args.ids)?;
+                    let plan = match (&args.mapping, &args.to) {
+                        (Some(mapping), _) => MovePlan::from_csv(mapping, &docs, &folders)?,
+                        (None, Some(to)) => {
+                            let mut filter = PurgeFilter::new();
+                            if let Some(name) = &args.name {
+                                filter.name(name)?;
+                            }
+                            if let Some(before) = &args.before {
+                                filter.before(before)?;
+                            }
+                            if let Some(after) = &args.after {
+                                filter.after(after)?;
+                            }
+                            if let Some(status) = args.status {
+                                filter.status(status);
+                            }
+                            MovePlan::filter(&docs, &filter, ctx.folder_id(&folders, to)?)
                         }
+                        (None, None) => MovePlan::new(),
+                    };
+                    info!("Documents selected to move: {}.", plan.count());
+                    if plan.count() > 0 && !args.yes {
+                        confirm(&format!("Move {} documents?", plan.count()))?;
+                    }
+                    let moved = plan
+                        .execute(ctx.store.as_ref(), &folders, ctx.host.as_deref())
+                        .await?;
+                    if !moved.is_empty() {
+                        relocate::write_diff(&args.output, &moved)?;
                     }
+                    output::write(format, &moved, &moved)?;
+                }
+                DocsCommand::Export { folder, output } => {
+                    let root = match &folder {
+                        Some(folder) => Some(ctx.folder_id(&folders, folder)?),
+                        None => None,
+                    };
+                    let mut records = metadata::export(ctx.store.as_ref(), &folders, root).await?;
+                    info!("Documents exported: {}", records.len());
+                    if let Some(path) = output {
+                        linkbuilder::utils::to_csv(&mut records, &path)?;
+                        info!("Export output to path: {}", path.display());
+                    }
+                    output::write(format, &records, &records)?;
+                }
+                DocsCommand::Delete {
+                    folder,
+                    ids,
+                    yes,
+                    max,
+                    backup,
+                } => {
+                    let id = ctx.folder_id(&folders, &folder)?;
+                    let docs = ctx.documents(Some(id)).await?.select(&ids)?;
+                    let plan = PurgePlan::new(&docs, &PurgeFilter::new());
+                    let store = prepare_purge(&ctx, &plan, &folder, yes, max, &backup).await?;
+                    let res = delete_documents(store.as_ref(), plan.documents_ref()).await?;
+                    info!("Documents deleted: {}", res.len());
+                    let results = action_results(plan.documents_ref(), "delete", &res);
+                    output::write(format, &results, &results)?;
                 }
             }
         }
+        Command::ApplyMetadata {
+            source,
+            folder,
+            dry_run,
+            yes,
+        } => {
+            let id = match &folder {
+                Some(folder) => Some(ctx.folder_id(&ctx.folders()?, folder)?),
+                None => None,
+            };
+            let docs = ctx.documents(id).await?;
+            let plan = MetadataPlan::from_csv(&source, &docs)?;
+            let diff = plan.diff();
+            info!(
+                "Documents with changes: {}, invalid rows: {}.",
+                plan.count(),
+                plan.errors()
+            );
+            if dry_run {
+                output::write(format, &diff, &diff)?;
+            } else {
+                if plan.count() > 0 && !yes {
+                    eprint!("{}", output::to_string(OutputFormat::Table, &diff, &diff)?);
+                    confirm(&format!("Update metadata of {} documents?", plan.count()))?;
+                }
+                let results = plan.execute(ctx.store.as_ref()).await?;
+                output::write(format, &results, &results)?;
+            }
+        }
+        Command::Retention {
+            rules,
+            as_of,
+            dry_run,
+            yes,
+        } => {
+            let rules = RetentionRules::load(&rules)?;
+            let plan = RetentionPlan::evaluate(
+                &rules,
+                ctx.store.as_ref(),
+                &ctx.folders()?,
+                as_of.as_deref(),
+            )
+            .await?;
+            let candidates = plan.candidates();
+            info!("Documents due under retention rules: {}", plan.count());
+            if dry_run {
+                output::write(format, &candidates, &candidates)?;
+            } else {
+                if plan.count() > 0 && !yes {
+                    eprint!(
+                        "{}",
+                        output::to_string(OutputFormat::Table, &candidates, &candidates)?
+                    );
+                    confirm(&format!("Apply retention to {} documents?", plan.count()))?;
+                }
+                let results = plan.execute(ctx.store.as_ref()).await?;
+                output::write(format, &results, &results)?;
+            }
+        }
+        Command::Undo { run_id } => {
+            let mut journal = ctx.journal.clone();
+            journal.undoes(&run_id);
+            let store = ctx.store.journaled(&journal);
+            let undone = Journal::undo(&cli.journal, &run_id, store.as_ref()).await?;
+            info!("Changes reversed: {}", undone.len());
+            let results = undone
+                .iter()
+                .map(|e| ActionResult {
+                    id: e.document_id.unwrap_or_default(),
+                    name: e.name.clone().unwrap_or_default(),
+                    action: e.action.clone(),
+                    response: e.result.clone(),
+                })
+                .collect::<Vec<ActionResult>>();
+            output::write(format, &results, &results)?;
+        }
+        Command::Login { .. } | Command::Logout { .. } | Command::Session { .. } => {}
+    }
 
-        _ => {}
+    if let Ok(entries) = Journal::read(ctx.journal.path_ref()) {
+        if entries
+            .iter()
+            .any(|e| &e.run_id == ctx.journal.run_id_ref())
+        {
+            info!(
+                "Changes recorded in {} under run id {}.",
+                ctx.journal.path_ref().display(),
+                ctx.journal.run_id_ref()
+            );
+        }
     }
 
     Ok(())
 }
+
+/// Returns `store`, or the store named in the `credentials` setting, or the keyring.
+fn credential_store(store: Option<CredentialStore>, settings: &Settings) -> CredentialStore {
+    match (store, settings.credentials.as_deref()) {
+        (Some(store), _) => store,
+        (None, Some("file")) => CredentialStore::File,
+        _ => CredentialStore::Keyring,
+    }
+}
+
+/// Prompts for credentials, checks them against CivicEngage and saves them in the store.
+async fn login(cli: &Cli, store: Option<CredentialStore>) -> LinkResult<()> {
+    use std::io::{BufRead, Write};
+    let settings = cli.settings()?;
+    let store = credential_store(store, &settings);
+    let username = match &settings.username {
+        Some(username) => username.clone(),
+        None => {
+            let mut stderr = std::io::stderr();
+            write!(stderr, "Username: ")?;
+            stderr.flush()?;
+            let mut username = String::new();
+            std::io::stdin().lock().read_line(&mut username)?;
+            username.trim().to_owned()
+        }
+    };
+    let mut password = rpassword::prompt_password(format!("Password for {}: ", username))?;
+    let credentials = Credentials::new(&username, &password);
+    zeroize::Zeroize::zeroize(&mut password);
+    let mut check = settings.clone();
+    check.username = Some(credentials.username.clone());
+    check.password = Some(credentials.password.clone());
+    SessionCache::from_settings(&settings)?
+        .refresh(&check)
+        .await?;
+    credential::store_provider(store, &settings)?.store(&credentials)?;
+    info!(
+        "Credentials stored for profile {}. Set credentials = \"{}\" in the profile to use them.",
+        settings.profile,
+        store.setting()
+    );
+    Ok(())
+}
+
+/// Checks `plan` against the `max` count of documents, asks to confirm the deletion from
+/// `folder` unless `yes` is set, and backs up the documents to `backup`.  Returns the store to
+/// delete with, journaling the backup path so that undo can restore the documents.
+async fn prepare_purge(
+    ctx: &Context,
+    plan: &PurgePlan,
+    folder: &str,
+    yes: bool,
+    max: usize,
+    backup: &std::path::Path,
+) -> LinkResult<Arc<dyn DocumentStore>> {
+    info!(
+        "Documents selected for deletion: {} ({}).",
+        plan.count(),
+        plan.size()?
+    );
+    plan.check_max(max)?;
+    if plan.count() == 0 {
+        return Ok(ctx.store.clone());
+    }
+    if !yes {
+        confirm(&format!(
+            "Delete {} documents ({}) from {}?",
+            plan.count(),
+            plan.size()?,
+            folder
+        ))?;
+    }
+    let path = plan.backup(backup, ctx.store.as_ref()).await?;
+    let mut journal = ctx.journal.clone();
+    journal.backup(&path);
+    Ok(ctx.store.journaled(&journal))
+}
+
+/// Asks the user to confirm `prompt` on stderr, returning an error unless they answer yes.
+/// Refuses without asking when stdin is not a terminal.
+fn confirm(prompt: &str) -> LinkResult<()> {
+    use std::io::{BufRead, IsTerminal, Write};
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        warn!("Cannot ask for confirmation without a terminal, pass --yes to proceed.");
+        return Err(LinkError::Cancelled);
+    }
+    let mut stderr = std::io::stderr();
+    write!(stderr, "{} [y/N] ", prompt)?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    stdin.lock().read_line(&mut answer)?;
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(LinkError::Cancelled),
+    }
+}
+
+/// Pairs each document in `docs` with its response to `action`.
+fn action_results(docs: &Documents, action: &str, responses: &[String]) -> Vec<ActionResult> {
+    docs.source_ref()
+        .iter()
+        .flatten()
+        .zip(responses)
+        .map(|(doc, response)| ActionResult {
+            id: doc.id(),
+            name: doc.name(),
+            action: action.to_owned(),
+            response: response.clone(),
+        })
+        .collect()
+}
+
+/// Adds `folder` and its subfolders to `nodes`, starting at depth `depth`.
+fn tree(folders: &Folders, folder: &Folder, depth: usize, nodes: &mut Vec<FolderNode>) {
+    nodes.push(FolderNode::new(folder, depth));
+    if let Some(id) = folder.id_ref() {
+        for child in folders.children(*id) {
+            tree(folders, &child, depth + 1, nodes);
+        }
+    }
+}
+
+/// Uploads files in the local directory `path` to the web folder with id `id` if not already
+/// present.
+async fn sync_folder<P: AsRef<std::path::Path>>(
+    ctx: &Context,
+    path: P,
+    id: i32,
+) -> LinkResult<SyncResult> {
+    let source = path.as_ref().display().to_string();
+    let names = FileNames::from_path(path)?;
+    trace!("Names read: {:?}", names.names().len());
+    let docs = ctx.documents(Some(id)).await?;
+
+    if let Some(count) = docs.total_count() {
+        info!("Total count of documents in folder: {}", count);
+    }
+    info!("Total size of documents in folder: {}", docs.total_size());
+    let links = DocumentLinks::from(&docs);
+    info!("Links read: {:?}", links.ref_links().len());
+    trace!("Names found: {:?}", links.ref_links().keys());
+    trace!("Comparing names of docs in web folder to names in local folder.");
+    let diff = names.not_in(&links);
+    info!("Local names not in web folder: {:?}", diff.names().len());
+    let res = diff.upload_to(ctx.store.as_ref(), id).await?;
+    info!("Files added to web folder: {:?}", res.len());
+    Ok(SyncResult {
+        source,
+        folder_id: id,
+        local: names.names().len(),
+        remote: links.ref_links().len(),
+        missing: diff.names().len(),
+        uploaded: res.len(),
+    })
+}
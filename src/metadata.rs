@@ -0,0 +1,383 @@
+//! Export and bulk editing of document metadata.
+//!
+//! [`export`] reads the full metadata of the documents in a folder and the folders below it, or
+//! on the whole Document Center, as [`DocumentRecord`] rows for records review.
+//!
+//! A manifest has a `document` column holding the id or name of each document, and any of the
+//! columns `description`, `alt_text`, `start_date`, `end_date` and `is_visible`.  Empty cells
+//! leave the current value unchanged.  A [`MetadataPlan`] checks each row against the documents
+//! in the store: the document must be found once, dates must be `YYYY-MM-DD` or
+//! `YYYY-MM-DDTHH:MM:SS` with the start date on or before the end date, and `is_visible` must be
+//! `true` or `false`.  [`MetadataPlan::diff`] lists the changes against the current values, and
+//! [`MetadataPlan::execute`] sends only the changed fields, reporting a result for each row.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::metadata::{MetadataPlan, MetadataRow};
+//! # use linkbuilder::prelude::*;
+//! # #[tokio::main]
+//! # async fn main() -> LinkResult<()> {
+//! let dir = std::env::temp_dir().join("linkbuilder_metadata_example");
+//! # let _ = std::fs::remove_dir_all(&dir);
+//! let store = LocalStore::new(dir.join("store"));
+//! let plats = store.create_folder("Plats", None)?;
+//! let file = dir.join("Plat 1.pdf");
+//! std::fs::write(&file, b"Plat")?;
+//! store.upload("Plat 1", &file, plats, true).await?;
+//!
+//! let rows = vec![
+//!     MetadataRow {
+//!         document: "Plat 1".to_owned(),
+//!         description: Some("Recorded plat.".to_owned()),
+//!         start_date: Some("2024-01-01".to_owned()),
+//!         ..Default::default()
+//!     },
+//!     MetadataRow {
+//!         document: "Plat 2".to_owned(),
+//!         ..Default::default()
+//!     },
+//! ];
+//! let plan = MetadataPlan::new(&rows, &store.documents(None).await?);
+//! assert_eq!(plan.diff().len(), 3);
+//! assert_eq!(plan.errors(), 1);
+//! let results = plan.execute(&store).await?;
+//! assert_eq!(results[0].changes, 2);
+//! assert_eq!(results[1].result, "Document not found.");
+//! # std::fs::remove_dir_all(&dir)?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::document::Document;
+use crate::output::DocumentRecord;
+use crate::prelude::*;
+use crate::store::DocumentStore;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{trace, warn};
+
+/// Journal action recorded for metadata changes.
+pub const METADATA_ACTION: &str = "metadata";
+
+/// Row of a metadata manifest.  Fields left empty are not changed.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MetadataRow {
+    /// Id or name of the document.
+    pub document: String,
+    /// Description of the document.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Alternate text of the document, read by screen readers.
+    #[serde(default)]
+    pub alt_text: Option<String>,
+    /// Date the document is first shown.
+    #[serde(default)]
+    pub start_date: Option<String>,
+    /// Date the document is last shown.
+    #[serde(default)]
+    pub end_date: Option<String>,
+    /// Whether the document is listed publicly, `true` or `false`.
+    #[serde(default)]
+    pub is_visible: Option<String>,
+}
+
+/// Change to one field of a document, listed by [`MetadataPlan::diff`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MetadataDiff {
+    /// Line of the row in the manifest.
+    pub row: usize,
+    /// Id of the document, or 0 if the row is invalid.
+    pub id: i32,
+    /// Name of the document, or the document column of an invalid row.
+    pub name: String,
+    /// Field changed, or "error" for an invalid row.
+    pub field: String,
+    /// Current value of the field.
+    pub current: String,
+    /// New value of the field, or the problem with an invalid row.
+    pub new: String,
+}
+
+/// Result of applying a row of the manifest.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MetadataResult {
+    /// Line of the row in the manifest.
+    pub row: usize,
+    /// Id of the document, or 0 if the row is invalid.
+    pub id: i32,
+    /// Name of the document, or the document column of an invalid row.
+    pub name: String,
+    /// Count of fields changed.
+    pub changes: usize,
+    /// Response from the store, or the problem with the row.
+    pub result: String,
+}
+
+/// Checked row of a manifest: the document and its changes, or the problem with the row.
+#[derive(Clone, Debug)]
+struct MetadataEntry {
+    row: usize,
+    document: String,
+    change: Result<(Document, DocumentPatch), String>,
+}
+
+/// Metadata changes read from a manifest and checked against the documents in a store.
+#[derive(Clone, Debug, Default)]
+pub struct MetadataPlan {
+    entries: Vec<MetadataEntry>,
+}
+
+impl MetadataPlan {
+    /// Creates a `MetadataPlan` from `rows`, finding each document in `docs`.  Rows are numbered
+    /// by their line in the manifest, starting from 2 after the header.
+    pub fn new(rows: &[MetadataRow], docs: &Documents) -> Self {
+        let docs = docs.source().unwrap_or_default();
+        let mut seen = HashMap::new();
+        let entries = rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let line = i + 2;
+                let change = find(&docs, &row.document).and_then(|doc| {
+                    if let Some(first) = seen.insert(doc.id(), line) {
+                        return Err(format!("Document also listed on row {}.", first));
+                    }
+                    let patch = changes(&doc, row)?;
+                    Ok((doc, patch))
+                });
+                MetadataEntry {
+                    row: line,
+                    document: row.document.clone(),
+                    change,
+                }
+            })
+            .collect();
+        MetadataPlan { entries }
+    }
+
+    /// Creates a `MetadataPlan` from the manifest at `path`, finding each document in `docs`.
+    pub fn from_csv<P: AsRef<Path>>(path: P, docs: &Documents) -> LinkResult<Self> {
+        let mut rdr = csv::Reader::from_path(path)?;
+        let rows = rdr
+            .deserialize::<MetadataRow>()
+            .collect::<Result<Vec<MetadataRow>, csv::Error>>()?;
+        Ok(Self::new(&rows, docs))
+    }
+
+    /// Returns the count of documents with changes.
+    pub fn count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| matches!(&e.change, Ok((_, patch)) if !patch.is_empty()))
+            .count()
+    }
+
+    /// Returns the count of invalid rows.
+    pub fn errors(&self) -> usize {
+        self.entries.iter().filter(|e| e.change.is_err()).count()
+    }
+
+    /// Lists each field to change with its current and new values, and each invalid row with
+    /// its problem.
+    pub fn diff(&self) -> Vec<MetadataDiff> {
+        let mut diff = Vec::new();
+        for entry in &self.entries {
+            match &entry.change {
+                Ok((doc, patch)) => {
+                    let current = serde_json::to_value(doc).unwrap_or_default();
+                    for (field, value) in patch.fields_ref() {
+                        diff.push(MetadataDiff {
+                            row: entry.row,
+                            id: doc.id(),
+                            name: doc.name(),
+                            field: field.clone(),
+                            current: text(&current[field]),
+                            new: text(value),
+                        });
+                    }
+                }
+                Err(e) => diff.push(MetadataDiff {
+                    row: entry.row,
+                    name: entry.document.clone(),
+                    field: "error".to_owned(),
+                    new: e.clone(),
+                    ..Default::default()
+                }),
+            }
+        }
+        diff
+    }
+
+    /// Sends the changes for each valid row to `store`.  Invalid rows, rows without changes and
+    /// updates that fail, such as documents changed by someone else since they were read, are
+    /// reported in the result for the row without stopping the run.  Returns a result for each
+    /// row.
+    pub async fn execute(&self, store: &dyn DocumentStore) -> LinkResult<Vec<MetadataResult>> {
+        let style = indicatif::ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {'Updating metadata.'}",
+        )
+        .unwrap();
+        let bar = ProgressBar::new(self.entries.len() as u64);
+        bar.set_style(style);
+        let mut results = Vec::new();
+        for entry in &self.entries {
+            bar.inc(1);
+            let (doc, patch) = match &entry.change {
+                Ok(change) => change,
+                Err(e) => {
+                    results.push(MetadataResult {
+                        row: entry.row,
+                        name: entry.document.clone(),
+                        result: e.clone(),
+                        ..Default::default()
+                    });
+                    continue;
+                }
+            };
+            let result = if patch.is_empty() {
+                trace!("No metadata changes for {}.", doc.name());
+                format!("Document {} unchanged.", doc.id())
+            } else {
+                match store.patch(doc, METADATA_ACTION, patch).await {
+                    Ok(result) => result,
+                    Err(e) => {
+                        warn!("Could not update metadata of {}: {}", doc.name(), e);
+                        e.to_string()
+                    }
+                }
+            };
+            results.push(MetadataResult {
+                row: entry.row,
+                id: doc.id(),
+                name: doc.name(),
+                changes: patch.fields_ref().len(),
+                result,
+            });
+        }
+        Ok(results)
+    }
+}
+
+/// Reads the metadata of the documents in `store` for export, with the folder path of each
+/// document from `folders`.  With `root` set, reads the folder with id `root` and every folder
+/// below it, otherwise every document on the Document Center.  Rows are sorted by folder path
+/// and name.
+pub async fn export(
+    store: &dyn DocumentStore,
+    folders: &Folders,
+    root: Option<i32>,
+) -> LinkResult<Vec<DocumentRecord>> {
+    let docs = match root {
+        Some(id) => {
+            let mut docs = Vec::new();
+            for folder_id in folders.subtree(id) {
+                trace!("Reading documents in {}.", folders.path(folder_id));
+                docs.extend(
+                    store
+                        .documents(Some(folder_id))
+                        .await?
+                        .source()
+                        .unwrap_or_default(),
+                );
+            }
+            docs
+        }
+        None => store.documents(None).await?.source().unwrap_or_default(),
+    };
+    let mut records = docs
+        .iter()
+        .map(|d| {
+            let path = d
+                .folder_id_ref()
+                .map(|id| folders.path(id))
+                .unwrap_or_default();
+            DocumentRecord::new(d, &path)
+        })
+        .collect::<Vec<DocumentRecord>>();
+    records.sort_by(|a, b| (&a.folder_path, &a.name).cmp(&(&b.folder_path, &b.name)));
+    Ok(records)
+}
+
+/// Finds the document in `docs` with the id or name `key`.
+fn find(docs: &[Document], key: &str) -> Result<Document, String> {
+    let key = key.trim();
+    if let Ok(id) = key.parse::<i32>() {
+        if let Some(doc) = docs.iter().find(|d| d.id() == id) {
+            return Ok(doc.clone());
+        }
+    }
+    let found = docs
+        .iter()
+        .filter(|d| d.name() == key)
+        .collect::<Vec<&Document>>();
+    match found.as_slice() {
+        [doc] => Ok((*doc).clone()),
+        [] => Err("Document not found.".to_owned()),
+        _ => Err(format!(
+            "Name matches {} documents, use the document id.",
+            found.len()
+        )),
+    }
+}
+
+/// Checks the values in `row` and returns a patch changing the fields of `doc` that differ.
+fn changes(doc: &Document, row: &MetadataRow) -> Result<DocumentPatch, String> {
+    let start = row.start_date.as_deref().map(date).transpose()?;
+    let end = row.end_date.as_deref().map(date).transpose()?;
+    let visible = match row.is_visible.as_deref().map(|v| v.trim().to_lowercase()) {
+        Some(v) if v == "true" => Some(true),
+        Some(v) if v == "false" => Some(false),
+        Some(v) => return Err(format!("is_visible must be true or false, not {}.", v)),
+        None => None,
+    };
+    let mut value = serde_json::to_value(doc).map_err(|e| e.to_string())?;
+    let current = |field: &str| value[field].as_str().map(|s| s.to_owned());
+    if let (Some(start), Some(end)) = (
+        start.clone().or_else(|| current("StartDate")),
+        end.clone().or_else(|| current("EndDate")),
+    ) {
+        if start > end {
+            return Err(format!("Start date {} is after end date {}.", start, end));
+        }
+    }
+    let set = [
+        ("Description", row.description.clone().map(|v| json!(v))),
+        ("AltText", row.alt_text.clone().map(|v| json!(v))),
+        ("StartDate", start.map(|v| json!(v))),
+        ("EndDate", end.map(|v| json!(v))),
+        ("IsVisible", visible.map(|v| json!(v))),
+    ];
+    if let Value::Object(map) = &mut value {
+        for (field, new) in set {
+            if let Some(new) = new {
+                map.insert(field.to_owned(), new);
+            }
+        }
+    }
+    let after = serde_json::from_value::<Document>(value).map_err(|e| e.to_string())?;
+    DocumentPatch::diff(doc, &after).map_err(|e| e.to_string())
+}
+
+/// Checks that `value` is a date in the form `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM:SS`, and returns
+/// it in the second form used by the Document Center.
+fn date(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(format!("{}T00:00:00", date.format("%Y-%m-%d")));
+    }
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .map(|d| d.format("%Y-%m-%dT%H:%M:%S").to_string())
+        .map_err(|_| format!("{} is not a date in the form YYYY-MM-DD.", value))
+}
+
+/// Returns `value` as plain text for display, without quotes around strings.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
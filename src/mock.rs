@@ -0,0 +1,635 @@
+//! In-process mock of the CivicEngage API for tests.
+//!
+//! A [`MockServer`] listens on a local port and serves the Authenticate, Folder and Document
+//! endpoints, along with document downloads from `/DocumentCenter/View/{id}`.  Folders and
+//! documents are held in memory, and the server follows the rules of CivicEngage that the program
+//! depends on: calls need a session key issued by Authenticate, queries are paged with `$top` and
+//! `$skip`, and published documents cannot be deleted.  Tests can also inject failed responses,
+//! set a rate limit, lock the account or expire sessions.
+//!
+//! Point the program at the server with the [`Settings`] from [`MockServer::settings`], or the
+//! config file profile from [`MockServer::profile`].  The module is built only with the
+//! `test-support` feature, which the tests of this crate enable.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::mock::MockServer;
+//! # use linkbuilder::prelude::*;
+//! # #[tokio::main]
+//! # async fn main() -> LinkResult<()> {
+//! let server = MockServer::start()?;
+//! let plats = server.add_folder("Plats", None);
+//! server.add_document(plats, "Plat 12", true, b"%PDF-1.4");
+//!
+//! let settings = server.settings();
+//! let user = authorize_user(&settings).await?;
+//! let store = CivicEngageStore::from_settings(&settings, &user)?;
+//! let folders = store.folders().await?;
+//! assert_eq!(folders.get_id("Plats"), Some(plats));
+//! let docs = store.documents(Some(plats)).await?;
+//! assert_eq!(docs.total_count(), Some(1));
+//!
+//! // Published documents must be set to draft before they can be deleted.
+//! let doc = &docs.source().unwrap_or_default()[0];
+//! store.delete(doc).await?;
+//! assert_eq!(server.documents(None).total_count(), Some(1));
+//! store.update(doc, "draft").await?;
+//! store.delete(doc).await?;
+//! assert_eq!(server.documents(None).total_count(), Some(0));
+//! # Ok(())
+//! # }
+//! ```
+use crate::prelude::*;
+use crate::store::now;
+use data_encoding::BASE64;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::oneshot;
+use tracing::trace;
+
+/// Host of the user domain on the mock server.
+pub const MOCK_HOST: &str = "example.gov";
+/// API key accepted by the mock server.
+pub const MOCK_API_KEY: &str = "mock-api-key";
+/// Partition accepted by the mock server.
+pub const MOCK_PARTITION: &str = "1234";
+/// User name accepted by the mock server, without the domain.
+pub const MOCK_USERNAME: &str = "gis";
+/// Password accepted by the mock server.
+pub const MOCK_PASSWORD: &str = "mock-password";
+/// Id of the user logged in on the mock server.
+pub const MOCK_USER_ID: i32 = 7;
+
+/// A request received by a [`MockServer`].
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    /// HTTP method of the request.
+    pub method: String,
+    /// Path of the request, without the query string.
+    pub path: String,
+    /// Decoded query parameters of the request.
+    pub query: HashMap<String, String>,
+    /// Body of the request, if it holds JSON.
+    pub body: Option<Value>,
+}
+
+/// Folders, documents and settings held by a [`MockServer`].
+#[derive(Debug, Default)]
+struct MockState {
+    next_id: i32,
+    folders: Vec<Map<String, Value>>,
+    documents: Vec<Map<String, Value>>,
+    files: HashMap<i32, (String, Vec<u8>)>,
+    sessions: HashSet<String>,
+    logins: usize,
+    locked: bool,
+    page_size: Option<usize>,
+    rate_limit: Option<usize>,
+    failures: VecDeque<(Option<String>, String, u16)>,
+    requests: Vec<MockRequest>,
+}
+
+impl MockState {
+    fn next_id(&mut self) -> i32 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/// Mock CivicEngage server running on a local port.  The server stops when dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockServer {
+    /// Starts a new `MockServer` on a free local port.  Must be called from within a tokio
+    /// runtime.
+    pub fn start() -> LinkResult<Self> {
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let shared = state.clone();
+        let (addr, shutdown) = spawn(move |req| respond(shared.clone(), req))?;
+        trace!("Mock server listening on {}.", addr);
+        Ok(MockServer {
+            addr,
+            state,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Returns the base url of the mock site, e.g. `http://127.0.0.1:8080`.
+    pub fn site(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Returns settings for logging in to the mock server.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            host: Some(MOCK_HOST.to_owned()),
+            site: Some(self.site()),
+            api_key: Some(MOCK_API_KEY.to_owned()),
+            partition: Some(MOCK_PARTITION.to_owned()),
+            username: Some(MOCK_USERNAME.to_owned()),
+            password: Some(secrecy::SecretString::new(MOCK_PASSWORD.to_owned())),
+            profile: "mock".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns a config file profile named `name` for logging in to the mock server.  See
+    /// [`crate::config`].
+    pub fn profile(&self, name: &str) -> String {
+        format!(
+            "[profiles.{}]\nhost = \"{}\"\nsite = \"{}\"\napi_key = \"{}\"\npartition = \"{}\"\nusername = \"{}\"\npassword = \"{}\"\n",
+            name,
+            MOCK_HOST,
+            self.site(),
+            MOCK_API_KEY,
+            MOCK_PARTITION,
+            MOCK_USERNAME,
+            MOCK_PASSWORD
+        )
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Adds a folder named `name` under the folder with id `parent_id`, or at the root if
+    /// `parent_id` is `None`.  Returns the id of the folder.
+    pub fn add_folder(&self, name: &str, parent_id: Option<i32>) -> i32 {
+        let mut state = self.state();
+        let id = state.next_id();
+        let folder = json!({
+            "Id": id,
+            "Name": name,
+            "Description": null,
+            "Status": 30,
+            "ParentID": parent_id,
+            "CreatedDate": now(),
+            "IsArchived": false,
+            "URL": format!("/DocumentCenter/Index/{}", id),
+        });
+        if let Value::Object(folder) = folder {
+            state.folders.push(folder);
+        }
+        id
+    }
+
+    /// Adds a document named `name` holding `data` to the folder with id `folder_id`, with status
+    /// "Published" if `publish` is true, or "Draft" otherwise.  Returns the id of the document.
+    pub fn add_document(&self, folder_id: i32, name: &str, publish: bool, data: &[u8]) -> i32 {
+        let mut state = self.state();
+        let file_name = format!("{}.pdf", name);
+        insert_document(
+            &mut state,
+            folder_id,
+            name,
+            &file_name,
+            publish,
+            data.to_vec(),
+        )
+    }
+
+    /// Sets the field `key` of the document with id `id` to `value`, as when CivicEngage adds a
+    /// field to its responses.
+    pub fn set_field(&self, id: i32, key: &str, value: Value) -> &Self {
+        let mut state = self.state();
+        if let Some(doc) = state.documents.iter_mut().find(|d| d["Id"] == json!(id)) {
+            doc.insert(key.to_owned(), value);
+        }
+        self
+    }
+
+    /// Returns the documents on the server in the folder with id `folder_id`, or every document
+    /// if `folder_id` is `None`.
+    pub fn documents(&self, folder_id: Option<i32>) -> Documents {
+        let state = self.state();
+        let docs = state
+            .documents
+            .iter()
+            .filter(|d| folder_id.is_none() || d.get("FolderId") == Some(&json!(folder_id)))
+            .filter_map(|d| serde_json::from_value(Value::Object(d.clone())).ok())
+            .collect::<Vec<crate::document::Document>>();
+        Documents::from(docs)
+    }
+
+    /// Limits the documents and folders returned by a query to `value` per page when the query
+    /// does not set `$top`.
+    pub fn page_size(&self, value: usize) -> &Self {
+        self.state().page_size = Some(value);
+        self
+    }
+
+    /// Answers every request after the next `value` with status 429 and a `Retry-After` header.
+    pub fn rate_limit(&self, value: usize) -> &Self {
+        let mut state = self.state();
+        let count = state.requests.len();
+        state.rate_limit = Some(count + value);
+        self
+    }
+
+    /// Answers the next request with a path starting with `path` with `status`.
+    pub fn fail_next(&self, path: &str, status: u16) -> &Self {
+        self.state()
+            .failures
+            .push_back((None, path.to_owned(), status));
+        self
+    }
+
+    /// Answers the next request with the HTTP `method` and a path starting with `path` with
+    /// `status`, e.g. to reject an update without failing the read that checks for newer edits.
+    pub fn fail_next_method(&self, method: &str, path: &str, status: u16) -> &Self {
+        self.state()
+            .failures
+            .push_back((Some(method.to_owned()), path.to_owned(), status));
+        self
+    }
+
+    /// Locks the account, so that logins fail with a locked account message.
+    pub fn lock_account(&self, value: bool) -> &Self {
+        self.state().locked = value;
+        self
+    }
+
+    /// Expires every session key issued so far, so that calls made with them are rejected.
+    pub fn expire_sessions(&self) -> &Self {
+        self.state().sessions.clear();
+        self
+    }
+
+    /// Returns the count of successful logins.
+    pub fn logins(&self) -> usize {
+        self.state().logins
+    }
+
+    /// Returns the requests received by the server, oldest first.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state().requests.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            tx.send(()).ok();
+        }
+    }
+}
+
+/// Serves requests on a free local port with `handler`, in a task on the current tokio runtime.
+/// Returns the address of the server, and a sender that stops the server.
+pub(crate) fn spawn<F, R>(handler: F) -> LinkResult<(SocketAddr, oneshot::Sender<()>)>
+where
+    F: Fn(Request<Body>) -> R + Clone + Send + Sync + 'static,
+    R: Future<Output = Result<Response<Body>, Infallible>> + Send + 'static,
+{
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    let make = make_service_fn(move |_| {
+        let handler = handler.clone();
+        async move { Ok::<_, Infallible>(service_fn(handler)) }
+    });
+    let server = hyper::Server::from_tcp(listener)?.serve(make);
+    let addr = server.local_addr();
+    let (tx, rx) = oneshot::channel::<()>();
+    tokio::spawn(server.with_graceful_shutdown(async {
+        rx.await.ok();
+    }));
+    Ok((addr, tx))
+}
+
+/// Adds a document record and file to `state`.  Returns the id of the document.
+fn insert_document(
+    state: &mut MockState,
+    folder_id: i32,
+    name: &str,
+    file_name: &str,
+    publish: bool,
+    data: Vec<u8>,
+) -> i32 {
+    let id = state.next_id();
+    let date = now();
+    let doc = json!({
+        "Id": id,
+        "Name": name,
+        "Description": null,
+        "Status": if publish { 30 } else { 10 },
+        "FileSize": data.len() as f64 / 1024.,
+        "CreatedDate": date,
+        "CreatedBy": MOCK_USER_ID,
+        "FileUploadedDate": date,
+        "FileUploadedBy": MOCK_USER_ID,
+        "IsVisible": false,
+        "FileType": "pdf",
+        "URL": format!("/DocumentCenter/View/{}/{}", id, crate::export::slug(name)),
+        "FolderId": folder_id,
+        "FileName": file_name,
+        "IsArchived": false,
+    });
+    if let Value::Object(doc) = doc {
+        state.documents.push(doc);
+    }
+    state.files.insert(id, (file_name.to_owned(), data));
+    id
+}
+
+/// Builds a response with status `status` and the JSON body `body`.
+pub(crate) fn reply(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap_or_default()
+}
+
+/// Returns the value of the header `name` in `req`.
+fn header<'a>(req: &'a Request<Body>, name: &str) -> &'a str {
+    req.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Handles a request to the mock server.
+async fn respond(
+    state: Arc<Mutex<MockState>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_owned();
+    let query = url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect::<HashMap<String, String>>();
+    let authorized = [
+        header(&req, "apikey") == MOCK_API_KEY,
+        header(&req, "partition") == MOCK_PARTITION,
+    ];
+    let session = header(&req, "userapikey").to_owned();
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return Ok(reply(StatusCode::BAD_REQUEST, json!("Unreadable body."))),
+    };
+    trace!("Mock request: {} {}", method, path);
+
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+    state.requests.push(MockRequest {
+        method: method.to_string(),
+        path: path.clone(),
+        query: query.clone(),
+        body: serde_json::from_slice(&body).ok(),
+    });
+    if state
+        .rate_limit
+        .is_some_and(|limit| state.requests.len() > limit)
+    {
+        let mut res = reply(
+            StatusCode::TOO_MANY_REQUESTS,
+            json!({ "Message": "Rate limit exceeded." }),
+        );
+        res.headers_mut()
+            .insert("retry-after", hyper::header::HeaderValue::from_static("1"));
+        return Ok(res);
+    }
+    if let Some(i) = state.failures.iter().position(|(m, p, _)| {
+        path.starts_with(p) && m.as_ref().is_none_or(|m| m.as_str() == method.as_str())
+    }) {
+        if let Some((_, _, status)) = state.failures.remove(i) {
+            let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            return Ok(reply(status, json!({ "Message": "Injected failure." })));
+        }
+    }
+
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .map(|s| s.to_owned())
+        .collect::<Vec<String>>();
+    let segments = segments.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+    let res = match (&method, segments.as_slice()) {
+        (&Method::POST, ["api", "Authentication", "v1", "Authenticate"]) => {
+            authenticate(&mut state, authorized, &body)
+        }
+        (_, ["api", "DocumentCenter", "v1", ..])
+            if !(authorized[0] && authorized[1] && state.sessions.contains(&session)) =>
+        {
+            reply(
+                StatusCode::UNAUTHORIZED,
+                json!({ "Message": "Authorization has been denied for this request." }),
+            )
+        }
+        (&Method::GET, ["api", "DocumentCenter", "v1", "Folder"]) => {
+            let folders = state.folders.clone();
+            page(&state, folders, &query)
+        }
+        (&Method::GET, ["api", "DocumentCenter", "v1", "Document"]) => {
+            let folder = query
+                .get("$filter")
+                .and_then(|f| f.strip_prefix("FolderId eq "))
+                .and_then(|id| id.trim().parse::<i32>().ok());
+            let docs = state
+                .documents
+                .iter()
+                .filter(|d| folder.is_none() || d.get("FolderId") == Some(&json!(folder)))
+                .cloned()
+                .collect();
+            page(&state, docs, &query)
+        }
+        (&Method::GET, ["api", "DocumentCenter", "v1", "Document", id]) => {
+            match state
+                .documents
+                .iter()
+                .find(|d| d["Id"].as_i64().map(|i| i.to_string()).as_deref() == Some(*id))
+            {
+                Some(doc) => reply(StatusCode::OK, Value::Object(doc.clone())),
+                None => reply(StatusCode::NOT_FOUND, json!("Document not found.")),
+            }
+        }
+        (&Method::POST, ["api", "DocumentCenter", "v1", "Document"]) => upload(&mut state, &body),
+        (&Method::PUT, ["api", "DocumentCenter", "v1", "Document", id]) => {
+            update(&mut state, id, &body)
+        }
+        (&Method::DELETE, ["api", "DocumentCenter", "v1", "Document", id]) => {
+            delete(&mut state, id)
+        }
+        (&Method::GET, ["DocumentCenter", "View", id, ..]) => download(&state, id),
+        _ => reply(StatusCode::NOT_FOUND, json!({ "Message": "Not found." })),
+    };
+    Ok(res)
+}
+
+/// Handles a login.  Failures use the messages CivicEngage returns.
+fn authenticate(state: &mut MockState, authorized: [bool; 2], body: &[u8]) -> Response<Body> {
+    let body = serde_json::from_slice::<Value>(body).unwrap_or_default();
+    let username = format!("{}@{}", MOCK_USERNAME, MOCK_HOST);
+    let failure = |status: StatusCode, message: &str| {
+        reply(
+            status,
+            json!({ "Success": false, "Message": message, "APIKey": null }),
+        )
+    };
+    if !authorized[0] {
+        failure(StatusCode::UNAUTHORIZED, "Invalid API key.")
+    } else if !authorized[1] {
+        failure(StatusCode::BAD_REQUEST, "Invalid partition.")
+    } else if state.locked {
+        failure(StatusCode::OK, "Account is locked.")
+    } else if body["Username"] != json!(username) || body["Password"] != json!(MOCK_PASSWORD) {
+        failure(StatusCode::OK, "Invalid username or password.")
+    } else {
+        state.logins += 1;
+        let key = format!("mock-session-{}", state.logins);
+        state.sessions.insert(key.clone());
+        reply(
+            StatusCode::OK,
+            json!({
+                "Success": true,
+                "APIKey": key,
+                "UserId": MOCK_USER_ID,
+                "Message": "Login successful.",
+                "AdditionalInfo": null,
+            }),
+        )
+    }
+}
+
+/// Returns the page of `items` selected by the `$top` and `$skip` parameters in `query`.
+fn page(
+    state: &MockState,
+    items: Vec<Map<String, Value>>,
+    query: &HashMap<String, String>,
+) -> Response<Body> {
+    let total = items.len();
+    let skip = query
+        .get("$skip")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0usize);
+    let top = query
+        .get("$top")
+        .and_then(|v| v.parse().ok())
+        .or(state.page_size)
+        .unwrap_or(total.max(1));
+    let source = items
+        .into_iter()
+        .skip(skip)
+        .take(top)
+        .collect::<Vec<Map<String, Value>>>();
+    let top = top.max(1);
+    let count = query.get("$inlinecount").map(|v| v == "allpages") == Some(true);
+    reply(
+        StatusCode::OK,
+        json!({
+            "CurrentPage": skip / top + 1,
+            "PageSize": top,
+            "TotalCount": if count { Some(total) } else { None },
+            "TotalPages": total.div_ceil(top),
+            "Source": source,
+            "SortBy": query.get("$orderby"),
+            "Filter": query.get("$filter"),
+            "HasPreviousPage": skip > 0,
+            "HasNextPage": skip + top < total,
+        }),
+    )
+}
+
+/// Handles a document upload.
+fn upload(state: &mut MockState, body: &[u8]) -> Response<Body> {
+    let body = serde_json::from_slice::<Value>(body).unwrap_or_default();
+    let folder_id = body["FolderId"].as_i64().map(|id| id as i32);
+    let data = body["File"]
+        .as_str()
+        .and_then(|f| BASE64.decode(f.as_bytes()).ok());
+    match (body["Name"].as_str(), folder_id, data) {
+        (Some(name), Some(folder_id), Some(data)) => {
+            if !state.folders.iter().any(|f| f["Id"] == json!(folder_id)) {
+                return reply(
+                    StatusCode::BAD_REQUEST,
+                    json!({ "Message": "Folder not found." }),
+                );
+            }
+            let file_name = body["FileName"].as_str().unwrap_or(name).to_owned();
+            let publish = body["Status"] == json!("Published");
+            let id = insert_document(state, folder_id, name, &file_name, publish, data);
+            reply(StatusCode::CREATED, json!(id.to_string()))
+        }
+        _ => reply(
+            StatusCode::BAD_REQUEST,
+            json!({ "Message": "Name, FolderId and File are required." }),
+        ),
+    }
+}
+
+/// Handles a document update, saving the fields sent in the body.
+fn update(state: &mut MockState, id: &str, body: &[u8]) -> Response<Body> {
+    let body = match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Object(body)) => body,
+        _ => return reply(StatusCode::BAD_REQUEST, json!("Invalid document.")),
+    };
+    match state
+        .documents
+        .iter_mut()
+        .find(|d| d["Id"].as_i64().map(|i| i.to_string()).as_deref() == Some(id))
+    {
+        Some(doc) => {
+            for (key, value) in body {
+                let key = match key.as_str() {
+                    "Url" => "URL".to_owned(),
+                    _ => key,
+                };
+                if key != "Id" {
+                    doc.insert(key, value);
+                }
+            }
+            doc.insert("LastModifiedOn".to_owned(), json!(now()));
+            reply(StatusCode::OK, json!("Document updated."))
+        }
+        None => reply(StatusCode::NOT_FOUND, json!("Document not found.")),
+    }
+}
+
+/// Handles a document delete.  Published documents are refused.
+fn delete(state: &mut MockState, id: &str) -> Response<Body> {
+    match state
+        .documents
+        .iter()
+        .position(|d| d["Id"].as_i64().map(|i| i.to_string()).as_deref() == Some(id))
+    {
+        Some(i) if state.documents[i]["Status"] == json!(30) => reply(
+            StatusCode::BAD_REQUEST,
+            json!("Published documents must be set to draft before deletion."),
+        ),
+        Some(i) => {
+            let doc = state.documents.remove(i);
+            if let Some(id) = doc["Id"].as_i64() {
+                state.files.remove(&(id as i32));
+            }
+            reply(StatusCode::OK, json!("Document deleted."))
+        }
+        None => reply(StatusCode::NOT_FOUND, json!("Document not found.")),
+    }
+}
+
+/// Handles a document download.
+fn download(state: &MockState, id: &str) -> Response<Body> {
+    match id.parse().ok().and_then(|id: i32| state.files.get(&id)) {
+        Some((file_name, data)) => Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/pdf")
+            .header(
+                "content-disposition",
+                format!("attachment; filename=\"{}\"", file_name),
+            )
+            .body(Body::from(data.clone()))
+            .unwrap_or_default(),
+        None => reply(StatusCode::NOT_FOUND, json!({ "Message": "Not found." })),
+    }
+}
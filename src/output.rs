@@ -0,0 +1,338 @@
+//! Machine-readable results for command line output.
+//!
+//! Each command of the `linkbuilder` binary produces one of the result types in this module, or
+//! a vector of rows such as [`crate::report::ReportItem`] or [`crate::health::BrokenLink`].  The
+//! field names of these types are the stable schema of the output.  Results are written to
+//! stdout with [`write`] in the [`OutputFormat`] chosen by the user, while logs go to stderr.
+//!
+//! With [`OutputFormat::Json`], the full result is written as pretty-printed JSON.  With
+//! [`OutputFormat::Csv`] and [`OutputFormat::Table`], the rows of the result are written, one
+//! line per row.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::output::{to_string, DocumentSummary, OutputFormat};
+//! # fn main() -> linkbuilder::prelude::LinkResult<()> {
+//! let rows = vec![DocumentSummary::default()];
+//! let table = to_string(OutputFormat::Table, &rows, &rows)?;
+//! assert!(table.starts_with("id"));
+//! let csv = to_string(OutputFormat::Csv, &rows, &rows)?;
+//! assert!(csv.starts_with("id,name,status,file_size,url"));
+//! # Ok(())
+//! # }
+//! ```
+use crate::config::Settings;
+use crate::document::{Document, Folder};
+use crate::error;
+use crate::session::{Session, SessionCache};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// Formats for writing command results to stdout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Pretty-printed JSON of the full result.
+    Json,
+    /// Rows of the result aligned in columns.
+    #[default]
+    Table,
+    /// Rows of the result as csv with a header line.
+    Csv,
+}
+
+/// Summary of a document on the Document Center.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DocumentSummary {
+    /// Document id.
+    pub id: i32,
+    /// Document name.
+    pub name: String,
+    /// Integer-coded status, `10` for "Draft" and `30` for "Published".
+    pub status: Option<i32>,
+    /// File size in KB.
+    pub file_size: Option<f64>,
+    /// Url of the document.
+    pub url: Option<String>,
+}
+
+impl From<&Document> for DocumentSummary {
+    fn from(doc: &Document) -> Self {
+        DocumentSummary {
+            id: doc.id(),
+            name: doc.name(),
+            status: *doc.status_ref(),
+            file_size: doc.file_size(),
+            url: doc.url(),
+        }
+    }
+}
+
+/// Full metadata of a document on the Document Center, exported for records review.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DocumentRecord {
+    /// Document id.
+    pub id: i32,
+    /// Document name.
+    pub name: String,
+    /// Id of the folder holding the document.
+    pub folder_id: Option<i32>,
+    /// Path of the folder holding the document, with folder names separated by "/".
+    pub folder_path: String,
+    /// Integer-coded status, `10` for "Draft" and `30` for "Published".
+    pub status: Option<i32>,
+    /// Type of the uploaded file, such as "pdf".
+    pub file_type: Option<String>,
+    /// Name of the uploaded file.
+    pub file_name: Option<String>,
+    /// File size in KB.
+    pub file_size: Option<f64>,
+    /// Date the document was created.
+    pub created_date: Option<String>,
+    /// Id of the user who created the document.
+    pub created_by: Option<i32>,
+    /// Date the file was last uploaded.
+    pub file_uploaded_date: Option<String>,
+    /// Id of the user who last uploaded the file.
+    pub file_uploaded_by: Option<i32>,
+    /// Date the document was last changed.
+    pub last_modified_on: Option<String>,
+    /// Id of the user who last changed the document.
+    pub last_modified_by: Option<i32>,
+    /// Date the document is first shown.
+    pub start_date: Option<String>,
+    /// Date the document is last shown.
+    pub end_date: Option<String>,
+    /// Whether the document is listed publicly.
+    pub is_visible: Option<bool>,
+    /// Whether the document is archived.
+    pub is_archived: Option<bool>,
+    /// Description of the document.
+    pub description: Option<String>,
+    /// Alternate text of the document.
+    pub alt_text: Option<String>,
+    /// Url of the document.
+    pub url: Option<String>,
+}
+
+impl DocumentRecord {
+    /// Creates a `DocumentRecord` for `doc`, held in the folder with path `folder_path`.
+    pub fn new(doc: &Document, folder_path: &str) -> Self {
+        DocumentRecord {
+            id: doc.id(),
+            name: doc.name(),
+            folder_id: *doc.folder_id_ref(),
+            folder_path: folder_path.to_owned(),
+            status: *doc.status_ref(),
+            file_type: doc.file_type_ref().clone(),
+            file_name: doc.file_name_ref().clone(),
+            file_size: doc.file_size(),
+            created_date: doc.created_date_ref().clone(),
+            created_by: *doc.created_by_ref(),
+            file_uploaded_date: doc.file_uploaded_date_ref().clone(),
+            file_uploaded_by: *doc.file_uploaded_by_ref(),
+            last_modified_on: doc.last_modified_on_ref().clone(),
+            last_modified_by: *doc.last_modified_by_ref(),
+            start_date: doc.start_date_ref().clone(),
+            end_date: doc.end_date_ref().clone(),
+            is_visible: *doc.is_visible_ref(),
+            is_archived: *doc.is_archived(),
+            description: doc.description_ref().clone(),
+            alt_text: doc.alt_text_ref().clone(),
+            url: doc.url(),
+        }
+    }
+}
+
+/// Count and size of the documents in a folder.  Rows are the [`DocumentSummary`] of each
+/// document.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FolderStats {
+    /// Folder name.
+    pub folder: String,
+    /// Folder id.
+    pub id: i32,
+    /// Count of documents in the folder.
+    pub count: Option<i32>,
+    /// Total size of documents in the folder in KB.
+    pub size: f64,
+    /// Documents in the folder.
+    pub documents: Vec<DocumentSummary>,
+}
+
+/// A folder in the folder tree, with its depth below the root of the tree.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FolderNode {
+    /// Folder id.
+    pub id: Option<i32>,
+    /// Folder name.
+    pub name: String,
+    /// Id of the parent folder.
+    pub parent_id: Option<i32>,
+    /// Depth below the root of the tree, starting at zero.
+    pub depth: usize,
+    /// Count of items in the folder.
+    pub item_count: Option<i32>,
+}
+
+impl FolderNode {
+    /// Creates a new `FolderNode` for `folder` at depth `depth`.
+    pub fn new(folder: &Folder, depth: usize) -> Self {
+        FolderNode {
+            id: *folder.id_ref(),
+            name: folder.name_ref().clone(),
+            parent_id: *folder.parent_id_ref(),
+            depth,
+            item_count: *folder.item_count_ref(),
+        }
+    }
+}
+
+/// Result of syncing a local directory to a web folder.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SyncResult {
+    /// Local directory.
+    pub source: String,
+    /// Id of the web folder.
+    pub folder_id: i32,
+    /// Count of files in the local directory.
+    pub local: usize,
+    /// Count of document names in the web folder before the sync.
+    pub remote: usize,
+    /// Count of local files not present in the web folder.
+    pub missing: usize,
+    /// Count of files uploaded.
+    pub uploaded: usize,
+}
+
+/// Result of an action on a single document, such as an update or a delete.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ActionResult {
+    /// Document id.
+    pub id: i32,
+    /// Document name.
+    pub name: String,
+    /// Action taken.
+    pub action: String,
+    /// Response from CivicEngage.
+    pub response: String,
+}
+
+/// Link files written for a document category.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LinkExport {
+    /// Name of the category folder.
+    pub folder: String,
+    /// Count of keys with at least one link.
+    pub keys: usize,
+    /// Count of documents with names that do not conform to the category naming scheme.
+    pub nonconforming: usize,
+    /// Paths of the files written, separated by semicolons.
+    pub files: String,
+}
+
+/// Cached login session, without the session key.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SessionInfo {
+    /// Name of the profile that logged in.
+    pub profile: String,
+    /// Id of the user.
+    pub user_id: i32,
+    /// Time the session was issued in seconds since the Unix epoch.
+    pub issued: u64,
+    /// Time the session expires in seconds since the Unix epoch.
+    pub expires: u64,
+    /// Whether the session can be reused with the current settings.
+    pub valid: bool,
+    /// Path to the session file.
+    pub path: String,
+}
+
+impl SessionInfo {
+    /// Creates a new `SessionInfo` for `session` read from `cache`.
+    pub fn new(session: &Session, cache: &SessionCache, settings: &Settings) -> Self {
+        let expires = session.issued() + cache.ttl();
+        SessionInfo {
+            profile: session.profile_ref().clone(),
+            user_id: session.user_ref().user_id(),
+            issued: session.issued(),
+            expires,
+            valid: session.matches(settings) && crate::journal::timestamp() < expires,
+            path: cache.path_ref().display().to_string(),
+        }
+    }
+}
+
+/// Writes `value` to stdout as JSON, or `rows` as csv or a table, depending on `format`.
+pub fn write<T: Serialize, R: Serialize>(
+    format: OutputFormat,
+    value: &T,
+    rows: &[R],
+) -> error::LinkResult<()> {
+    let text = to_string(format, value, rows)?;
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(text.as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Returns `value` as JSON, or `rows` as csv or a table, depending on `format`.
+pub fn to_string<T: Serialize, R: Serialize>(
+    format: OutputFormat,
+    value: &T,
+    rows: &[R],
+) -> error::LinkResult<String> {
+    match format {
+        OutputFormat::Json => Ok(format!("{}\n", serde_json::to_string_pretty(value)?)),
+        OutputFormat::Csv => to_csv_string(rows),
+        OutputFormat::Table => {
+            let csv = to_csv_string(rows)?;
+            let mut rdr = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(csv.as_bytes());
+            let mut lines = Vec::new();
+            for record in rdr.records() {
+                lines.push(
+                    record?
+                        .iter()
+                        .map(|s| s.to_owned())
+                        .collect::<Vec<String>>(),
+                );
+            }
+            Ok(table(&lines))
+        }
+    }
+}
+
+fn to_csv_string<R: Serialize>(rows: &[R]) -> error::LinkResult<String> {
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        wtr.serialize(row)?;
+    }
+    wtr.flush()?;
+    let bytes = wtr
+        .into_inner()
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn table(lines: &[Vec<String>]) -> String {
+    let width = lines.iter().map(|l| l.len()).max().unwrap_or(0);
+    let mut widths = vec![0; width];
+    for line in lines {
+        for (i, cell) in line.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    let mut text = String::new();
+    for line in lines {
+        let cells = line
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| format!("{:<w$}", cell, w = widths[i]))
+            .collect::<Vec<String>>();
+        text.push_str(cells.join("  ").trim_end());
+        text.push('\n');
+    }
+    text
+}
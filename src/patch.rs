@@ -0,0 +1,254 @@
+//! Partial updates to documents on the Document Center.
+//!
+//! A [`DocumentPatch`] holds the fields to change on a document, and sends only those fields,
+//! with the id, name and folder id CivicEngage requires, instead of the full record.  Before
+//! sending, the patch reads the document again and compares its `LastModifiedOn` date with the
+//! date when the document was first read.  If web staff changed the document in between, the
+//! patch is refused with [`LinkError::ConflictError`] rather than overwriting their edits.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::document::Document;
+//! # use linkbuilder::patch::DocumentPatch;
+//! # use linkbuilder::prelude::*;
+//! # fn main() -> LinkResult<()> {
+//! let doc: Document = serde_json::from_value(serde_json::json!({
+//!     "Id": 1,
+//!     "Name": "Plat 1",
+//!     "FolderId": 2,
+//!     "Status": 30,
+//!     "File": "UGxhdCAx",
+//! }))?;
+//! let mut patch = DocumentPatch::new(&doc);
+//! patch.status(10).description("Superseded.");
+//! assert_eq!(
+//!     patch.payload(),
+//!     serde_json::json!({
+//!         "Id": 1,
+//!         "Name": "Plat 1",
+//!         "FolderId": 2,
+//!         "Status": 10,
+//!         "Description": "Superseded.",
+//!     })
+//! );
+//! # Ok(())
+//! # }
+//! ```
+use crate::document::Document;
+use crate::prelude::*;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use serde_json::{json, Map, Value};
+use tracing::trace;
+
+/// Fields set by CivicEngage rather than the user, left out of patches.
+const READ_ONLY: [&str; 15] = [
+    "Id",
+    "CreatedDate",
+    "CreatedBy",
+    "FileSize",
+    "FileType",
+    "FileUploadedDate",
+    "FileUploadedBy",
+    "File",
+    "FileName",
+    "IsChunked",
+    "IsLastChunk",
+    "UploadId",
+    "LastModifiedBy",
+    "LastModifiedOn",
+    "Url",
+];
+
+/// Changes to the fields of a [`Document`], sent to the Document Center without the unchanged
+/// fields.
+#[derive(Clone, Debug)]
+pub struct DocumentPatch {
+    id: i32,
+    name: String,
+    folder_id: Option<i32>,
+    last_modified_on: Option<String>,
+    checked: bool,
+    fields: Map<String, Value>,
+}
+
+impl DocumentPatch {
+    /// Creates an empty `DocumentPatch` for `doc`.  The patch is refused if the document was
+    /// modified after `doc` was read.
+    pub fn new(doc: &Document) -> Self {
+        DocumentPatch {
+            id: doc.id(),
+            name: doc.name(),
+            folder_id: *doc.folder_id_ref(),
+            last_modified_on: doc.last_modified_on_ref().clone(),
+            checked: true,
+            fields: Map::new(),
+        }
+    }
+
+    /// Creates a `DocumentPatch` changing the fields of `before` that differ in `after`.  Fields
+    /// set by CivicEngage, such as dates and the file, are ignored.
+    pub fn diff(before: &Document, after: &Document) -> LinkResult<Self> {
+        let mut patch = Self::new(before);
+        let (before, after) = match (serde_json::to_value(before)?, serde_json::to_value(after)?) {
+            (Value::Object(before), Value::Object(after)) => (before, after),
+            _ => return Ok(patch),
+        };
+        let keys = before
+            .keys()
+            .chain(after.keys())
+            .filter(|k| !READ_ONLY.contains(&k.as_str()))
+            .cloned()
+            .collect::<std::collections::BTreeSet<String>>();
+        for key in keys {
+            let value = after.get(&key).cloned().unwrap_or(Value::Null);
+            if before.get(&key).unwrap_or(&Value::Null) != &value {
+                patch.fields.insert(key, value);
+            }
+        }
+        Ok(patch)
+    }
+
+    /// Sets the field `key` to `value`.  `key` is the name of the field on CivicEngage, e.g.
+    /// "Status".
+    pub fn field(&mut self, key: &str, value: Value) -> &mut Self {
+        self.fields.insert(key.to_owned(), value);
+        self
+    }
+
+    /// Sets the status of the document, 10 for "Draft" or 30 for "Published".
+    pub fn status(&mut self, value: i32) -> &mut Self {
+        self.field("Status", json!(value))
+    }
+
+    /// Sets whether the document is archived.
+    pub fn is_archived(&mut self, value: bool) -> &mut Self {
+        self.field("IsArchived", json!(value))
+    }
+
+    /// Sets whether the document is listed publicly.
+    pub fn is_visible(&mut self, value: bool) -> &mut Self {
+        self.field("IsVisible", json!(value))
+    }
+
+    /// Sets the name of the document.
+    pub fn name(&mut self, value: &str) -> &mut Self {
+        self.field("Name", json!(value))
+    }
+
+    /// Sets the description of the document.
+    pub fn description(&mut self, value: &str) -> &mut Self {
+        self.field("Description", json!(value))
+    }
+
+    /// Sets the alternate text of the document, read by screen readers.
+    pub fn alt_text(&mut self, value: &str) -> &mut Self {
+        self.field("AltText", json!(value))
+    }
+
+    /// Sets the date the document is first shown, in the form `YYYY-MM-DDTHH:MM:SS`.
+    pub fn start_date(&mut self, value: &str) -> &mut Self {
+        self.field("StartDate", json!(value))
+    }
+
+    /// Sets the date the document is last shown, in the form `YYYY-MM-DDTHH:MM:SS`.
+    pub fn end_date(&mut self, value: &str) -> &mut Self {
+        self.field("EndDate", json!(value))
+    }
+
+    /// Sets the id of the folder holding the document.
+    pub fn folder_id(&mut self, value: i32) -> &mut Self {
+        self.field("FolderId", json!(value))
+    }
+
+    /// Sends the patch without checking whether the document changed since it was read.  Used to
+    /// restore documents from the journal, where the change being reversed is our own.
+    pub fn unchecked(&mut self) -> &mut Self {
+        self.checked = false;
+        self
+    }
+
+    /// The `id` field holds the id of the document to change.  This function returns the value
+    /// of the field.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// The `fields` field holds the fields to change and their new values.  This function
+    /// returns a reference to the field.
+    pub fn fields_ref(&self) -> &Map<String, Value> {
+        &self.fields
+    }
+
+    /// Returns true if the patch changes no fields.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns the body sent to CivicEngage: the changed fields, with the id, name and folder id
+    /// of the document.
+    pub fn payload(&self) -> Value {
+        let mut body = Map::new();
+        body.insert("Id".to_owned(), json!(self.id));
+        body.insert("Name".to_owned(), json!(self.name));
+        if let Some(folder_id) = self.folder_id {
+            body.insert("FolderId".to_owned(), json!(folder_id));
+        }
+        body.extend(self.fields.clone());
+        Value::Object(body)
+    }
+
+    /// Returns an error if `current`, the document as it is now, was modified after the patch
+    /// was created.
+    pub fn check(&self, current: &Document) -> LinkResult<()> {
+        if self.checked && current.last_modified_on_ref() != &self.last_modified_on {
+            return Err(LinkError::ConflictError {
+                id: self.id,
+                expected: self
+                    .last_modified_on
+                    .clone()
+                    .unwrap_or_else(|| "never".to_owned()),
+                found: current
+                    .last_modified_on_ref()
+                    .clone()
+                    .unwrap_or_else(|| "never".to_owned()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns `doc` with the patch applied and its `LastModifiedOn` date set to `modified`.
+    /// Used by stores that hold documents locally.
+    pub fn apply_to(&self, doc: &Document, modified: &str) -> LinkResult<Document> {
+        let mut value = serde_json::to_value(doc)?;
+        if let Value::Object(map) = &mut value {
+            map.extend(self.fields.clone());
+            map.insert("LastModifiedOn".to_owned(), json!(modified));
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Checks the document for changes unless the patch is unchecked, then sends the patch.
+    /// Returns the response for the caller to read.
+    pub(crate) async fn put(
+        &self,
+        info: &DocInfo,
+        user: &AuthorizedUser,
+    ) -> LinkResult<reqwest::Response> {
+        if self.checked {
+            let current = Document::get(info, user, self.id).await?;
+            self.check(&current)?;
+        }
+        trace!("Patching document {}: {}", self.id, self.payload());
+        Ok(reqwest::Client::new()
+            .put(format!("{}/{}", info.url_ref(), self.id))
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json")
+            .header(info.headers().api_key(), user.api_key())
+            .header(info.headers().partition(), user.partition())
+            .header(info.headers().user_api_key(), user.user_api_key())
+            .body(self.payload().to_string())
+            .send()
+            .await?)
+    }
+}
@@ -0,0 +1,275 @@
+//! Safe removal of documents from a folder on the Document Center.
+//!
+//! Deleting documents cannot be undone from the API, so a purge goes through a [`PurgePlan`].
+//! The plan selects the documents in a folder that match a [`PurgeFilter`], refuses to proceed
+//! if the selection is larger than a maximum count, and downloads each document with its
+//! metadata to a local backup directory before anything is deleted.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::purge::{DocStatus, PurgeFilter};
+//! # fn main() -> linkbuilder::prelude::LinkResult<()> {
+//! let mut filter = PurgeFilter::new();
+//! filter
+//!     .name("^Plat")?
+//!     .after("2020-01-01")?
+//!     .before("2021-01-01")?
+//!     .status(DocStatus::Draft);
+//! assert!(filter.matches_date(&Some("2020-06-30T10:15:00".to_owned())));
+//! assert!(!filter.matches_date(&Some("2021-01-01T00:00:00".to_owned())));
+//! assert!(!filter.matches_date(&None));
+//! # Ok(())
+//! # }
+//! ```
+use crate::document::Document;
+use crate::prelude::*;
+use crate::store::{delete_documents, update_documents, DocumentStore};
+use std::path::{Path, PathBuf};
+use tracing::{info, trace, warn};
+
+/// Document status values that can be used to filter a purge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum DocStatus {
+    /// Documents with "Draft" status.
+    Draft,
+    /// Documents with "Published" status.
+    Published,
+}
+
+impl DocStatus {
+    /// Returns the integer code used by the Document Center for the status.
+    pub fn code(&self) -> i32 {
+        match self {
+            DocStatus::Draft => 10,
+            DocStatus::Published => 30,
+        }
+    }
+}
+
+/// Selects the documents to remove in a purge.  An empty filter matches every document.
+#[derive(Clone, Debug, Default)]
+pub struct PurgeFilter {
+    name: Option<regex::Regex>,
+    before: Option<String>,
+    after: Option<String>,
+    status: Option<DocStatus>,
+}
+
+impl PurgeFilter {
+    /// Creates a new `PurgeFilter` that matches every document.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches documents with names matching the regular expression `value`.
+    pub fn name(&mut self, value: &str) -> LinkResult<&mut Self> {
+        self.name = Some(regex::Regex::new(value)?);
+        Ok(self)
+    }
+
+    /// Matches documents dated before `value`, in the form `YYYY-MM-DD`.  Returns
+    /// [`LinkError::DateError`] if `value` is not a date in that form.
+    pub fn before(&mut self, value: &str) -> LinkResult<&mut Self> {
+        self.before = Some(midnight(value)?);
+        Ok(self)
+    }
+
+    /// Matches documents dated on or after `value`, in the form `YYYY-MM-DD`.  Returns
+    /// [`LinkError::DateError`] if `value` is not a date in that form.
+    pub fn after(&mut self, value: &str) -> LinkResult<&mut Self> {
+        self.after = Some(midnight(value)?);
+        Ok(self)
+    }
+
+    /// Matches documents with status `value`.
+    pub fn status(&mut self, value: DocStatus) -> &mut Self {
+        self.status = Some(value);
+        self
+    }
+
+    /// Returns true if `date` falls within the date range of the filter.  Dates from the
+    /// Document Center are ISO 8601 strings, and the bounds are held as midnight in the same
+    /// form, so they compare in order as text.  Documents without a date do not match a filter
+    /// with a date range.
+    pub fn matches_date(&self, date: &Option<String>) -> bool {
+        if self.before.is_none() && self.after.is_none() {
+            return true;
+        }
+        match date {
+            Some(date) => {
+                let before = self.before.as_ref().is_none_or(|b| date < b);
+                let after = self.after.as_ref().is_none_or(|a| date >= a);
+                before && after
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if `doc` matches the filter.
+    pub fn matches(&self, doc: &Document) -> bool {
+        let name = self.name.as_ref().is_none_or(|re| re.is_match(&doc.name()));
+        let status = self
+            .status
+            .is_none_or(|s| doc.status_ref() == &Some(s.code()));
+        name && status && self.matches_date(&doc.date())
+    }
+}
+
+/// Documents selected for removal from a folder.
+#[derive(Clone, Debug)]
+pub struct PurgePlan {
+    documents: Documents,
+}
+
+impl PurgePlan {
+    /// Creates a new `PurgePlan` from the documents in `docs` that match `filter`.
+    pub fn new(docs: &Documents, filter: &PurgeFilter) -> Self {
+        PurgePlan {
+            documents: docs.retain(|d| filter.matches(d)),
+        }
+    }
+
+    /// The `documents` field holds the documents selected for removal.  This function returns a
+    /// reference to the field.
+    pub fn documents_ref(&self) -> &Documents {
+        &self.documents
+    }
+
+    /// Returns the count of documents selected for removal.
+    pub fn count(&self) -> usize {
+        self.documents.source_ref().as_ref().map_or(0, |d| d.len())
+    }
+
+    /// Returns the total size of the documents selected for removal as a human-readable string.
+    pub fn size(&self) -> LinkResult<String> {
+        let size =
+            byte_unit::Byte::from_unit(self.documents.total_size(), byte_unit::ByteUnit::KB)?;
+        Ok(size.get_appropriate_unit(false).to_string())
+    }
+
+    /// Returns an error if the plan would remove more than `max` documents.
+    pub fn check_max(&self, max: usize) -> LinkResult<()> {
+        let count = self.count();
+        if count > max {
+            Err(LinkError::PurgeLimit { count, max })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Downloads each document in the plan from `store` to a new subdirectory of `dir`, along
+    /// with a `metadata.json` file holding the document records.  Returns the path to the
+    /// subdirectory.
+    pub async fn backup<P: AsRef<Path>>(
+        &self,
+        dir: P,
+        store: &dyn DocumentStore,
+    ) -> LinkResult<PathBuf> {
+        let path = backup_dir(dir.as_ref())?;
+        let docs = self.documents.source().unwrap_or_default();
+        let metadata = serde_json::to_string_pretty(&docs)?;
+        std::fs::write(path.join("metadata.json"), metadata)?;
+        info!(
+            "Metadata for {} documents written to {}.",
+            docs.len(),
+            path.display()
+        );
+
+        for doc in &docs {
+            if doc.url_ref().is_none() {
+                warn!("Document {} has no url, skipping download.", doc.id());
+                continue;
+            }
+            trace!("Downloading document {}.", doc.id());
+            let download = store.download(doc).await?;
+            let file_name = download
+                .file_name
+                .unwrap_or_else(|| crate::export::slug(&doc.name()));
+            std::fs::write(
+                path.join(format!("{}_{}", doc.id(), file_name)),
+                download.data,
+            )?;
+        }
+        info!("Documents backed up to {}.", path.display());
+        Ok(path)
+    }
+
+    /// Sets each document in the plan to draft and then deletes it from `store`.  Returns the
+    /// responses to the delete requests.
+    pub async fn execute(&self, store: &dyn DocumentStore) -> LinkResult<Vec<String>> {
+        let res = update_documents(store, &self.documents, "draft").await?;
+        trace!("Response: {:?}", res);
+        delete_documents(store, &self.documents).await
+    }
+}
+
+/// Creates a subdirectory of `dir` named for the current time, adding a count to the name if a
+/// backup made in the same second holds it already.  Returns the path to the subdirectory.
+/// Returns the date `value`, in the form `YYYY-MM-DD`, as midnight in the ISO 8601 form used by
+/// the Document Center.
+fn midnight(value: &str) -> LinkResult<String> {
+    let date =
+        chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| LinkError::DateError {
+            value: value.to_owned(),
+        })?;
+    Ok(format!("{}T00:00:00", date.format("%Y-%m-%d")))
+}
+
+fn backup_dir(dir: &Path) -> LinkResult<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let mut count = 0;
+    loop {
+        let name = match count {
+            0 => format!("purge_{}", stamp),
+            _ => format!("purge_{}_{}", stamp, count),
+        };
+        let path = dir.join(name);
+        match std::fs::create_dir(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => count += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_dates_are_rejected() {
+        let mut filter = PurgeFilter::new();
+        assert!(matches!(
+            filter.before("2021-1-1x"),
+            Err(LinkError::DateError { .. })
+        ));
+        assert!(matches!(
+            filter.after("01/01/2020"),
+            Err(LinkError::DateError { .. })
+        ));
+        assert!(matches!(
+            filter.after("2020-02-30"),
+            Err(LinkError::DateError { .. })
+        ));
+        assert!(filter.matches_date(&Some("2020-06-30T10:15:00".to_owned())));
+    }
+
+    #[test]
+    fn dates_compare_from_midnight() {
+        let mut filter = PurgeFilter::new();
+        filter
+            .after("2020-1-5")
+            .unwrap()
+            .before("2020-02-01")
+            .unwrap();
+        assert!(filter.matches_date(&Some("2020-01-05T00:00:00".to_owned())));
+        assert!(!filter.matches_date(&Some("2020-01-04T23:59:59".to_owned())));
+        assert!(filter.matches_date(&Some("2020-01-31T23:59:59".to_owned())));
+        assert!(!filter.matches_date(&Some("2020-02-01T00:00:00".to_owned())));
+    }
+}
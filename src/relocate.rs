@@ -0,0 +1,235 @@
+//! Moving documents between folders on the Document Center.
+//!
+//! Moving a document changes its folder id through the update endpoint, so the document keeps
+//! its id and public url.  A [`MovePlan`] lists the documents to move and the folder for each,
+//! selected with a [`PurgeFilter`] from a folder or read from a csv mapping with
+//! [`MovePlan::from_csv`].  Each move is written to a link diff, `moved_links.csv` in the link
+//! export directory, recording the link and the folders it moved between, so that link files
+//! split by folder can be updated to match.  Moves are recorded in the journal, and
+//! [`crate::journal::Journal::undo`] returns the documents to their folders.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::prelude::*;
+//! # use linkbuilder::relocate::MovePlan;
+//! # #[tokio::main]
+//! # async fn main() -> LinkResult<()> {
+//! let dir = std::env::temp_dir().join("linkbuilder_relocate_example");
+//! # let _ = std::fs::remove_dir_all(&dir);
+//! let store = LocalStore::new(dir.join("store"));
+//! let plats = store.create_folder("Plats", None)?;
+//! let older = store.create_folder("Plats 1990-1999", Some(plats))?;
+//! let file = dir.join("Plat 1995-01.pdf");
+//! std::fs::write(&file, b"Plat")?;
+//! store.upload("Plat 1995-01", &file, plats, true).await?;
+//!
+//! let mut filter = PurgeFilter::new();
+//! filter.name("^Plat 199")?;
+//! let plan = MovePlan::filter(&store.documents(Some(plats)).await?, &filter, older);
+//! let moved = plan.execute(&store, &store.folders().await?, None).await?;
+//! assert_eq!(moved[0].to, "Plats 1990-1999");
+//! assert_eq!(store.documents(Some(older)).await?.total_count(), Some(1));
+//! # std::fs::remove_dir_all(&dir)?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::document::Document;
+use crate::prelude::*;
+use crate::store::DocumentStore;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{info, trace, warn};
+
+/// Name of the link diff written to the link export directory.
+pub const MOVED_LINKS: &str = "moved_links.csv";
+
+/// Row of a csv mapping documents to the folders they move to.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MoveMapping {
+    /// Id or name of the document.
+    pub document: String,
+    /// Name of the folder to move the document to.
+    pub folder: String,
+}
+
+/// Row of the link diff, recording a document moved between folders.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MovedLink {
+    /// Id of the document.
+    pub id: i32,
+    /// Name of the document.
+    pub name: String,
+    /// Link to the document, unchanged by the move.
+    pub link: String,
+    /// Name of the folder the document moved from.
+    pub from: String,
+    /// Name of the folder the document moved to.
+    pub to: String,
+    /// Response from the store.
+    pub response: String,
+}
+
+/// Documents to move, and the id of the folder to move each to.
+#[derive(Clone, Debug, Default)]
+pub struct MovePlan {
+    moves: Vec<(Document, i32)>,
+}
+
+impl MovePlan {
+    /// Creates an empty `MovePlan`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a `MovePlan` moving the documents in `docs` that match `filter` to the folder with
+    /// id `folder_id`.  Documents already in the folder are left out.
+    pub fn filter(docs: &Documents, filter: &PurgeFilter, folder_id: i32) -> Self {
+        let mut plan = Self::new();
+        for doc in docs.source().unwrap_or_default() {
+            if filter.matches(&doc) {
+                plan.push(doc, folder_id);
+            }
+        }
+        plan
+    }
+
+    /// Creates a `MovePlan` from the csv file at `path`, with a `document` column holding the id
+    /// or name of each document in `docs` and a `folder` column holding the name of the folder in
+    /// `folders` to move it to.  Documents not found in `docs` are skipped with a warning, and an
+    /// unknown folder name returns [`LinkError::FolderError`].
+    pub fn from_csv<P: AsRef<Path>>(
+        path: P,
+        docs: &Documents,
+        folders: &Folders,
+    ) -> LinkResult<Self> {
+        let docs = docs.source().unwrap_or_default();
+        let mut plan = Self::new();
+        let mut rdr = csv::Reader::from_path(path)?;
+        for row in rdr.deserialize::<MoveMapping>() {
+            let row = row?;
+            let folder_id = folders.get_id(&row.folder).ok_or(LinkError::FolderError {
+                value: row.folder.clone(),
+            })?;
+            let found = docs
+                .iter()
+                .filter(|d| d.id().to_string() == row.document || d.name() == row.document)
+                .cloned()
+                .collect::<Vec<Document>>();
+            if found.is_empty() {
+                warn!("Document {} not found, skipping.", row.document);
+            }
+            for doc in found {
+                plan.push(doc, folder_id);
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Adds a move of `doc` to the folder with id `folder_id`, unless the document is already in
+    /// the folder.
+    pub fn push(&mut self, doc: Document, folder_id: i32) -> &mut Self {
+        if doc.folder_id_ref() == &Some(folder_id) {
+            trace!("Document {} is already in folder {}.", doc.id(), folder_id);
+        } else {
+            self.moves.push((doc, folder_id));
+        }
+        self
+    }
+
+    /// The `moves` field holds the documents to move and the folder id for each.  This function
+    /// returns a reference to the field.
+    pub fn moves_ref(&self) -> &Vec<(Document, i32)> {
+        &self.moves
+    }
+
+    /// Returns the count of documents to move.
+    pub fn count(&self) -> usize {
+        self.moves.len()
+    }
+
+    /// Moves each document in the plan with `store`.  Folder names for the link diff are read
+    /// from `folders`, and links are written with the domain `host` if set, or as relative urls
+    /// otherwise.  Documents changed by someone else since they were read, and moves CivicEngage
+    /// rejects, are skipped with a warning.  Returns a row of the link diff for each document
+    /// moved, leaving out the moves that failed.
+    pub async fn execute(
+        &self,
+        store: &dyn DocumentStore,
+        folders: &Folders,
+        host: Option<&str>,
+    ) -> LinkResult<Vec<MovedLink>> {
+        let style = indicatif::ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {'Moving files.'}",
+        )
+        .unwrap();
+        let bar = ProgressBar::new(self.moves.len() as u64);
+        bar.set_style(style);
+        let folder_name =
+            |id: Option<i32>| id.and_then(|id| folders.get_name(id)).unwrap_or_default();
+        let mut moved = Vec::new();
+        let mut failed = 0;
+        for (doc, folder_id) in &self.moves {
+            bar.inc(1);
+            let response = match store.move_to(doc, *folder_id).await {
+                Ok(response) => response,
+                Err(e @ LinkError::ConflictError { .. }) => {
+                    warn!("{}", e);
+                    failed += 1;
+                    continue;
+                }
+                Err(e) => {
+                    warn!(
+                        "Move of document {} to {} failed: {}",
+                        doc.id(),
+                        folder_name(Some(*folder_id)),
+                        e
+                    );
+                    failed += 1;
+                    continue;
+                }
+            };
+            let url = doc.url().unwrap_or_default();
+            let link = match host {
+                Some(host) => LinkPolicy::new(host)
+                    .canonical(&url, &doc.name())?
+                    .to_string(),
+                None => url,
+            };
+            moved.push(MovedLink {
+                id: doc.id(),
+                name: doc.name(),
+                link,
+                from: folder_name(*doc.folder_id_ref()),
+                to: folder_name(Some(*folder_id)),
+                response,
+            });
+        }
+        if failed > 0 {
+            warn!("Documents not moved: {}", failed);
+        }
+        Ok(moved)
+    }
+}
+
+/// Appends `moved` to the link diff [`MOVED_LINKS`] in the directory `dir`, writing the header
+/// if the file is new.  Returns the path to the link diff.
+pub fn write_diff<P: AsRef<Path>>(dir: P, moved: &[MovedLink]) -> LinkResult<PathBuf> {
+    std::fs::create_dir_all(dir.as_ref())?;
+    let path = dir.as_ref().join(MOVED_LINKS);
+    let exists = path.is_file();
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(!exists)
+        .from_writer(file);
+    for row in moved {
+        wtr.serialize(row)?;
+    }
+    wtr.flush()?;
+    info!("Moved links written to {}.", path.display());
+    Ok(path)
+}
@@ -1,6 +1,12 @@
+use crate::document::Document;
+use crate::prelude::*;
 use crate::{error, utils};
 use serde::{Deserialize, Serialize};
-use std::ops::Div;
+use std::collections::{BTreeMap, HashSet};
+use tracing::warn;
+
+/// Name of the row for the total of every document in a report.
+pub const TOTAL: &str = "Total";
 
 /// Represents total size of dcouments in a Document Center folder.
 #[derive(Clone, Deserialize, Debug, Serialize)]
@@ -10,6 +16,12 @@ pub struct FolderSize {
 }
 
 impl FolderSize {
+    /// The field `folder` holds the name or path of the folder.  This function returns a
+    /// reference to the field.
+    pub fn folder(&self) -> &str {
+        &self.folder
+    }
+
     /// The field `size` represents the total storage in KB.
     pub fn size(&self) -> f64 {
         self.size
@@ -43,6 +55,12 @@ impl FolderSizes {
         &self.records
     }
 
+    /// Adds `record` to the folder sizes.
+    pub fn push(&mut self, record: FolderSize) -> &mut Self {
+        self.records.push(record);
+        self
+    }
+
     /// Returns the total size of all [`FolderSize`] objects.
     pub fn size(&self) -> f64 {
         self.records_ref().iter().fold(0.0, |acc, x| acc + x.size())
@@ -55,28 +73,58 @@ impl From<Vec<FolderSize>> for FolderSizes {
     }
 }
 
-/// Holds row values for the storage report.
-#[derive(Clone, Deserialize, Debug, Serialize)]
+/// Holds row values for the storage report.  The `section` of the row is one of "total",
+/// "folder", "file_type", "status" or "largest".
+#[derive(Clone, Deserialize, Debug, Default, Serialize)]
 pub struct ReportItem {
-    folder: String,
-    size: String,
-    percent: f64,
+    /// Section of the report holding the row.
+    pub section: String,
+    /// Document id, for rows in the "largest" section.
+    pub id: Option<i32>,
+    /// Name of the folder, file type, status or document.
+    pub name: String,
+    /// Count of documents.
+    pub documents: usize,
+    /// Storage in readable units.
+    pub size: String,
+    /// Storage in KB.
+    pub kb: f64,
+    /// Share of the total storage, from 0 to 100.
+    pub percent: f64,
 }
 
 impl ReportItem {
     /// Create new ReportItem representing storage in a folder in the Document Center on
-    /// CivicEngage.
+    /// CivicEngage.  `percent` is the share of `size` in `total_size`, or 0 if `total_size` is
+    /// 0.
     pub fn new(folder: &str, size: f64, total_size: f64) -> error::LinkResult<Self> {
         let sz = byte_unit::Byte::from_unit(size, byte_unit::ByteUnit::KB)?;
         let sz = sz.get_appropriate_unit(false);
-        let flt = size as f64;
-        let pct = flt.div(total_size);
+        let pct = match total_size > 0.0 {
+            true => (size / total_size * 10000.0).round() / 100.0,
+            false => 0.0,
+        };
         Ok(ReportItem {
-            folder: folder.to_owned(),
+            section: "folder".to_owned(),
+            name: folder.to_owned(),
             size: sz.to_string(),
+            kb: size,
             percent: pct,
+            ..Default::default()
         })
     }
+
+    /// Sets the section of the row.
+    pub fn section(mut self, value: &str) -> Self {
+        self.section = value.to_owned();
+        self
+    }
+
+    /// Sets the count of documents in the row.
+    pub fn documents(mut self, value: usize) -> Self {
+        self.documents = value;
+        self
+    }
 }
 
 /// Holds a vector of [`ReportItem`] objects.
@@ -86,6 +134,12 @@ pub struct ReportItems {
 }
 
 impl ReportItems {
+    /// The `records` field holds a vector of [`ReportItem`] objects.  This function returns a
+    /// reference to the field.
+    pub fn records_ref(&self) -> &Vec<ReportItem> {
+        &self.records
+    }
+
     /// Outputs a storage report to csv at path `title`.
     pub fn to_csv<P: AsRef<std::path::Path>>(&mut self, title: P) -> Result<(), std::io::Error> {
         utils::to_csv(&mut self.records, title)?;
@@ -96,21 +150,206 @@ impl ReportItems {
 impl TryFrom<FolderSizes> for ReportItems {
     type Error = error::LinkError;
 
+    /// Creates a row for each folder, with its share of the total of the folder sizes.
     fn try_from(folder_sizes: FolderSizes) -> Result<Self, Self::Error> {
-        let mut sizes = folder_sizes
+        let total = folder_sizes.size();
+        let records = folder_sizes
             .records_ref()
             .iter()
-            .map(|v| v.size())
-            .collect::<Vec<f64>>();
-        sizes.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        let max_size = sizes[sizes.len() - 1];
+            .map(|item| ReportItem::new(&item.folder, item.size(), total))
+            .collect::<error::LinkResult<Vec<ReportItem>>>()?;
+        Ok(ReportItems { records })
+    }
+}
 
-        let mut records = Vec::new();
-        folder_sizes
-            .records_ref()
+impl From<&StorageReport> for FolderSizes {
+    /// Creates a [`FolderSize`] for each folder row of the report, without the total.
+    fn from(report: &StorageReport) -> Self {
+        let records = report
+            .folders
             .iter()
-            .map(|item| records.push(ReportItem::new(&item.folder, item.size(), max_size).unwrap()))
-            .for_each(drop);
-        Ok(ReportItems { records })
+            .map(|item| FolderSize::new(&item.name, item.kb))
+            .collect();
+        FolderSizes { records }
     }
 }
+
+impl From<&StorageReport> for ReportItems {
+    fn from(report: &StorageReport) -> Self {
+        let mut records = vec![report.total.clone()];
+        records.extend(report.folders.iter().cloned());
+        records.extend(report.file_types.iter().cloned());
+        records.extend(report.statuses.iter().cloned());
+        records.extend(report.largest.iter().cloned());
+        ReportItems { records }
+    }
+}
+
+/// Storage report for the documents in a folder tree on the Document Center.
+///
+/// Folder rows count the documents in the folder and the folders below it, so the row for a
+/// parent folder includes its subfolders.  Shares are of the total for the whole report.
+/// Documents without a file size count toward the document counts but not the storage.
+///
+/// ```rust
+/// # use linkbuilder::document::{Document, Folder};
+/// # use linkbuilder::prelude::*;
+/// # use linkbuilder::report::StorageReport;
+/// # fn main() -> LinkResult<()> {
+/// let folders: Vec<Folder> = serde_json::from_value(serde_json::json!([
+///     { "Id": 1, "Name": "GIS" },
+///     { "Id": 2, "Name": "Plats", "ParentId": 1 },
+/// ]))?;
+/// let docs: Vec<Document> = serde_json::from_value(serde_json::json!([
+///     { "Id": 10, "Name": "Map", "FolderId": 1, "FileSize": 300.0, "FileType": "pdf", "Status": 30 },
+///     { "Id": 11, "Name": "Plat 1", "FolderId": 2, "FileSize": 100.0, "FileType": "pdf", "Status": 10 },
+/// ]))?;
+/// let report = StorageReport::new(&Documents::from(docs), &Folders::from(folders), None, 1)?;
+/// assert_eq!(report.total.documents, 2);
+/// assert_eq!(report.folders[0].percent, 100.0);
+/// assert_eq!(report.folders[1].name, "GIS/Plats");
+/// assert_eq!(report.folders[1].percent, 25.0);
+/// assert_eq!(report.largest[0].name, "Map");
+///
+/// let empty = StorageReport::new(&Documents::from(Vec::new()), &Folders::default(), None, 1)?;
+/// assert_eq!(empty.total.percent, 0.0);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Deserialize, Debug, Default, Serialize)]
+pub struct StorageReport {
+    /// Count and storage of every document in the report.
+    pub total: ReportItem,
+    /// Count and storage of each folder and the folders below it, by folder path.
+    pub folders: Vec<ReportItem>,
+    /// Count and storage of each file type, largest first.
+    pub file_types: Vec<ReportItem>,
+    /// Count and storage of each document status, largest first.
+    pub statuses: Vec<ReportItem>,
+    /// Largest documents, largest first.
+    pub largest: Vec<ReportItem>,
+}
+
+impl StorageReport {
+    /// Creates a `StorageReport` for the documents in `docs`, with folder paths from `folders`.
+    /// With `root` set, the report covers the folder with id `root` and the folders below it,
+    /// otherwise every folder, and documents in folders not found are reported under "(no
+    /// folder)".  Lists the `top` largest documents.
+    pub fn new(
+        docs: &Documents,
+        folders: &Folders,
+        root: Option<i32>,
+        top: usize,
+    ) -> error::LinkResult<Self> {
+        let ids = match root {
+            Some(id) => folders.subtree(id),
+            None => folders
+                .source()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|f| *f.id_ref())
+                .collect(),
+        };
+        let known = ids.iter().copied().collect::<HashSet<i32>>();
+        let docs = docs
+            .source()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|d| root.is_none() || d.folder_id_ref().is_some_and(|id| known.contains(&id)))
+            .collect::<Vec<Document>>();
+        let unsized_docs = docs.iter().filter(|d| d.file_size().is_none()).count();
+        if unsized_docs > 0 {
+            warn!("Documents without a file size: {}", unsized_docs);
+        }
+        let total_size = size(&docs);
+        let row = |section: &str, name: &str, docs: &[&Document]| -> error::LinkResult<_> {
+            let kb = docs.iter().filter_map(|d| d.file_size()).sum();
+            Ok(ReportItem::new(name, kb, total_size)?
+                .section(section)
+                .documents(docs.len()))
+        };
+        let all = docs.iter().collect::<Vec<&Document>>();
+
+        let mut paths = ids
+            .iter()
+            .map(|id| (folders.path(*id), *id))
+            .collect::<Vec<(String, i32)>>();
+        paths.sort();
+        let mut folder_rows = Vec::new();
+        for (path, id) in paths {
+            let below = folders.subtree(id);
+            let inside = all
+                .iter()
+                .copied()
+                .filter(|d| d.folder_id_ref().is_some_and(|f| below.contains(&f)))
+                .collect::<Vec<&Document>>();
+            folder_rows.push(row("folder", &path, &inside)?);
+        }
+        let orphans = all
+            .iter()
+            .copied()
+            .filter(|d| d.folder_id_ref().is_none_or(|f| !known.contains(&f)))
+            .collect::<Vec<&Document>>();
+        if !orphans.is_empty() {
+            folder_rows.push(row("folder", "(no folder)", &orphans)?);
+        }
+
+        let mut file_types = BTreeMap::new();
+        let mut statuses = BTreeMap::new();
+        for doc in &all {
+            let file_type = doc
+                .file_type_ref()
+                .clone()
+                .unwrap_or_else(|| "(unknown)".to_owned());
+            file_types
+                .entry(file_type)
+                .or_insert_with(Vec::new)
+                .push(*doc);
+            let status = match doc.status_ref() {
+                Some(10) => "Draft".to_owned(),
+                Some(30) => "Published".to_owned(),
+                Some(code) => format!("Status {}", code),
+                None => "(unknown)".to_owned(),
+            };
+            statuses.entry(status).or_insert_with(Vec::new).push(*doc);
+        }
+        let breakdown = |section: &str, groups: BTreeMap<String, Vec<&Document>>| {
+            let mut rows = groups
+                .iter()
+                .map(|(name, docs)| row(section, name, docs))
+                .collect::<error::LinkResult<Vec<ReportItem>>>()?;
+            rows.sort_by(|a, b| b.kb.total_cmp(&a.kb));
+            Ok::<_, error::LinkError>(rows)
+        };
+
+        let mut largest = all.clone();
+        largest.sort_by(|a, b| {
+            b.file_size()
+                .unwrap_or_default()
+                .total_cmp(&a.file_size().unwrap_or_default())
+                .then(a.id().cmp(&b.id()))
+        });
+        let largest = largest
+            .into_iter()
+            .take(top)
+            .map(|doc| {
+                let mut item = row("largest", &doc.name(), &[doc])?;
+                item.id = Some(doc.id());
+                Ok(item)
+            })
+            .collect::<error::LinkResult<Vec<ReportItem>>>()?;
+
+        Ok(StorageReport {
+            total: row("total", TOTAL, &all)?,
+            folders: folder_rows,
+            file_types: breakdown("file_type", file_types)?,
+            statuses: breakdown("status", statuses)?,
+            largest,
+        })
+    }
+}
+
+/// Returns the total file size of `docs` in KB.
+fn size(docs: &[Document]) -> f64 {
+    docs.iter().filter_map(|d| d.file_size()).sum()
+}
@@ -0,0 +1,292 @@
+//! Archiving documents under retention rules.
+//!
+//! Retention rules are read from a TOML file with one `[[rules]]` table per folder:
+//!
+//! ```toml
+//! [[rules]]
+//! folder = "Agendas"
+//! years_since_upload = 7
+//!
+//! [[rules]]
+//! folder = "Public Notices"
+//! subfolders = true
+//! end_date_passed = true
+//! action = "draft"
+//! ```
+//!
+//! A rule selects the documents in its folder uploaded more than `years_since_upload` years ago,
+//! or with an end date before today when `end_date_passed` is set, and applies its `action`,
+//! "archive" by default or "draft".  With `subfolders` set, the folders below it are included.
+//! A [`RetentionPlan`] lists the documents due under the rules, the first matching rule
+//! deciding the action for each, and leaves out documents already archived or in draft.
+//! Changes are recorded in the journal, so [`crate::journal::Journal::undo`] reverses them, and
+//! archived and draft documents are left out of the link files on the next export.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::prelude::*;
+//! # use linkbuilder::retention::{RetentionPlan, RetentionRules};
+//! # #[tokio::main]
+//! # async fn main() -> LinkResult<()> {
+//! let dir = std::env::temp_dir().join("linkbuilder_retention_example");
+//! # let _ = std::fs::remove_dir_all(&dir);
+//! let store = LocalStore::new(dir.join("store"));
+//! let agendas = store.create_folder("Agendas", None)?;
+//! let file = dir.join("Agenda 2024-01.pdf");
+//! std::fs::write(&file, b"Agenda")?;
+//! store.upload("Agenda 2024-01", &file, agendas, true).await?;
+//!
+//! let rules = RetentionRules::from_toml(
+//!     r#"
+//!     [[rules]]
+//!     folder = "Agendas"
+//!     years_since_upload = 7
+//!     "#,
+//! )?;
+//! let folders = store.folders().await?;
+//! let plan = RetentionPlan::evaluate(&rules, &store, &folders, None).await?;
+//! assert_eq!(plan.count(), 0);
+//! let later = RetentionPlan::evaluate(&rules, &store, &folders, Some("2099-01-01")).await?;
+//! assert_eq!(later.candidates()[0].reason, "Uploaded more than 7 years ago.");
+//! later.execute(&store).await?;
+//! let docs = store.documents(Some(agendas)).await?.source().unwrap_or_default();
+//! assert_eq!(docs[0].is_archived(), &Some(true));
+//! # std::fs::remove_dir_all(&dir)?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::document::Document;
+use crate::output::ActionResult;
+use crate::prelude::*;
+use crate::store::DocumentStore;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use tracing::{trace, warn};
+
+/// Name of the retention rules file.
+pub const RETENTION_FILE: &str = "retention.toml";
+
+/// Change applied to documents due under a retention rule.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionAction {
+    /// Archive the document.
+    #[default]
+    Archive,
+    /// Set the document to "Draft" status.
+    Draft,
+}
+
+impl RetentionAction {
+    /// Returns the update command for the action, as passed to [`DocumentStore::update`] and
+    /// recorded in the journal.
+    pub fn command(&self) -> &'static str {
+        match self {
+            RetentionAction::Archive => "archive",
+            RetentionAction::Draft => "draft",
+        }
+    }
+}
+
+/// Retention rule for the documents in a folder.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RetentionRule {
+    /// Name of the web folder.
+    pub folder: String,
+    /// Whether the rule covers the folders below `folder`.
+    #[serde(default)]
+    pub subfolders: bool,
+    /// Select documents uploaded more than this many years ago.
+    #[serde(default)]
+    pub years_since_upload: Option<u32>,
+    /// Select documents with an end date before today.
+    #[serde(default)]
+    pub end_date_passed: bool,
+    /// Change applied to the documents selected.
+    #[serde(default)]
+    pub action: RetentionAction,
+}
+
+impl RetentionRule {
+    /// Returns the reason `doc` is due under the rule on the date `today`, or `None` if the rule
+    /// does not select it.  Documents without an upload or end date are not selected by the
+    /// matching condition.
+    pub fn reason(&self, doc: &Document, today: chrono::NaiveDate) -> Option<String> {
+        let midnight = format!("{}T00:00:00", today.format("%Y-%m-%d"));
+        if self.end_date_passed {
+            if let Some(end) = doc.end_date_ref().as_ref().filter(|end| **end < midnight) {
+                return Some(format!("End date {} passed.", end));
+            }
+        }
+        if let Some(years) = self.years_since_upload {
+            let cutoff = today
+                .checked_sub_months(chrono::Months::new(years * 12))
+                .map(|d| format!("{}T00:00:00", d.format("%Y-%m-%d")))?;
+            if doc.date().is_some_and(|date| date < cutoff) {
+                return Some(format!("Uploaded more than {} years ago.", years));
+            }
+        }
+        None
+    }
+}
+
+/// Contents of a retention rules file.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RetentionRules {
+    /// Rules in the order they are applied.
+    #[serde(default)]
+    pub rules: Vec<RetentionRule>,
+}
+
+impl RetentionRules {
+    /// Parses `RetentionRules` from the TOML text `value`.  Returns
+    /// [`LinkError::RetentionError`] for a rule that sets no condition.
+    pub fn from_toml(value: &str) -> LinkResult<Self> {
+        let rules: Self = toml::from_str(value)?;
+        for rule in &rules.rules {
+            if rule.years_since_upload.is_none() && !rule.end_date_passed {
+                return Err(LinkError::RetentionError {
+                    value: format!(
+                        "the rule for {} sets neither years_since_upload nor end_date_passed",
+                        rule.folder
+                    ),
+                });
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Reads the retention rules file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> LinkResult<Self> {
+        trace!("Reading retention rules from {}.", path.as_ref().display());
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Document due under a retention rule, listed by [`RetentionPlan::candidates`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RetentionCandidate {
+    /// Id of the document.
+    pub id: i32,
+    /// Name of the document.
+    pub name: String,
+    /// Path of the folder holding the document.
+    pub folder: String,
+    /// Change to apply, "archive" or "draft".
+    pub action: String,
+    /// Condition of the rule the document meets.
+    pub reason: String,
+}
+
+/// Documents due under a set of retention rules, and the change to apply to each.
+#[derive(Clone, Debug, Default)]
+pub struct RetentionPlan {
+    entries: Vec<(Document, RetentionAction, RetentionCandidate)>,
+}
+
+impl RetentionPlan {
+    /// Reads the documents in the folders named by `rules` from `store` and selects those due on
+    /// the date `today`, in the form `YYYY-MM-DD`, or on the current date if `today` is `None`.
+    /// Returns [`LinkError::FolderError`] if a folder is not found in `folders`.
+    pub async fn evaluate(
+        rules: &RetentionRules,
+        store: &dyn DocumentStore,
+        folders: &Folders,
+        today: Option<&str>,
+    ) -> LinkResult<Self> {
+        let today = match today {
+            Some(value) => value.to_owned(),
+            None => crate::store::now()[..10].to_owned(),
+        };
+        let today = chrono::NaiveDate::parse_from_str(&today, "%Y-%m-%d").map_err(|_| {
+            LinkError::RetentionError {
+                value: format!("{} is not a date in the form YYYY-MM-DD", today),
+            }
+        })?;
+        let mut plan = Self::default();
+        let mut seen = HashSet::new();
+        for rule in &rules.rules {
+            let id = folders.get_id(&rule.folder).ok_or(LinkError::FolderError {
+                value: rule.folder.clone(),
+            })?;
+            let ids = match rule.subfolders {
+                true => folders.subtree(id),
+                false => vec![id],
+            };
+            for folder_id in ids {
+                let docs = store.documents(Some(folder_id)).await?;
+                for doc in docs.source().unwrap_or_default() {
+                    if seen.contains(&doc.id()) {
+                        continue;
+                    }
+                    let reason = match rule.reason(&doc, today) {
+                        Some(reason) => reason,
+                        None => continue,
+                    };
+                    seen.insert(doc.id());
+                    if doc.patch(rule.action.command(), &doc)?.is_empty() {
+                        trace!(
+                            "No {} needed for document {}.",
+                            rule.action.command(),
+                            doc.id()
+                        );
+                        continue;
+                    }
+                    let candidate = RetentionCandidate {
+                        id: doc.id(),
+                        name: doc.name(),
+                        folder: folders.path(folder_id),
+                        action: rule.action.command().to_owned(),
+                        reason,
+                    };
+                    plan.entries.push((doc, rule.action, candidate));
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Returns the documents due and the change to apply to each.
+    pub fn candidates(&self) -> Vec<RetentionCandidate> {
+        self.entries.iter().map(|(_, _, c)| c.clone()).collect()
+    }
+
+    /// Returns the count of documents due.
+    pub fn count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Applies the change for each document in the plan with `store`.  Documents changed by
+    /// someone else since they were read, and updates CivicEngage rejects, are skipped with a
+    /// warning.  Returns the response for
+    /// each document.
+    pub async fn execute(&self, store: &dyn DocumentStore) -> LinkResult<Vec<ActionResult>> {
+        let style = indicatif::ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {'Applying retention rules.'}",
+        )
+        .unwrap();
+        let bar = ProgressBar::new(self.entries.len() as u64);
+        bar.set_style(style);
+        let mut results = Vec::new();
+        for (doc, action, _) in &self.entries {
+            bar.inc(1);
+            let response = match store.update(doc, action.command()).await {
+                Err(e @ (LinkError::ConflictError { .. } | LinkError::UpdateRejected { .. })) => {
+                    warn!("{}", e);
+                    e.to_string()
+                }
+                result => result?,
+            };
+            results.push(ActionResult {
+                id: doc.id(),
+                name: doc.name(),
+                action: action.command().to_owned(),
+                response,
+            });
+        }
+        Ok(results)
+    }
+}
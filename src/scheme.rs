@@ -0,0 +1,478 @@
+//! Parses document names into the keys used to join links to GIS features.
+//!
+//! File names on the Document Center encode the key of the GIS feature they describe, such as the
+//! map taxlot number of an unrecorded parcel.  Each document category has a [`NameScheme`] that
+//! extracts and normalizes these keys.  A single document may map to several keys, and names that
+//! do not yield any key are reported as nonconforming.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::scheme::*;
+//! let taxlots = TaxlotScheme::default();
+//! assert_eq!(taxlots.keys("36-05-17-AB-1000"), vec!["360517AB01000"]);
+//! assert_eq!(
+//!     taxlots.keys("360517AB01000 & 1100"),
+//!     vec!["360517AB01000", "360517AB01100"]
+//! );
+//! assert_eq!(taxlots.keys("360517_ab_900"), vec!["360517AB00900"]);
+//! assert!(!taxlots.conforms("Parcel map scan"));
+//!
+//! let agreements = AgreementScheme::default();
+//! assert_eq!(agreements.keys("DDA 19-4"), vec!["2019-004"]);
+//!
+//! let districts = DistrictScheme::default();
+//! assert_eq!(districts.keys("AFD 7 Redwood Ave"), vec!["AFD-007"]);
+//!
+//! let addresses = AddressScheme::default();
+//! assert_eq!(addresses.keys("1234 ne  F St_2021-05-03"), vec!["1234 NE F ST"]);
+//!
+//! let subdivisions = SubdivisionScheme::default();
+//! assert_eq!(
+//!     subdivisions.keys("Redwood Estates Ph 2 Sheet 1 of 3"),
+//!     vec!["REDWOOD ESTATES PHASE 2"]
+//! );
+//! assert_eq!(subdivisions.keys("Redwood Estates Ph 2_2of3"), vec!["REDWOOD ESTATES PHASE 2"]);
+//!
+//! let as_builts = AsBuiltScheme;
+//! assert_eq!(as_builts.keys("2019-04 Redwood Ave Sewer/Sheet 3"), vec!["2019-04 Redwood Ave Sewer"]);
+//! assert!(!as_builts.conforms("Loose drawing"));
+//! ```
+use crate::document::DocumentLinks;
+use crate::utils;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Extracts normalized GIS keys from document names for a category of documents.
+pub trait NameScheme {
+    /// Returns the name of the document category using this scheme.
+    fn category(&self) -> &str;
+
+    /// Returns the normalized keys parsed from the document `name`, in the order found.  Returns
+    /// an empty vector if the name does not conform to the scheme.
+    fn keys(&self, name: &str) -> Vec<String>;
+
+    /// Returns true if at least one key can be parsed from `name`.
+    fn conforms(&self, name: &str) -> bool {
+        !self.keys(name).is_empty()
+    }
+
+    /// Returns true if documents in the category are stored in subfolders of the category
+    /// folder, one subfolder per project.  Names passed to [`NameScheme::keys`] then take the form
+    /// `{subfolder}/{document name}`.
+    fn nested(&self) -> bool {
+        false
+    }
+}
+
+/// Uses the document name as its only key.  Applies to categories without a naming convention.
+#[derive(Clone, Debug)]
+pub struct VerbatimScheme {
+    category: String,
+}
+
+impl VerbatimScheme {
+    /// Creates a new `VerbatimScheme` for the document category `category`.
+    pub fn new(category: &str) -> Self {
+        VerbatimScheme {
+            category: category.to_owned(),
+        }
+    }
+}
+
+impl NameScheme for VerbatimScheme {
+    fn category(&self) -> &str {
+        &self.category
+    }
+
+    fn keys(&self, name: &str) -> Vec<String> {
+        let name = name.trim();
+        if name.is_empty() {
+            Vec::new()
+        } else {
+            vec![name.to_owned()]
+        }
+    }
+}
+
+/// Parses map taxlot numbers from the names of Unrecorded Parcels.  A map taxlot consists of the
+/// township, range and section as two digits each, an optional quarter and quarter-quarter
+/// section letter, and a taxlot number.  Separators between the parts are optional.  Keys are
+/// normalized to upper case with the taxlot zero-padded to five digits, e.g. `360517AB01000`.
+/// Additional taxlot numbers following the first, such as `360517AB01000 & 1100`, are read as
+/// taxlots on the same map.
+#[derive(Clone, Debug)]
+pub struct TaxlotScheme {
+    map: Regex,
+    extra: Regex,
+}
+
+impl Default for TaxlotScheme {
+    fn default() -> Self {
+        let map = Regex::new(
+            r"(?i)\b(\d{2})[-\s]?(\d{2})[-\s]?(\d{2})[-\s]?([A-D]{1,2})?[-\s_]*(\d{3,5})\b",
+        )
+        .unwrap();
+        let extra = Regex::new(r"(?i)^\s*(?:,|&|\+|and)\s*(\d{3,5})\b").unwrap();
+        TaxlotScheme { map, extra }
+    }
+}
+
+impl NameScheme for TaxlotScheme {
+    fn category(&self) -> &str {
+        "Unrecorded Parcels"
+    }
+
+    fn keys(&self, name: &str) -> Vec<String> {
+        let name = &name.replace('_', " ");
+        let mut keys = Vec::new();
+        for caps in self.map.captures_iter(name) {
+            let quarter = caps
+                .get(4)
+                .map(|m| m.as_str().to_uppercase())
+                .unwrap_or_default();
+            let map = format!("{}{}{}{}", &caps[1], &caps[2], &caps[3], quarter);
+            keys.push(format!("{}{:0>5}", map, &caps[5]));
+            let mut rest = &name[caps.get(0).map(|m| m.end()).unwrap_or(name.len())..];
+            while let Some(extra) = self.extra.captures(rest) {
+                keys.push(format!("{}{:0>5}", map, &extra[1]));
+                rest = &rest[extra.get(0).map(|m| m.end()).unwrap_or(rest.len())..];
+            }
+        }
+        unique(keys)
+    }
+}
+
+/// Parses agreement numbers from the names of Deferred Development Agreements.  Agreement
+/// numbers take the form year-number, e.g. `2019-004`, `19-4` or `19_4`.  Keys are normalized to
+/// a four digit year and a three digit number.  Two digit years above 50 are read as 19xx.
+/// Dates such as `2024-01-15` are not read as agreement numbers.
+#[derive(Clone, Debug)]
+pub struct AgreementScheme {
+    pattern: Regex,
+    date: Regex,
+}
+
+impl Default for AgreementScheme {
+    fn default() -> Self {
+        // Word boundaries are checked in `keys`, since `\b` treats "_" as part of a word.
+        let pattern = Regex::new(r"(\d{4}|\d{2})[-_](\d{1,4})").unwrap();
+        let date = Regex::new(r"^[-_/.](\d{1,2})(?:[^0-9A-Za-z]|$)").unwrap();
+        AgreementScheme { pattern, date }
+    }
+}
+
+impl AgreementScheme {
+    /// Returns true if the match from `start` to `end` in `name` stands alone, not joined to
+    /// letters or digits on either side.
+    fn isolated(name: &str, start: usize, end: usize) -> bool {
+        let joined = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric());
+        !joined(name[..start].chars().next_back()) && !joined(name[end..].chars().next())
+    }
+
+    /// Returns true if the year and `number` matched before `rest` are the start of a date, a
+    /// month followed by a day.
+    fn is_date(&self, number: &str, rest: &str) -> bool {
+        let month = number.len() <= 2 && number.parse::<u32>().is_ok_and(|m| (1..=12).contains(&m));
+        month
+            && self
+                .date
+                .captures(rest)
+                .and_then(|caps| caps[1].parse::<u32>().ok())
+                .is_some_and(|d| (1..=31).contains(&d))
+    }
+}
+
+impl NameScheme for AgreementScheme {
+    fn category(&self) -> &str {
+        "Deferred Development Agreements"
+    }
+
+    fn keys(&self, name: &str) -> Vec<String> {
+        let mut keys = Vec::new();
+        for caps in self.pattern.captures_iter(name) {
+            let whole = caps.get(0).unwrap();
+            if !Self::isolated(name, whole.start(), whole.end())
+                || self.is_date(&caps[2], &name[whole.end()..])
+            {
+                continue;
+            }
+            let year = &caps[1];
+            let year = if year.len() == 2 {
+                match year.parse::<i32>() {
+                    Ok(y) if y > 50 => format!("19{}", year),
+                    _ => format!("20{}", year),
+                }
+            } else {
+                year.to_owned()
+            };
+            keys.push(format!("{}-{:0>3}", year, &caps[2]));
+        }
+        unique(keys)
+    }
+}
+
+/// Parses district ids from the names of Advance Finance Districts.  District ids follow the
+/// prefix "AFD" or "District", e.g. `AFD 7` or `District #12`.  Keys are normalized to the form
+/// `AFD-007`.
+#[derive(Clone, Debug)]
+pub struct DistrictScheme {
+    pattern: Regex,
+}
+
+impl Default for DistrictScheme {
+    fn default() -> Self {
+        let pattern = Regex::new(r"(?i)\b(?:AFD|District)[-\s_#]*(\d{1,3})\b").unwrap();
+        DistrictScheme { pattern }
+    }
+}
+
+impl NameScheme for DistrictScheme {
+    fn category(&self) -> &str {
+        "Advance Finance Districts"
+    }
+
+    fn keys(&self, name: &str) -> Vec<String> {
+        let keys = self
+            .pattern
+            .captures_iter(&name.replace('_', " "))
+            .map(|caps| format!("AFD-{:0>3}", &caps[1]))
+            .collect::<Vec<String>>();
+        unique(keys)
+    }
+}
+
+/// Removes sheet designations such as "Sheet 2", "Sht 2 of 5" or "2of5" from the end of a
+/// document name, so that each sheet of a multi-sheet document maps to the same key.
+#[derive(Clone, Debug)]
+pub struct SheetSuffix {
+    pattern: Regex,
+}
+
+impl Default for SheetSuffix {
+    fn default() -> Self {
+        let pattern = Regex::new(
+            r"(?i)(?:[\s_-]*\b(?:sheet|sht|sh|page|pg)[\s_.#-]*\d+(?:\s*of\s*\d+)?|[\s_-]+\d+\s*of\s*\d+)\s*$",
+        )
+        .unwrap();
+        SheetSuffix { pattern }
+    }
+}
+
+impl SheetSuffix {
+    /// Returns `name` with any trailing sheet designation removed.
+    pub fn strip<'a>(&self, name: &'a str) -> &'a str {
+        match self.pattern.find(name) {
+            Some(m) => name[..m.start()].trim_end(),
+            None => name.trim_end(),
+        }
+    }
+}
+
+/// Parses situs addresses from the names of Address Notifications.  Names begin with the house
+/// number, an optional directional prefix and the street name ending in a street type, e.g.
+/// `1234 NE F St`.  Anything after the street type, such as a date, is ignored.  Keys are
+/// normalized to upper case with single spaces, e.g. `1234 NE F ST`.
+#[derive(Clone, Debug)]
+pub struct AddressScheme {
+    pattern: Regex,
+}
+
+impl Default for AddressScheme {
+    fn default() -> Self {
+        let pattern = Regex::new(
+            r"(?i)^\s*(\d{1,6}[A-Z]?)\s+((?:[NSEW]{1,2}\s+)?[A-Z0-9 .]+?)\s+(ST|AVE|RD|DR|LN|CT|PL|WAY|BLVD|CIR|TER|HWY|LOOP|PKWY)\b",
+        )
+        .unwrap();
+        AddressScheme { pattern }
+    }
+}
+
+impl NameScheme for AddressScheme {
+    fn category(&self) -> &str {
+        "Address Notifications"
+    }
+
+    fn keys(&self, name: &str) -> Vec<String> {
+        match self.pattern.captures(&name.replace('_', " ")) {
+            Some(caps) => {
+                let street = caps[2].split_whitespace().collect::<Vec<&str>>().join(" ");
+                vec![format!("{} {} {}", &caps[1], street, &caps[3]).to_uppercase()]
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Parses subdivision names from the scanned plats of Subdivisions.  Plats of more than one
+/// sheet share a key, with sheet designations removed by [`SheetSuffix`].  Keys are normalized to
+/// upper case with single spaces, and "Ph" is expanded to "PHASE", e.g. `Redwood Estates Ph 2
+/// Sheet 1 of 3` becomes `REDWOOD ESTATES PHASE 2`.
+#[derive(Clone, Debug, Default)]
+pub struct SubdivisionScheme {
+    sheet: SheetSuffix,
+}
+
+impl NameScheme for SubdivisionScheme {
+    fn category(&self) -> &str {
+        "Subdivisions"
+    }
+
+    fn keys(&self, name: &str) -> Vec<String> {
+        let name = name.replace('_', " ");
+        let key = self
+            .sheet
+            .strip(&name)
+            .split_whitespace()
+            .map(|word| match word.to_uppercase().as_str() {
+                "PH" | "PH." => "PHASE".to_owned(),
+                other => other.to_owned(),
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        if key.is_empty() {
+            Vec::new()
+        } else {
+            vec![key]
+        }
+    }
+}
+
+/// Reads the project from the nested folder layout of As-Builts.  Each project has a subfolder
+/// in the As-Builts folder holding the sheets of its as-built drawings, and the key of each
+/// document is the name of its project folder with whitespace collapsed.  Documents stored
+/// directly in the As-Builts folder do not conform.
+#[derive(Clone, Debug, Default)]
+pub struct AsBuiltScheme;
+
+impl NameScheme for AsBuiltScheme {
+    fn category(&self) -> &str {
+        "As-Builts"
+    }
+
+    fn keys(&self, name: &str) -> Vec<String> {
+        match name.split_once('/') {
+            Some((project, _)) => {
+                let key = project.split_whitespace().collect::<Vec<&str>>().join(" ");
+                if key.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![key]
+                }
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn nested(&self) -> bool {
+        true
+    }
+}
+
+/// Removes repeated keys, keeping the first occurrence of each.
+fn unique(keys: Vec<String>) -> Vec<String> {
+    let mut result = Vec::new();
+    for key in keys {
+        if !result.contains(&key) {
+            result.push(key);
+        }
+    }
+    result
+}
+
+/// Returns the [`NameScheme`] for documents in the Document Center folder named `folder`.
+/// Folders without a naming convention use the [`VerbatimScheme`].
+pub fn for_folder(folder: &str) -> Box<dyn NameScheme> {
+    match folder {
+        "Unrecorded Parcels" => Box::<TaxlotScheme>::default(),
+        "Deferred Development Agreements" => Box::<AgreementScheme>::default(),
+        "Advance Finance Districts" => Box::<DistrictScheme>::default(),
+        "Address Notifications" => Box::<AddressScheme>::default(),
+        "Subdivisions" => Box::<SubdivisionScheme>::default(),
+        "As-Builts" => Box::<AsBuiltScheme>::default(),
+        _ => Box::new(VerbatimScheme::new(folder)),
+    }
+}
+
+/// Document whose name does not conform to the [`NameScheme`] of its category.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NameIssue {
+    category: String,
+    name: String,
+    web_link: String,
+}
+
+impl NameIssue {
+    /// The `name` field holds the nonconforming document name.  This function returns a
+    /// reference to the field.
+    pub fn name_ref(&self) -> &String {
+        &self.name
+    }
+}
+
+/// Holds a vector of [`NameIssue`] objects.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct NameIssues {
+    records: Vec<NameIssue>,
+}
+
+impl NameIssues {
+    /// Collects the documents in `links` with names that do not conform to `scheme`, sorted by
+    /// name.
+    pub fn from_links(links: &DocumentLinks, scheme: &dyn NameScheme) -> Self {
+        let mut records = links
+            .ref_links()
+            .iter()
+            .filter(|(name, _)| !scheme.conforms(name))
+            .flat_map(|(name, items)| {
+                items.iter().map(|link| NameIssue {
+                    category: scheme.category().to_owned(),
+                    name: name.clone(),
+                    web_link: link.url_ref().clone(),
+                })
+            })
+            .collect::<Vec<NameIssue>>();
+        records.sort_by(|a, b| a.name.cmp(&b.name).then(a.web_link.cmp(&b.web_link)));
+        NameIssues { records }
+    }
+
+    /// The `records` field holds a vector of [`NameIssue`] objects.  This function returns a
+    /// reference to the field.
+    pub fn records_ref(&self) -> &Vec<NameIssue> {
+        &self.records
+    }
+
+    /// Outputs the nonconforming names to csv at path `title`.
+    pub fn to_csv<P: AsRef<std::path::Path>>(&mut self, title: P) -> Result<(), std::io::Error> {
+        utils::to_csv(&mut self.records, title)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agreement_numbers_may_be_joined_by_underscores() {
+        let agreements = AgreementScheme::default();
+        assert_eq!(agreements.keys("DDA_19_4"), vec!["2019-004"]);
+        assert_eq!(agreements.keys("DDA_2019_004_Redwood"), vec!["2019-004"]);
+        assert_eq!(
+            agreements.keys("DDA 19-4 & 19_5"),
+            vec!["2019-004", "2019-005"]
+        );
+        assert!(agreements.keys("DDA19-4").is_empty());
+        assert!(agreements.keys("DDA 2019-00412").is_empty());
+    }
+
+    #[test]
+    fn dates_are_not_agreement_numbers() {
+        let agreements = AgreementScheme::default();
+        assert!(agreements.keys("2024-01-15").is_empty());
+        assert!(agreements.keys("Minutes_2024_01_15").is_empty());
+        assert!(agreements.keys("Scan 24-01-15.pdf").is_empty());
+        assert_eq!(agreements.keys("DDA 2019-004 2024-01-15"), vec!["2019-004"]);
+        assert_eq!(agreements.keys("DDA 2019-004-2"), vec!["2019-004"]);
+    }
+}
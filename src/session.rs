@@ -0,0 +1,192 @@
+//! Caches the CivicEngage session key between runs.
+//!
+//! Logging in issues a session key held in the [`AuthorizedUser`].  A [`SessionCache`] saves
+//! the authorized user to a file in the user cache directory, readable only by the user, with
+//! the time the session was issued.  Later runs reuse the cached session until it is older than
+//! the time to live, or until CivicEngage rejects it.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::prelude::{AuthorizedUser, LinkResult, Settings};
+//! # use linkbuilder::session::SessionCache;
+//! # fn main() -> LinkResult<()> {
+//! let path = std::env::temp_dir().join("linkbuilder_session_example");
+//! let mut settings = Settings::default();
+//! settings.host = Some("grantspassoregon.gov".to_owned());
+//! let cache = SessionCache::new(&path, 60);
+//! cache.store(&settings, &AuthorizedUser::default())?;
+//! assert!(cache.load(&settings)?.is_some());
+//!
+//! // Sessions for a different partition are not reused.
+//! settings.partition = Some("5678".to_owned());
+//! assert!(cache.load(&settings)?.is_none());
+//! assert!(cache.clear()?);
+//! # Ok(())
+//! # }
+//! ```
+use crate::journal::timestamp;
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{info, trace};
+
+/// Session time to live in minutes used when the `session_minutes` setting is empty.
+pub const SESSION_MINUTES: u64 = 30;
+
+/// A cached session.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Session {
+    profile: String,
+    host: Option<String>,
+    partition: Option<String>,
+    issued: u64,
+    user: AuthorizedUser,
+}
+
+impl Session {
+    /// The `profile` field holds the name of the profile that logged in.  This function returns
+    /// a reference to the field.
+    pub fn profile_ref(&self) -> &String {
+        &self.profile
+    }
+
+    /// The `issued` field holds the time the session was issued in seconds since the Unix epoch.
+    /// This function returns the value of the field.
+    pub fn issued(&self) -> u64 {
+        self.issued
+    }
+
+    /// The `user` field holds the [`AuthorizedUser`] for the session.  This function returns a
+    /// reference to the field.
+    pub fn user_ref(&self) -> &AuthorizedUser {
+        &self.user
+    }
+
+    /// Returns true if the session was issued for the host and partition in `settings`.
+    pub fn matches(&self, settings: &Settings) -> bool {
+        self.host == settings.host && self.partition == settings.partition
+    }
+}
+
+/// Saves and loads a [`Session`] from a file.
+#[derive(Clone, Debug)]
+pub struct SessionCache {
+    path: PathBuf,
+    ttl: u64,
+}
+
+impl SessionCache {
+    /// Creates a new `SessionCache` at `path`, reusing sessions for up to `ttl` seconds.
+    pub fn new<P: AsRef<Path>>(path: P, ttl: u64) -> Self {
+        SessionCache {
+            path: path.as_ref().to_path_buf(),
+            ttl,
+        }
+    }
+
+    /// Creates a `SessionCache` for the profile in `settings` at the default path, using the
+    /// `session_minutes` setting for the time to live.
+    pub fn from_settings(settings: &Settings) -> LinkResult<Self> {
+        let minutes = settings.session_minutes.unwrap_or(SESSION_MINUTES);
+        Ok(Self::new(
+            Self::default_path(&settings.profile)?,
+            minutes * 60,
+        ))
+    }
+
+    /// Returns the default path of the session file for `profile`, in the user cache directory.
+    pub fn default_path(profile: &str) -> LinkResult<PathBuf> {
+        let dir = dirs::cache_dir().ok_or(LinkError::CredentialError {
+            value: "No user cache directory found.".to_owned(),
+        })?;
+        Ok(dir.join("linkbuilder").join(format!("{}.session", profile)))
+    }
+
+    /// The `path` field holds the path to the session file.  This function returns a reference
+    /// to the field.
+    pub fn path_ref(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// The `ttl` field holds the time to live of a session in seconds.  This function returns
+    /// the value of the field.
+    pub fn ttl(&self) -> u64 {
+        self.ttl
+    }
+
+    /// Returns the cached session, whether or not it has expired.
+    pub fn read(&self) -> LinkResult<Option<Session>> {
+        if !self.path.is_file() {
+            return Ok(None);
+        }
+        let session = serde_json::from_str(&std::fs::read_to_string(&self.path)?)?;
+        Ok(Some(session))
+    }
+
+    /// Returns the cached session if it was issued for `settings` and has not expired.
+    pub fn load(&self, settings: &Settings) -> LinkResult<Option<Session>> {
+        match self.read()? {
+            Some(session) if !session.matches(settings) => {
+                trace!("Cached session is for a different host or partition.");
+                Ok(None)
+            }
+            Some(session) if timestamp() >= session.issued + self.ttl => {
+                trace!("Cached session expired.");
+                Ok(None)
+            }
+            session => Ok(session),
+        }
+    }
+
+    /// Saves the session for `user`, logged in with `settings`, stamped with the current time.
+    pub fn store(&self, settings: &Settings, user: &AuthorizedUser) -> LinkResult<Session> {
+        let session = Session {
+            profile: settings.profile.clone(),
+            host: settings.host.clone(),
+            partition: settings.partition.clone(),
+            issued: timestamp(),
+            user: user.clone(),
+        };
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string(&session)?)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        trace!("Session cached at {}.", self.path.display());
+        Ok(session)
+    }
+
+    /// Removes the cached session.  Returns true if a session was removed.
+    pub fn clear(&self) -> LinkResult<bool> {
+        if self.path.is_file() {
+            std::fs::remove_file(&self.path)?;
+            info!("Cached session removed.");
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Returns the cached user for `settings`, or logs in and caches the new session.
+    pub async fn user(&self, settings: &Settings) -> LinkResult<AuthorizedUser> {
+        match self.load(settings)? {
+            Some(session) => {
+                info!("Reusing cached session for profile {}.", session.profile);
+                Ok(session.user)
+            }
+            None => self.refresh(settings).await,
+        }
+    }
+
+    /// Logs in with `settings` and caches the new session, replacing any cached before.
+    pub async fn refresh(&self, settings: &Settings) -> LinkResult<AuthorizedUser> {
+        let user = authorize_user(settings).await?;
+        self.store(settings, &user)?;
+        Ok(user)
+    }
+}
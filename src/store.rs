@@ -0,0 +1,730 @@
+//! Backends holding the folders and documents of a Document Center.
+//!
+//! The [`DocumentStore`] trait covers the calls the program makes against the Document Center:
+//! listing folders and documents, and uploading, updating, deleting and downloading documents.
+//! [`CivicEngageStore`] makes these calls against the CivicEngage API.  [`LocalStore`] keeps the
+//! folders and documents in a local directory, assigning ids, urls, statuses and dates the way
+//! CivicEngage does, so that content can be staged and commands tried without a connection.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use linkbuilder::store::{DocumentStore, LocalStore};
+//! # #[tokio::main]
+//! # async fn main() -> linkbuilder::prelude::LinkResult<()> {
+//! let root = std::env::temp_dir().join("linkbuilder_store_example");
+//! # let _ = std::fs::remove_dir_all(&root);
+//! let source = root.join("Plat 12.pdf");
+//! std::fs::create_dir_all(&root)?;
+//! std::fs::write(&source, b"%PDF-1.4")?;
+//!
+//! let store = LocalStore::new(&root);
+//! let plats = store.create_folder("Plats", None)?;
+//! store.upload("Plat 12", &source, plats, true).await?;
+//! let docs = store.documents(Some(plats)).await?;
+//! let doc = &docs.source().unwrap_or_default()[0];
+//! assert_eq!(
+//!     doc.url().as_deref(),
+//!     Some(format!("/DocumentCenter/View/{}/Plat-12", doc.id()).as_str())
+//! );
+//!
+//! // Published documents must be set to draft before they can be deleted.
+//! store.update(doc, "draft").await?;
+//! let doc = &store.documents(Some(plats)).await?.source().unwrap_or_default()[0];
+//! assert_eq!(store.download(doc).await?.data, b"%PDF-1.4");
+//! store.delete(doc).await?;
+//! assert_eq!(store.documents(None).await?.total_count(), Some(0));
+//! # std::fs::remove_dir_all(&root)?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::document::Document;
+use crate::journal::JournalEntry;
+use crate::prelude::*;
+use async_trait::async_trait;
+use indicatif::ProgressBar;
+use reqwest::header::CONTENT_DISPOSITION;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, trace, warn};
+
+/// A file downloaded from a [`DocumentStore`].
+#[derive(Clone, Debug, Default)]
+pub struct Download {
+    /// Name of the file, if the store reports one.
+    pub file_name: Option<String>,
+    /// Contents of the file.
+    pub data: Vec<u8>,
+}
+
+/// Calls made against the folders and documents of a Document Center.
+#[async_trait]
+pub trait DocumentStore: std::fmt::Debug + Send + Sync {
+    /// Returns every folder in the store.
+    async fn folders(&self) -> LinkResult<Folders>;
+
+    /// Returns the documents in the folder with id `folder_id`, or every document in the store
+    /// if `folder_id` is `None`.
+    async fn documents(&self, folder_id: Option<i32>) -> LinkResult<Documents>;
+
+    /// Uploads the file at `path` as a document named `name` to the folder with id `folder_id`,
+    /// with status "Published" if `publish` is true, or "Draft" otherwise.  Returns the response
+    /// from the store.
+    async fn upload(
+        &self,
+        name: &str,
+        path: &Path,
+        folder_id: i32,
+        publish: bool,
+    ) -> LinkResult<String>;
+
+    /// Updates `doc` with `command`, one of "draft" or "archive", changing only the fields the
+    /// command sets.  The command "restore" sets the fields changed since back to the values in
+    /// `doc`.  Fails with [`LinkError::ConflictError`] if the document changed after `doc` was
+    /// read.  See [`Document::patch()`].  Returns the response from the store.
+    async fn update(&self, doc: &Document, command: &str) -> LinkResult<String>;
+
+    /// Changes the fields of `doc` set in `patch`, recording the change in the journal as
+    /// `action`.  Fails with [`LinkError::ConflictError`] if the document changed after `doc` was
+    /// read, unless the patch is unchecked.  Returns the response from the store.
+    async fn patch(
+        &self,
+        doc: &Document,
+        action: &str,
+        patch: &DocumentPatch,
+    ) -> LinkResult<String>;
+
+    /// Moves `doc` to the folder with id `folder_id`, keeping its id and url.  Fails with
+    /// [`LinkError::ConflictError`] if the document changed after `doc` was read.  Returns the
+    /// response from the store.
+    async fn move_to(&self, doc: &Document, folder_id: i32) -> LinkResult<String>;
+
+    /// Deletes `doc`.  Documents with status "Published" must be set to "Draft" first.  Returns
+    /// the response from the store.
+    async fn delete(&self, doc: &Document) -> LinkResult<String>;
+
+    /// Downloads the file for `doc`.
+    async fn download(&self, doc: &Document) -> LinkResult<Download>;
+
+    /// Returns a copy of the store recording changes in the [`Journal`] `journal`.
+    fn journaled(&self, journal: &Journal) -> Arc<dyn DocumentStore>;
+
+    /// Returns the id of the user making changes, recorded in the journal.
+    fn user_id(&self) -> i32 {
+        0
+    }
+}
+
+/// Sets the status of each document in `docs` with `command`.  Calls [`DocumentStore::update()`].
+/// Returns the responses from the store.
+pub async fn update_documents(
+    store: &dyn DocumentStore,
+    docs: &Documents,
+    command: &str,
+) -> LinkResult<Vec<String>> {
+    let mut res = Vec::new();
+    if let Some(docs) = docs.source_ref() {
+        let style = indicatif::ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {'Updating files.'}",
+        )
+        .unwrap();
+        let bar = ProgressBar::new(docs.len() as u64);
+        bar.set_style(style);
+        for doc in docs {
+            // A document edited by someone else since it was read, or one CivicEngage refuses to
+            // update, is left alone.
+            match store.update(doc, command).await {
+                Err(e @ (LinkError::ConflictError { .. } | LinkError::UpdateRejected { .. })) => {
+                    warn!("{}", e);
+                    res.push(e.to_string());
+                }
+                result => res.push(result?),
+            }
+            bar.inc(1);
+        }
+    }
+    Ok(res)
+}
+
+/// Deletes each document in `docs`.  Calls [`DocumentStore::delete()`].  Returns the responses
+/// from the store.
+pub async fn delete_documents(
+    store: &dyn DocumentStore,
+    docs: &Documents,
+) -> LinkResult<Vec<String>> {
+    let mut res = Vec::new();
+    if let Some(docs) = docs.source_ref() {
+        let style = indicatif::ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {'Deleting files.'}",
+        )
+        .unwrap();
+        let bar = ProgressBar::new(docs.len() as u64);
+        bar.set_style(style);
+        for doc in docs {
+            res.push(store.delete(doc).await?);
+            bar.inc(1);
+        }
+    }
+    Ok(res)
+}
+
+/// Document Center on CivicEngage.  Changes are recorded in the attached [`Journal`], if any.
+#[derive(Clone, Debug)]
+pub struct CivicEngageStore {
+    folder_url: Option<String>,
+    document_url: String,
+    headers: DocumentHeaders,
+    query: DocQuery,
+    user: AuthorizedUser,
+    journal: Option<Journal>,
+}
+
+impl CivicEngageStore {
+    /// Creates a new `CivicEngageStore` for the Document endpoint `document_url`, making calls
+    /// as `user`.  Queries return all matches on the server.
+    pub fn new(document_url: &str, user: &AuthorizedUser) -> Self {
+        let mut query = DocQuery::new();
+        query.inlinecount("allpages");
+        CivicEngageStore {
+            folder_url: None,
+            document_url: document_url.to_owned(),
+            headers: DocumentHeaders::default(),
+            query,
+            user: user.clone(),
+            journal: None,
+        }
+    }
+
+    /// Creates a new `CivicEngageStore` using the folder and document endpoints in `settings`.
+    pub fn from_settings(settings: &Settings, user: &AuthorizedUser) -> LinkResult<Self> {
+        let mut store = Self::new(&settings.document_url()?, user);
+        store.folder_url(&settings.folder_url()?);
+        Ok(store)
+    }
+
+    /// The `folder_url()` function sets the value of the `folder_url` field to `value`.  Required
+    /// to list folders.
+    pub fn folder_url(&mut self, value: &str) -> &mut Self {
+        self.folder_url = Some(value.to_owned());
+        self
+    }
+
+    /// The `headers()` function sets the value of the `headers` field to `value`.
+    pub fn headers(&mut self, value: &DocumentHeaders) -> &mut Self {
+        self.headers = value.clone();
+        self
+    }
+
+    /// The `query()` function sets the value of the `query` field to `value`.  Used as the base
+    /// of folder and document queries.
+    pub fn query(&mut self, value: &DocQuery) -> &mut Self {
+        self.query = value.clone();
+        self
+    }
+
+    /// The `user()` function sets the value of the `user` field to `value`.
+    pub fn user(&mut self, value: &AuthorizedUser) -> &mut Self {
+        self.user = value.clone();
+        self
+    }
+
+    /// Records changes made through the store in the [`Journal`] `value`.
+    pub fn journal(&mut self, value: &Journal) -> &mut Self {
+        self.journal = Some(value.clone());
+        self
+    }
+
+    /// The `user` field holds the [`AuthorizedUser`] making calls.  This function returns a
+    /// reference to the field.
+    pub fn user_ref(&self) -> &AuthorizedUser {
+        &self.user
+    }
+
+    /// Query info for the documents in the folder with id `folder_id`, or every document if
+    /// `folder_id` is `None`.
+    fn info(&self, folder_id: Option<i32>) -> DocInfo {
+        self.page(&self.document_url, folder_id, 0)
+    }
+
+    /// Query info for the page of results from `url` starting after the first `skip`, limited to
+    /// the folder with id `folder_id` if set.
+    fn page(&self, url: &str, folder_id: Option<i32>, skip: usize) -> DocInfo {
+        let mut query = self.query.clone();
+        if let Some(id) = folder_id {
+            query.filter(&format!("FolderId eq {}", id));
+        }
+        if skip > 0 {
+            query.skip(skip as i32);
+        }
+        let mut info = DocInfo::new(&self.headers, &query, url);
+        if let Some(journal) = &self.journal {
+            info.journal(journal);
+        }
+        info
+    }
+}
+
+#[async_trait]
+impl DocumentStore for CivicEngageStore {
+    async fn folders(&self) -> LinkResult<Folders> {
+        match &self.folder_url {
+            Some(url) => {
+                let mut folders = Vec::new();
+                loop {
+                    let page =
+                        Folders::query(&self.page(url, None, folders.len()), &self.user).await?;
+                    let source = page.source().unwrap_or_default();
+                    let done = source.is_empty();
+                    folders.extend(source);
+                    if done || !more(page.total_count(), folders.len()) {
+                        return Ok(Folders::from(folders));
+                    }
+                    trace!("Read {} folders, reading the next page.", folders.len());
+                }
+            }
+            None => {
+                warn!("Missing folder endpoint.");
+                Err(LinkError::BuildError)
+            }
+        }
+    }
+
+    async fn documents(&self, folder_id: Option<i32>) -> LinkResult<Documents> {
+        let mut docs = Vec::new();
+        loop {
+            let info = self.page(&self.document_url, folder_id, docs.len());
+            let page = Documents::query(&info, &self.user).await?;
+            let source = page.source().unwrap_or_default();
+            let done = source.is_empty();
+            docs.extend(source);
+            if done || !more(page.total_count(), docs.len()) {
+                return Ok(Documents::from(docs));
+            }
+            trace!("Read {} documents, reading the next page.", docs.len());
+        }
+    }
+
+    async fn upload(
+        &self,
+        name: &str,
+        path: &Path,
+        folder_id: i32,
+        publish: bool,
+    ) -> LinkResult<String> {
+        let doc: Document = serde_json::from_value(json!({
+            "Id": 0,
+            "Name": name,
+            "FolderId": folder_id,
+        }))?;
+        doc.upload(path.to_path_buf(), &self.info(None), &self.user, publish)
+            .await
+    }
+
+    async fn update(&self, doc: &Document, command: &str) -> LinkResult<String> {
+        doc.update(&self.info(None), &self.user, command).await
+    }
+
+    async fn patch(
+        &self,
+        doc: &Document,
+        action: &str,
+        patch: &DocumentPatch,
+    ) -> LinkResult<String> {
+        doc.edit(&self.info(None), &self.user, action, patch).await
+    }
+
+    async fn move_to(&self, doc: &Document, folder_id: i32) -> LinkResult<String> {
+        doc.move_to(&self.info(None), &self.user, folder_id).await
+    }
+
+    async fn delete(&self, doc: &Document) -> LinkResult<String> {
+        doc.delete(&self.info(None), &self.user).await
+    }
+
+    async fn download(&self, doc: &Document) -> LinkResult<Download> {
+        // Relative document urls resolve against the site of the document endpoint.
+        let link = match doc.url_ref() {
+            Some(url) => url::Url::parse(&self.document_url)?.join(url)?,
+            None => {
+                return Err(LinkError::BackupError {
+                    id: doc.id(),
+                    status: 404,
+                })
+            }
+        };
+        trace!("Downloading {}.", link);
+        let res = reqwest::Client::new()
+            .get(link.as_str())
+            .header(self.headers.clone().api_key(), self.user.api_key())
+            .header(self.headers.clone().partition(), self.user.partition())
+            .header(
+                self.headers.clone().user_api_key(),
+                self.user.user_api_key(),
+            )
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            return Err(LinkError::BackupError {
+                id: doc.id(),
+                status: res.status().as_u16(),
+            });
+        }
+        let file_name = res
+            .headers()
+            .get(CONTENT_DISPOSITION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(attachment_name);
+        let data = res.bytes().await?.to_vec();
+        Ok(Download { file_name, data })
+    }
+
+    fn journaled(&self, journal: &Journal) -> Arc<dyn DocumentStore> {
+        let mut store = self.clone();
+        store.journal(journal);
+        Arc::new(store)
+    }
+
+    fn user_id(&self) -> i32 {
+        self.user.user_id()
+    }
+}
+
+/// Returns true if fewer than the `total` results reported by CivicEngage have been read in
+/// `count`.  Without a total, the first page is taken as every result.
+fn more(total: Option<i32>, count: usize) -> bool {
+    total.is_some_and(|total| count < total.max(0) as usize)
+}
+
+/// Reads the file name out of a `Content-Disposition` header value, stripping any directories.
+fn attachment_name(value: &str) -> Option<String> {
+    value
+        .split(';')
+        .map(|p| p.trim())
+        .find_map(|p| p.strip_prefix("filename="))
+        .map(|n| n.trim_matches('"'))
+        .and_then(|n| n.rsplit(['/', '\\']).next())
+        .filter(|n| !n.is_empty())
+        .map(|n| n.to_owned())
+}
+
+/// Name of the file holding the folder and document records of a [`LocalStore`].
+pub const LOCAL_INDEX: &str = "index.json";
+
+/// Folder and document records of a [`LocalStore`].
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct LocalIndex {
+    next_id: i32,
+    folders: Vec<Folder>,
+    documents: Vec<Document>,
+}
+
+impl LocalIndex {
+    /// Returns a new id, unique across folders and documents.
+    fn next_id(&mut self) -> i32 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/// Document Center kept in a local directory.  Folder and document records are saved in
+/// `index.json` at the root of the directory, and uploaded files in the `files` subdirectory.
+/// Changes are recorded in the attached [`Journal`], if any.
+#[derive(Clone, Debug)]
+pub struct LocalStore {
+    root: PathBuf,
+    journal: Option<Journal>,
+}
+
+impl LocalStore {
+    /// Creates a new `LocalStore` in the directory `root`.  The directory is created on the first
+    /// change.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        LocalStore {
+            root: root.as_ref().to_path_buf(),
+            journal: None,
+        }
+    }
+
+    /// Records changes made through the store in the [`Journal`] `value`.
+    pub fn journal(&mut self, value: &Journal) -> &mut Self {
+        self.journal = Some(value.clone());
+        self
+    }
+
+    /// The `root` field holds the path to the store directory.  This function returns a
+    /// reference to the field.
+    pub fn root_ref(&self) -> &PathBuf {
+        &self.root
+    }
+
+    /// Creates a folder named `name` under the folder with id `parent_id`, or at the root if
+    /// `parent_id` is `None`.  Returns the id of the folder, or of the existing folder with the
+    /// same name and parent.
+    pub fn create_folder(&self, name: &str, parent_id: Option<i32>) -> LinkResult<i32> {
+        let mut index = self.read()?;
+        if let Some(id) = index
+            .folders
+            .iter()
+            .find(|f| f.name_ref() == name && f.parent_id_ref() == &parent_id)
+            .and_then(|f| *f.id_ref())
+        {
+            return Ok(id);
+        }
+        let path = match parent_id {
+            Some(parent) => {
+                let parent = index
+                    .folders
+                    .iter()
+                    .find(|f| f.id_ref() == &Some(parent))
+                    .ok_or(LinkError::FolderError {
+                        value: parent.to_string(),
+                    })?;
+                format!("{}/{}", parent.path_ref().clone().unwrap_or_default(), name)
+            }
+            None => format!("/{}", name),
+        };
+        let id = index.next_id();
+        let folder: Folder = serde_json::from_value(json!({
+            "Id": id,
+            "Name": name,
+            "ParentId": parent_id,
+            "Path": path,
+            "Status": 30,
+            "CreatedDate": now(),
+            "IsArchived": false,
+        }))?;
+        index.folders.push(folder);
+        self.write(&index)?;
+        info!("Folder {} created with id {}.", name, id);
+        Ok(id)
+    }
+
+    /// Reads the index, or returns an empty index if the store has no index yet.
+    fn read(&self) -> LinkResult<LocalIndex> {
+        let path = self.root.join(LOCAL_INDEX);
+        if !path.is_file() {
+            return Ok(LocalIndex::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Saves `index`, creating the store directory if it does not exist.
+    fn write(&self, index: &LocalIndex) -> LinkResult<()> {
+        std::fs::create_dir_all(&self.root)?;
+        std::fs::write(
+            self.root.join(LOCAL_INDEX),
+            serde_json::to_string_pretty(index)?,
+        )?;
+        Ok(())
+    }
+
+    /// Returns the path of the file for the document with id `id`.
+    fn file_path(&self, id: i32) -> PathBuf {
+        self.root.join("files").join(id.to_string())
+    }
+
+    /// Records `entry` in the attached journal, if any.
+    fn record(&self, entry: JournalEntry) -> LinkResult<()> {
+        if let Some(journal) = &self.journal {
+            let mut entry = entry;
+            entry.run_id.clone_from(journal.run_id_ref());
+            journal.record(&entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the current time in the format of dates on CivicEngage.
+pub(crate) fn now() -> String {
+    let stamp = crate::journal::timestamp() as i64;
+    chrono::DateTime::from_timestamp(stamp, 0)
+        .unwrap_or_default()
+        .format("%Y-%m-%dT%H:%M:%S")
+        .to_string()
+}
+
+#[async_trait]
+impl DocumentStore for LocalStore {
+    async fn folders(&self) -> LinkResult<Folders> {
+        Ok(Folders::from(self.read()?.folders))
+    }
+
+    async fn documents(&self, folder_id: Option<i32>) -> LinkResult<Documents> {
+        let docs = self
+            .read()?
+            .documents
+            .into_iter()
+            .filter(|d| folder_id.is_none() || d.folder_id_ref() == &folder_id)
+            .collect::<Vec<Document>>();
+        Ok(Documents::from(docs))
+    }
+
+    async fn upload(
+        &self,
+        name: &str,
+        path: &Path,
+        folder_id: i32,
+        publish: bool,
+    ) -> LinkResult<String> {
+        let mut index = self.read()?;
+        if !index.folders.iter().any(|f| f.id_ref() == &Some(folder_id)) {
+            return Err(LinkError::FolderError {
+                value: folder_id.to_string(),
+            });
+        }
+        let data = std::fs::read(path)?;
+        let id = index.next_id();
+        let date = now();
+        let doc: Document = serde_json::from_value(json!({
+            "Id": id,
+            "Name": name,
+            "Status": if publish { 30 } else { 10 },
+            "FileSize": data.len() as f64 / 1024.,
+            "CreatedDate": date,
+            "FileUploadedDate": date,
+            "IsVisible": false,
+            "FileType": path.extension().and_then(|e| e.to_str()),
+            "Url": format!("/DocumentCenter/View/{}/{}", id, crate::export::slug(name)),
+            "FolderId": folder_id,
+            "FileName": path.file_name().and_then(|n| n.to_str()),
+            "IsArchived": false,
+        }))?;
+        std::fs::create_dir_all(self.root.join("files"))?;
+        std::fs::write(self.file_path(id), data)?;
+        index.documents.push(doc.clone());
+        self.write(&index)?;
+        trace!("Document {} stored with id {}.", name, id);
+        let result = id.to_string();
+        let mut entry = JournalEntry::new("", self.user_id(), "upload");
+        entry.document_id = Some(id);
+        entry.name = Some(name.to_owned());
+        entry.folder_id = Some(folder_id);
+        entry.after = Some(doc);
+        entry.result.clone_from(&result);
+        entry.success = true;
+        self.record(entry)?;
+        Ok(result)
+    }
+
+    async fn update(&self, doc: &Document, command: &str) -> LinkResult<String> {
+        let index = self.read()?;
+        let patch = match index.documents.iter().find(|d| d.id() == doc.id()) {
+            Some(current) => doc.patch(command, current)?,
+            None => DocumentPatch::new(doc),
+        };
+        self.patch(doc, command, &patch).await
+    }
+
+    async fn patch(
+        &self,
+        doc: &Document,
+        action: &str,
+        patch: &DocumentPatch,
+    ) -> LinkResult<String> {
+        let mut index = self.read()?;
+        let (after, result) = match index.documents.iter_mut().find(|d| d.id() == doc.id()) {
+            Some(current) => {
+                patch.check(current)?;
+                if patch.is_empty() {
+                    (
+                        Some(current.clone()),
+                        format!("Document {} unchanged.", doc.id()),
+                    )
+                } else {
+                    *current = patch.apply_to(current, &now())?;
+                    let updated = current.clone();
+                    self.write(&index)?;
+                    (Some(updated), format!("Document {} updated.", doc.id()))
+                }
+            }
+            None => (None, format!("Document {} not found.", doc.id())),
+        };
+        let mut entry = JournalEntry::new("", self.user_id(), action).document(doc);
+        entry.success = after.is_some();
+        entry.after = after;
+        entry.result.clone_from(&result);
+        self.record(entry)?;
+        Ok(result)
+    }
+
+    async fn move_to(&self, doc: &Document, folder_id: i32) -> LinkResult<String> {
+        let mut index = self.read()?;
+        if !index.folders.iter().any(|f| f.id_ref() == &Some(folder_id)) {
+            return Err(LinkError::FolderError {
+                value: folder_id.to_string(),
+            });
+        }
+        let (after, result) = match index.documents.iter_mut().find(|d| d.id() == doc.id()) {
+            Some(current) if current.folder_id_ref() == &Some(folder_id) => (
+                Some(current.clone()),
+                format!("Document {} unchanged.", doc.id()),
+            ),
+            Some(current) => {
+                let mut patch = DocumentPatch::new(doc);
+                patch.folder_id(folder_id);
+                patch.check(current)?;
+                *current = patch.apply_to(current, &now())?;
+                let moved = current.clone();
+                self.write(&index)?;
+                (Some(moved), format!("Document {} moved.", doc.id()))
+            }
+            None => (None, format!("Document {} not found.", doc.id())),
+        };
+        let mut entry = JournalEntry::new("", self.user_id(), "move").document(doc);
+        entry.success = after.is_some();
+        entry.after = after;
+        entry.result.clone_from(&result);
+        self.record(entry)?;
+        Ok(result)
+    }
+
+    async fn delete(&self, doc: &Document) -> LinkResult<String> {
+        let mut index = self.read()?;
+        let (success, result) = match index.documents.iter().position(|d| d.id() == doc.id()) {
+            Some(i) if index.documents[i].status_ref() == &Some(30) => (
+                false,
+                format!(
+                    "Document {} is published and must be set to draft before deletion.",
+                    doc.id()
+                ),
+            ),
+            Some(i) => {
+                index.documents.remove(i);
+                self.write(&index)?;
+                let path = self.file_path(doc.id());
+                if path.is_file() {
+                    std::fs::remove_file(path)?;
+                }
+                (true, format!("Document {} deleted.", doc.id()))
+            }
+            None => (false, format!("Document {} not found.", doc.id())),
+        };
+        let mut entry = JournalEntry::new("", self.user_id(), "delete").document(doc);
+        entry.result.clone_from(&result);
+        entry.success = success;
+        self.record(entry)?;
+        Ok(result)
+    }
+
+    async fn download(&self, doc: &Document) -> LinkResult<Download> {
+        let path = self.file_path(doc.id());
+        if !path.is_file() {
+            return Err(LinkError::BackupError {
+                id: doc.id(),
+                status: 404,
+            });
+        }
+        Ok(Download {
+            file_name: doc.file_name_ref().clone(),
+            data: std::fs::read(path)?,
+        })
+    }
+
+    fn journaled(&self, journal: &Journal) -> Arc<dyn DocumentStore> {
+        let mut store = self.clone();
+        store.journal(journal);
+        Arc::new(store)
+    }
+}
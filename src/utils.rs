@@ -1,44 +1,39 @@
+use crate::credential;
 use crate::prelude::*;
 use serde::Serialize;
 use tracing::{info, trace};
 
 /// Generic function to write a struct out to a csv file.  Called by internal library functions.
 pub fn to_csv<T: Serialize + Clone, P: AsRef<std::path::Path>>(
-    item: &mut Vec<T>,
+    item: &mut [T],
     title: P,
 ) -> Result<(), std::io::Error> {
     let mut wtr = csv::Writer::from_path(title)?;
-    for i in item.clone() {
+    for i in item.iter() {
         wtr.serialize(i)?;
     }
     wtr.flush()?;
     Ok(())
 }
 
-/// This function authenticates a user with the CivicEngage API.
+/// This function authenticates a user with the CivicEngage API, reading settings from the default
+/// profile of the config file and the environment.  See [`crate::config`].
 pub async fn load_user() -> LinkResult<AuthorizedUser> {
-    trace!("Loading environmental variables.");
-    let api_key = std::env::var("API_KEY")?;
-    let partition = std::env::var("PARTITION")?;
-    let name = std::env::var("USERNAME")?;
-    let password = std::env::var("PASSWORD")?;
-    let host = std::env::var("HOST")?;
-    trace!("Environmental variables loaded.");
+    let settings = Config::load(None)?.profile(None)?;
+    authorize_user(&settings).await
+}
 
-    trace!("Creating user from environmental variables.");
-    let user = User::new()
-        .api_key(&api_key)
-        .partition(&partition)
-        .name(&name)
-        .password(&password)
-        .host(&host)
-        .build()?;
+/// This function authenticates the user described in `settings` with the CivicEngage API.
+pub async fn authorize_user(settings: &Settings) -> LinkResult<AuthorizedUser> {
+    trace!("Creating user from profile {}.", settings.profile);
+    let credentials = credential::resolve(settings)?;
+    let user = settings.user(&credentials)?;
 
     trace!("Preparing authorization headers.");
     let headers = AuthorizeHeaders::default();
     trace!("Authorizing user.");
     let auth_info = AuthorizeInfo::new(&user, headers);
-    let url = std::env::var("AUTHENTICATE")?;
+    let url = settings.authenticate_url()?;
     let auth_res = auth_info.authorize(&url).await?;
     info!("Authorization successful for user {}.", &auth_res.id());
     trace!("Recording session id of user.");
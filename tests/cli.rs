@@ -0,0 +1,870 @@
+//! Runs the command line program against a [`MockServer`].
+use linkbuilder::mock::MockServer;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+/// Working directory holding the config file, journal and cache for a run of the program.
+struct Workspace {
+    dir: tempfile::TempDir,
+}
+
+impl Workspace {
+    fn new(server: &MockServer) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let config = format!("default_profile = \"mock\"\n{}", server.profile("mock"));
+        std::fs::write(dir.path().join("linkbuilder.toml"), config).unwrap();
+        Workspace { dir }
+    }
+
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Runs the program with `args` and the extra environment variables `env`.
+    async fn run_with(&self, args: &[&str], env: &[(&str, &str)]) -> Output {
+        tokio::process::Command::new(env!("CARGO_BIN_EXE_linkbuilder"))
+            .args(args)
+            .current_dir(self.path())
+            .env_clear()
+            .env("HOME", self.path())
+            .env("XDG_CACHE_HOME", self.path().join("cache"))
+            .env("XDG_CONFIG_HOME", self.path().join("config"))
+            .envs(env.iter().copied())
+            .output()
+            .await
+            .unwrap()
+    }
+
+    /// Runs the program with `args`, asserting that it succeeds.
+    async fn run(&self, args: &[&str]) -> Output {
+        let output = self.run_with(args, &[]).await;
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    /// Runs the program with `args` and `--format json`, returning the parsed output.
+    async fn json(&self, args: &[&str]) -> Value {
+        let mut args = args.to_vec();
+        args.extend(["--format", "json"]);
+        let output = self.run(&args).await;
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+
+    /// Writes files named `names` to the directory `dir`, returning its path.
+    fn source(&self, dir: &str, names: &[&str]) -> PathBuf {
+        let path = self.path().join(dir);
+        std::fs::create_dir_all(&path).unwrap();
+        for name in names {
+            std::fs::write(path.join(format!("{}.pdf", name)), name.as_bytes()).unwrap();
+        }
+        path
+    }
+
+    /// Returns the run id of the last entry in the journal.
+    fn last_run(&self) -> String {
+        let journal = std::fs::read_to_string(self.path().join("journal.jsonl")).unwrap();
+        let entry: Value = serde_json::from_str(journal.lines().last().unwrap()).unwrap();
+        entry["run_id"].as_str().unwrap().to_owned()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_uploads_missing_files_and_exports_links() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    server.add_document(plats, "Plat 1", true, b"Plat 1");
+    let ws = Workspace::new(&server);
+    let source = ws.source("plats", &["Plat 1", "Plat 2"]);
+
+    let sync = ws
+        .json(&["sync", "-s", source.to_str().unwrap(), "-f", "Plats"])
+        .await;
+    assert_eq!(sync[0]["missing"], 1);
+    assert_eq!(sync[0]["uploaded"], 1);
+    assert_eq!(server.documents(Some(plats)).total_count(), Some(2));
+    let journal = std::fs::read_to_string(ws.path().join("journal.jsonl")).unwrap();
+    let entry: Value = serde_json::from_str(journal.lines().last().unwrap()).unwrap();
+    assert_eq!(entry["action"], "upload");
+    assert_eq!(entry["name"], "Plat 2");
+    assert_eq!(entry["folder_id"], plats);
+    assert_eq!(entry["document_id"], Value::Null);
+    assert_eq!(entry["before"], Value::Null);
+
+    let docs = ws.json(&["docs", "list", "-f", "Plats"]).await;
+    assert_eq!(docs.as_array().map(|d| d.len()), Some(2));
+
+    let exports = ws.json(&["links", "-o", "."]).await;
+    let plat = exports
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|e| e["folder"] == "Plats")
+        .unwrap();
+    assert_eq!(plat["keys"], 2);
+    let links = std::fs::read_to_string(ws.path().join("plat_links.csv")).unwrap();
+    assert!(links.contains("Plat 2,https://127.0.0.1/DocumentCenter/View/"));
+
+    let report = ws.json(&["report"]).await;
+    assert!(report["folders"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|r| r["name"] == "Plats" && r["documents"] == 2));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn purge_backs_up_documents_and_undo_restores_them() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let plat = server.add_document(plats, "Plat 1", true, b"Plat 1");
+    server.add_document(plats, "Plat 2", false, b"Plat 2");
+    let ws = Workspace::new(&server);
+
+    let results = ws
+        .json(&["folder", "purge", "Plats", "--yes", "--backup", "backup"])
+        .await;
+    assert_eq!(results.as_array().map(|r| r.len()), Some(2));
+    assert_eq!(server.documents(None).total_count(), Some(0));
+    let backup = std::fs::read_dir(ws.path().join("backup"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    assert_eq!(
+        std::fs::read(backup.join(format!("{}_Plat 1.pdf", plat))).unwrap(),
+        b"Plat 1"
+    );
+
+    let run_id = ws.last_run();
+    let undone = ws.json(&["undo", &run_id]).await;
+    assert_eq!(undone.as_array().map(|r| r.len()), Some(2));
+    let docs = server.documents(Some(plats));
+    let mut names = docs
+        .source()
+        .unwrap_or_default()
+        .iter()
+        .map(|d| (d.name(), *d.status_ref()))
+        .collect::<Vec<(String, Option<i32>)>>();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            ("Plat 1".to_owned(), Some(30)),
+            ("Plat 2".to_owned(), Some(10))
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn docs_delete_and_update_are_limited_confirmed_and_backed_up() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let plat = server.add_document(plats, "Plat 1", false, b"Plat 1");
+    server.add_document(plats, "Plat 2", false, b"Plat 2");
+    let ws = Workspace::new(&server);
+
+    let output = ws.run_with(&["docs", "delete", "-f", "Plats"], &[]).await;
+    assert!(!output.status.success());
+    let output = ws
+        .run_with(
+            &["docs", "delete", "-f", "Plats", "--yes", "--max", "1"],
+            &[],
+        )
+        .await;
+    assert!(!output.status.success());
+    let output = ws
+        .run_with(&["docs", "update", "-f", "Plats", "-a", "archive"], &[])
+        .await;
+    assert!(!output.status.success());
+    assert_eq!(server.documents(Some(plats)).total_count(), Some(2));
+
+    let args = ["docs", "update", "-f", "Plats", "-a", "archive", "--yes"];
+    let output = ws
+        .run_with(&[&args[..], &["--id", "99999"]].concat(), &[])
+        .await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Documents not found: 99999."), "{}", stderr);
+
+    let results = ws
+        .json(&[
+            "docs",
+            "delete",
+            "-f",
+            "Plats",
+            "--id",
+            &plat.to_string(),
+            "--yes",
+        ])
+        .await;
+    assert_eq!(results.as_array().map(|r| r.len()), Some(1));
+    assert_eq!(server.documents(Some(plats)).total_count(), Some(1));
+    let backup = std::fs::read_dir(ws.path().join("backup"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    assert!(backup.join(format!("{}_Plat 1.pdf", plat)).is_file());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_updates_are_reported_and_the_rest_applied() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let plat = server.add_document(plats, "Plat 1", true, b"Plat 1");
+    let other = server.add_document(plats, "Plat 2", true, b"Plat 2");
+    let ws = Workspace::new(&server);
+
+    let path = format!("/api/DocumentCenter/v1/Document/{}", plat);
+    server.fail_next_method("PUT", &path, 400);
+    let results = ws
+        .json(&["docs", "update", "-f", "Plats", "-a", "archive", "--yes"])
+        .await;
+    let results = results.as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    let rejected = results.iter().find(|r| r["id"] == plat).unwrap();
+    assert!(rejected["response"]
+        .as_str()
+        .unwrap()
+        .contains("rejected with status 400"));
+    let docs = server.documents(Some(plats)).source().unwrap();
+    let archived = |id: i32| *docs.iter().find(|d| d.id() == id).unwrap().is_archived();
+    assert_ne!(archived(plat), Some(true));
+    assert_eq!(archived(other), Some(true));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn undo_skips_changes_that_failed() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let plat = server.add_document(plats, "Plat 1", false, b"Plat 1");
+    server.add_document(plats, "Plat 2", false, b"Plat 2");
+    let ws = Workspace::new(&server);
+
+    server.fail_next(&format!("/api/DocumentCenter/v1/Document/{}", plat), 500);
+    ws.run(&["docs", "delete", "-f", "Plats", "--yes"]).await;
+    assert_eq!(server.documents(Some(plats)).total_count(), Some(1));
+
+    let run_id = ws.last_run();
+    let undone = ws.json(&["undo", &run_id]).await;
+    assert_eq!(undone.as_array().map(|r| r.len()), Some(1));
+    let mut names = server
+        .documents(Some(plats))
+        .source()
+        .unwrap_or_default()
+        .iter()
+        .map(|d| d.name())
+        .collect::<Vec<String>>();
+    names.sort();
+    assert_eq!(names, vec!["Plat 1", "Plat 2"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn undo_refuses_a_run_already_undone() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let plat = server.add_document(plats, "Plat 1", true, b"Plat 1");
+    let ws = Workspace::new(&server);
+
+    ws.run(&["docs", "update", "-f", "Plats", "-a", "archive", "--yes"])
+        .await;
+    let run_id = ws.last_run();
+    let undone = ws.json(&["undo", &run_id]).await;
+    assert_eq!(undone[0]["id"], plat);
+    assert_eq!(undone[0]["action"], "restore");
+
+    let output = ws.run_with(&["undo", &run_id], &[]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("already undone"), "{}", stderr);
+    let docs = server.documents(Some(plats)).source().unwrap();
+    assert_ne!(*docs[0].is_archived(), Some(true));
+    assert_eq!(
+        server
+            .requests()
+            .iter()
+            .filter(|r| r.method == "PUT")
+            .count(),
+        2
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn queries_read_every_page() {
+    let server = MockServer::start().unwrap();
+    let gis = server.add_folder("GIS", None);
+    server.add_folder("Minutes", None);
+    let plats = server.add_folder("Plats", Some(gis));
+    for i in 0..5 {
+        server.add_document(plats, &format!("Plat {}", i), true, b"Plat");
+    }
+    server.page_size(2);
+    let ws = Workspace::new(&server);
+
+    let docs = ws.json(&["docs", "list", "-f", "Plats"]).await;
+    assert_eq!(docs.as_array().map(|d| d.len()), Some(5));
+    let skips = server
+        .requests()
+        .iter()
+        .filter(|r| r.path == "/api/DocumentCenter/v1/Document")
+        .map(|r| r.query.get("$skip").cloned().unwrap_or_default())
+        .collect::<Vec<String>>();
+    assert_eq!(skips, vec!["", "2", "4"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cached_session_is_reused_until_rejected() {
+    let server = MockServer::start().unwrap();
+    server.add_folder("Plats", None);
+    let ws = Workspace::new(&server);
+
+    ws.run(&["docs", "list", "-f", "Plats"]).await;
+    ws.run(&["docs", "list", "-f", "Plats"]).await;
+    assert_eq!(server.logins(), 1);
+
+    server.expire_sessions();
+    ws.run(&["docs", "list", "-f", "Plats"]).await;
+    assert_eq!(server.logins(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn system_username_and_host_do_not_override_the_profile() {
+    let server = MockServer::start().unwrap();
+    server.add_folder("Plats", None);
+    let ws = Workspace::new(&server);
+
+    let env = [("USERNAME", "WORKSTATION\\clerk"), ("HOST", "workstation")];
+    let output = ws.run_with(&["folder", "tree"], &env).await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(server.logins(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn login_failures_are_reported() {
+    let server = MockServer::start().unwrap();
+    let ws = Workspace::new(&server);
+
+    let output = ws
+        .run_with(
+            &["folder", "tree"],
+            &[("LINKBUILDER_PASSWORD", "wrong-password")],
+        )
+        .await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Invalid username or password."),
+        "{}",
+        stderr
+    );
+
+    server.lock_account(true);
+    let output = ws.run_with(&["folder", "tree"], &[]).await;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Account is locked."), "{}", stderr);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rate_limits_are_reported() {
+    let server = MockServer::start().unwrap();
+    server.add_folder("Plats", None);
+    let ws = Workspace::new(&server);
+
+    // The login is allowed, and the folder query is refused.
+    server.rate_limit(1);
+    let output = ws.run_with(&["docs", "list", "-f", "Plats"], &[]).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Rate limited by CivicEngage, retry after 1 seconds."),
+        "{}",
+        stderr
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn move_keeps_links_and_writes_the_link_diff() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let older = server.add_folder("Plats 1990-1999", Some(plats));
+    let newer = server.add_folder("Plats 2000-2009", Some(plats));
+    let first = server.add_document(plats, "Plat 1995-01", true, b"Plat");
+    let second = server.add_document(plats, "Plat 2003-07", true, b"Plat");
+    server.add_document(plats, "Plat 2021-02", true, b"Plat");
+    let ws = Workspace::new(&server);
+
+    let moved = ws
+        .json(&[
+            "docs",
+            "move",
+            "-f",
+            "Plats",
+            "-t",
+            "Plats 1990-1999",
+            "--name",
+            "^Plat 199",
+            "--yes",
+            "-o",
+            "links",
+        ])
+        .await;
+    assert_eq!(moved.as_array().map(|m| m.len()), Some(1));
+    assert_eq!(moved[0]["id"], first);
+    assert_eq!(moved[0]["from"], "Plats");
+    assert_eq!(moved[0]["to"], "Plats 1990-1999");
+    let docs = server.documents(Some(older));
+    let doc = &docs.source().unwrap()[0];
+    assert_eq!(doc.id(), first);
+    assert_eq!(
+        doc.url().as_deref(),
+        Some(format!("/DocumentCenter/View/{}/Plat-1995-01", first).as_str())
+    );
+
+    std::fs::write(
+        ws.path().join("mapping.csv"),
+        "document,folder\nPlat 2003-07,Plats 2000-2009\n",
+    )
+    .unwrap();
+    ws.run(&[
+        "docs",
+        "move",
+        "--mapping",
+        "mapping.csv",
+        "--yes",
+        "-o",
+        "links",
+    ])
+    .await;
+    assert_eq!(
+        server.documents(Some(newer)).source().unwrap()[0].id(),
+        second
+    );
+    let diff = std::fs::read_to_string(ws.path().join("links/moved_links.csv")).unwrap();
+    assert_eq!(diff.lines().count(), 3, "{}", diff);
+    assert!(diff.contains(&format!(
+        "{},Plat 2003-07,https://127.0.0.1/DocumentCenter/View/{}/Plat-2003-07,Plats,Plats 2000-2009",
+        second, second
+    )));
+
+    let run_id = ws.last_run();
+    ws.run(&["undo", &run_id]).await;
+    assert_eq!(server.documents(Some(newer)).total_count(), Some(0));
+    assert_eq!(server.documents(Some(plats)).total_count(), Some(2));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_moves_are_left_out_of_the_link_diff() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let older = server.add_folder("Plats 1990-1999", Some(plats));
+    let first = server.add_document(plats, "Plat 1995-01", true, b"Plat");
+    let second = server.add_document(plats, "Plat 1996-03", true, b"Plat");
+    let ws = Workspace::new(&server);
+
+    server.fail_next(&format!("/api/DocumentCenter/v1/Document/{}", first), 500);
+    let moved = ws
+        .json(&[
+            "docs",
+            "move",
+            "-f",
+            "Plats",
+            "-t",
+            "Plats 1990-1999",
+            "--yes",
+            "-o",
+            "links",
+        ])
+        .await;
+    assert_eq!(moved.as_array().map(|m| m.len()), Some(1));
+    assert_eq!(moved[0]["id"], second);
+    assert_eq!(server.documents(Some(older)).total_count(), Some(1));
+    let diff = std::fs::read_to_string(ws.path().join("links/moved_links.csv")).unwrap();
+    assert_eq!(diff.lines().count(), 2, "{}", diff);
+    assert!(!diff.contains(&format!("{},Plat 1995-01", first)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn apply_metadata_lists_changes_and_applies_valid_rows() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let plat = server.add_document(plats, "Plat 1", true, b"Plat 1");
+    server.add_document(plats, "Plat 2", true, b"Plat 2");
+    let ws = Workspace::new(&server);
+    std::fs::write(
+        ws.path().join("metadata.csv"),
+        format!(
+            "document,description,alt_text,start_date,end_date,is_visible\n\
+             {},Recorded plat.,Plat map,2024-01-01,2030-12-31,true\n\
+             Plat 2,,,2024-13-01,,\n\
+             Plat 3,Missing,,,,\n",
+            plat
+        ),
+    )
+    .unwrap();
+
+    let diff = ws
+        .json(&["apply-metadata", "-s", "metadata.csv", "--dry-run"])
+        .await;
+    let fields = diff
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| d["field"].as_str().unwrap().to_owned())
+        .collect::<Vec<String>>();
+    assert_eq!(
+        fields,
+        vec![
+            "AltText",
+            "Description",
+            "EndDate",
+            "IsVisible",
+            "StartDate",
+            "error",
+            "error"
+        ]
+    );
+    assert_eq!(diff[3]["current"], "false");
+    assert_eq!(diff[3]["new"], "true");
+    assert_eq!(diff[4]["new"], "2024-01-01T00:00:00");
+    assert_eq!(
+        *server.documents(None).source().unwrap()[0].is_visible_ref(),
+        Some(false)
+    );
+
+    let results = ws
+        .json(&["apply-metadata", "-s", "metadata.csv", "--yes"])
+        .await;
+    assert_eq!(results[0]["changes"], 5);
+    assert_eq!(
+        results[1]["result"],
+        "2024-13-01 is not a date in the form YYYY-MM-DD."
+    );
+    assert_eq!(results[2]["result"], "Document not found.");
+    let docs = server.documents(Some(plats));
+    let doc = &docs.source().unwrap()[0];
+    assert_eq!(*doc.is_visible_ref(), Some(true));
+
+    let run_id = ws.last_run();
+    ws.run(&["undo", &run_id]).await;
+    let docs = server.documents(Some(plats));
+    let doc = &docs.source().unwrap()[0];
+    assert_eq!(*doc.is_visible_ref(), Some(false));
+    let requests = server.requests();
+    let body = requests.last().unwrap().body.clone().unwrap();
+    assert_eq!(body["Description"], serde_json::Value::Null);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_metadata_updates_are_reported_and_the_rest_applied() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let first = server.add_document(plats, "Plat 1", true, b"Plat 1");
+    let second = server.add_document(plats, "Plat 2", true, b"Plat 2");
+    let ws = Workspace::new(&server);
+    std::fs::write(
+        ws.path().join("metadata.csv"),
+        format!(
+            "document,description\n\
+             {},First plat.\n\
+             {},Second plat.\n",
+            first, second
+        ),
+    )
+    .unwrap();
+
+    server.fail_next(&format!("/api/DocumentCenter/v1/Document/{}", first), 500);
+    let results = ws
+        .json(&["apply-metadata", "-s", "metadata.csv", "--yes"])
+        .await;
+    assert_eq!(results.as_array().map(|r| r.len()), Some(2));
+    assert_eq!(results[0]["id"], first);
+    assert_eq!(results[0]["result"], "Authorization failed.");
+    assert_eq!(results[1]["id"], second);
+    let docs = server.documents(Some(plats));
+    let docs = docs.source().unwrap();
+    assert_eq!(*docs[0].description_ref(), None);
+    assert_eq!(*docs[1].description_ref(), Some("Second plat.".to_owned()));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn export_lists_full_metadata_for_a_folder_subtree() {
+    let server = MockServer::start().unwrap();
+    let gis = server.add_folder("GIS", None);
+    let plats = server.add_folder("Plats", Some(gis));
+    let older = server.add_folder("Plats 1990-1999", Some(plats));
+    let other = server.add_folder("Minutes", None);
+    server.add_document(plats, "Plat 2021-02", true, b"Plat");
+    let draft = server.add_document(older, "Plat 1995-01", false, b"Plat");
+    server.add_document(other, "Minutes 2024-01", true, b"Minutes");
+    let ws = Workspace::new(&server);
+
+    let records = ws.json(&["docs", "export", "-f", "Plats"]).await;
+    let records = records.as_array().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["folder_path"], "GIS/Plats");
+    assert_eq!(records[1]["folder_path"], "GIS/Plats/Plats 1990-1999");
+    assert_eq!(records[1]["id"], draft);
+    assert_eq!(records[1]["status"], 10);
+    assert_eq!(records[1]["file_type"], "pdf");
+    assert_eq!(records[1]["is_archived"], false);
+    assert_eq!(records[1]["created_by"], 7);
+
+    ws.run(&["docs", "export", "-o", "export.csv"]).await;
+    let csv = std::fs::read_to_string(ws.path().join("export.csv")).unwrap();
+    assert!(csv.starts_with("id,name,folder_id,folder_path,status,file_type"));
+    assert_eq!(csv.lines().count(), 4);
+    assert!(csv.contains(",Minutes,"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retention_archives_due_documents_and_drops_their_links() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let older = server.add_folder("Plats 1990-1999", Some(plats));
+    let current = server.add_document(plats, "Plat 2021-02", true, b"Plat");
+    let old = server.add_document(plats, "Plat 2003-07", true, b"Plat");
+    let ended = server.add_document(older, "Plat 1995-01", true, b"Plat");
+    server
+        .set_field(old, "FileUploadedDate", "2003-07-01T00:00:00".into())
+        .set_field(ended, "EndDate", "2024-01-01T00:00:00".into());
+    let ws = Workspace::new(&server);
+    std::fs::write(
+        ws.path().join("retention.toml"),
+        "[[rules]]\nfolder = \"Plats\"\nsubfolders = true\nyears_since_upload = 10\n\
+         end_date_passed = true\n",
+    )
+    .unwrap();
+
+    let archived = |id: i32| {
+        server
+            .documents(None)
+            .source()
+            .unwrap()
+            .iter()
+            .any(|d| d.id() == id && d.is_archived() == &Some(true))
+    };
+
+    let due = ws.json(&["retention", "--dry-run"]).await;
+    let due = due.as_array().unwrap();
+    assert_eq!(due.len(), 2);
+    assert_eq!(due[0]["id"], old);
+    assert_eq!(due[0]["reason"], "Uploaded more than 10 years ago.");
+    assert_eq!(due[1]["folder"], "Plats/Plats 1990-1999");
+    assert_eq!(due[1]["reason"], "End date 2024-01-01T00:00:00 passed.");
+    assert!(!archived(old));
+
+    let results = ws.json(&["retention", "-y"]).await;
+    assert_eq!(results.as_array().map(|r| r.len()), Some(2));
+    assert!(archived(old) && archived(ended) && !archived(current));
+    let again = ws.json(&["retention", "--dry-run"]).await;
+    assert_eq!(again.as_array().map(|r| r.len()), Some(0));
+
+    ws.run(&["links", "-o", "."]).await;
+    let links = std::fs::read_to_string(ws.path().join("plat_links.csv")).unwrap();
+    assert!(links.contains("Plat 2021-02"));
+    assert!(!links.contains("Plat 2003-07") && !links.contains("Plat 1995-01"));
+
+    let run_id = ws.last_run();
+    ws.run(&["undo", &run_id]).await;
+    assert!(!archived(old) && !archived(ended));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn report_breaks_down_storage_by_folder_type_and_status() {
+    let server = MockServer::start().unwrap();
+    let gis = server.add_folder("GIS", None);
+    let plats = server.add_folder("Plats", Some(gis));
+    server.add_folder("Empty", Some(gis));
+    let minutes = server.add_folder("Minutes", None);
+    let big = server.add_document(gis, "Map", true, &[0; 3072]);
+    server.add_document(plats, "Plat 1", false, &[0; 1024]);
+    let agenda = server.add_document(minutes, "Agenda", true, &[0; 4096]);
+    server.set_field(agenda, "FileType", "docx".into());
+    let ws = Workspace::new(&server);
+
+    let report = ws.json(&["report", "--top", "2"]).await;
+    assert_eq!(report["total"]["documents"], 3);
+    assert_eq!(report["total"]["kb"], 8.0);
+    assert_eq!(report["total"]["percent"], 100.0);
+    let folders = report["folders"].as_array().unwrap();
+    let names = folders
+        .iter()
+        .map(|f| f["name"].as_str().unwrap())
+        .collect::<Vec<&str>>();
+    assert_eq!(names, ["GIS", "GIS/Empty", "GIS/Plats", "Minutes"]);
+    assert_eq!(folders[0]["documents"], 2);
+    assert_eq!(folders[0]["percent"], 50.0);
+    assert_eq!(folders[1]["documents"], 0);
+    assert_eq!(folders[1]["percent"], 0.0);
+    assert_eq!(report["file_types"][0]["name"], "docx");
+    assert_eq!(report["file_types"][1]["percent"], 50.0);
+    assert_eq!(report["statuses"][0]["name"], "Published");
+    assert_eq!(report["statuses"][0]["documents"], 2);
+    assert_eq!(report["statuses"][1]["name"], "Draft");
+    let largest = report["largest"].as_array().unwrap();
+    assert_eq!(largest.len(), 2);
+    assert_eq!(largest[0]["id"], agenda);
+    assert_eq!(largest[1]["id"], big);
+
+    let scoped = ws.json(&["report", "-f", "Plats"]).await;
+    assert_eq!(scoped["total"]["documents"], 1);
+    assert_eq!(scoped["folders"][0]["percent"], 100.0);
+
+    ws.run(&["report", "-f", "Empty", "-o", "report.csv"]).await;
+    let csv = std::fs::read_to_string(ws.path().join("report.csv")).unwrap();
+    assert!(csv.starts_with("section,id,name,documents,size,kb,percent"));
+    assert!(csv.contains("total,,Total,0,"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn report_runs_build_a_history_and_trend_projects_the_quota() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    server.add_document(plats, "Plat 1", true, &[0; 2048]);
+    let ws = Workspace::new(&server);
+
+    ws.run(&["report"]).await;
+    ws.run(&["report", "-f", "Plats"]).await;
+    let history = std::fs::read_to_string(ws.path().join("report_history.csv")).unwrap();
+    let lines = history.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[0], "date,folder,kb");
+    assert_eq!(lines.len(), 4);
+    assert!(lines[2].ends_with(",Total,2.0"));
+    assert!(lines[3].ends_with(",Plats,2.0"));
+
+    std::fs::write(
+        ws.path().join("council.csv"),
+        "date,folder,kb\n\
+         2024-01-01T00:00:00,GIS,1000000\n\
+         2024-01-01T00:00:00,Total,4000000\n\
+         2025-01-01T00:00:00,GIS,1500000\n\
+         2025-01-01T00:00:00,Total,5000000\n\
+         2026-01-01T00:00:00,Total,6000000\n",
+    )
+    .unwrap();
+    let args = ["report", "trend", "--history", "council.csv"];
+    let trend = ws.json(&args).await;
+    assert_eq!(trend[0]["folder"], "GIS");
+    assert_eq!(trend[0]["growth_kb"], 500000.0);
+    assert_eq!(trend[0]["quota_date"], Value::Null);
+    assert_eq!(trend[1]["folder"], "Total");
+    assert_eq!(trend[1]["snapshots"], 3);
+    assert_eq!(trend[1]["quota_percent"], Value::Null);
+
+    let trend = ws.json(&[&args[..], &["--quota", "10 GB"]].concat()).await;
+    assert_eq!(trend[1]["quota_percent"], 60.0);
+    assert_eq!(trend[1]["quota_date"], "2030-01-02");
+
+    let config = std::fs::read_to_string(ws.path().join("linkbuilder.toml")).unwrap();
+    let config = format!("{}storage_quota = \"5 GB\"\n", config);
+    std::fs::write(ws.path().join("linkbuilder.toml"), config).unwrap();
+    let trend = ws.json(&args).await;
+    assert_eq!(trend[1]["quota_percent"], 120.0);
+    assert_eq!(trend[1]["quota_date"], "2025-01-01");
+
+    std::fs::write(
+        ws.path().join("slow.csv"),
+        "date,folder,kb\n\
+         2024-01-01T00:00:00,Total,1000\n\
+         2025-01-01T00:00:00,Total,1001\n",
+    )
+    .unwrap();
+    let args = [
+        "report",
+        "trend",
+        "--history",
+        "slow.csv",
+        "--quota",
+        "100 GB",
+    ];
+    let trend = ws.json(&args).await;
+    assert_eq!(trend[0]["folder"], "Total");
+    assert_eq!(trend[0]["quota_date"], Value::Null);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn crossed_thresholds_alert_and_exit_with_a_distinct_code() {
+    let server = MockServer::start().unwrap();
+    let gis = server.add_folder("GIS", None);
+    let plats = server.add_folder("Plats", Some(gis));
+    server.add_document(plats, "Plat 1", true, &[0; 3072]);
+    server.add_document(gis, "Map", true, &[0; 1024]);
+    let ws = Workspace::new(&server);
+    let config = std::fs::read_to_string(ws.path().join("linkbuilder.toml")).unwrap();
+    let write_config = |total: &str, plats: &str| {
+        let text = format!(
+            "{}storage_quota = \"10 KB\"\nalert_file = \"alerts.json\"\n\
+             alert_command = \"cat > command.json\"\n\
+             [profiles.mock.thresholds]\nTotal = \"{}\"\n\"GIS/Plats\" = \"{}\"\n",
+            config, total, plats
+        );
+        std::fs::write(ws.path().join("linkbuilder.toml"), text).unwrap();
+    };
+
+    write_config("50%", "3 KB");
+    let output = ws.run_with(&["report", "--format", "json"], &[]).await;
+    assert_eq!(output.status.code(), Some(3));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["total"]["documents"], 2);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Storage thresholds crossed: 1."),
+        "{}",
+        stderr
+    );
+    let alerts: Value =
+        serde_json::from_str(&std::fs::read_to_string(ws.path().join("alerts.json")).unwrap())
+            .unwrap();
+    assert_eq!(alerts["profile"], "mock");
+    assert_eq!(alerts["alerts"][0]["folder"], "GIS/Plats");
+    assert_eq!(alerts["alerts"][0]["percent"], 100.0);
+    let command = std::fs::read_to_string(ws.path().join("command.json")).unwrap();
+    assert_eq!(serde_json::from_str::<Value>(&command).unwrap(), alerts);
+
+    write_config("30%", "10 KB");
+    ws.run(&["report", "-f", "GIS"]).await;
+    assert!(ws.path().join("alerts.json").exists());
+    let output = ws.run_with(&["report"], &[]).await;
+    assert_eq!(output.status.code(), Some(3));
+    let alerts = std::fs::read_to_string(ws.path().join("alerts.json")).unwrap();
+    assert!(alerts.contains("\"folder\": \"Total\""));
+
+    write_config("90%", "10 KB");
+    ws.run(&["report"]).await;
+    assert!(!ws.path().join("alerts.json").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn alert_commands_that_ignore_the_summary_still_alert() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    server.add_document(plats, "Plat 1", true, &[0; 3072]);
+    let ws = Workspace::new(&server);
+    let config = std::fs::read_to_string(ws.path().join("linkbuilder.toml")).unwrap();
+    std::fs::write(
+        ws.path().join("linkbuilder.toml"),
+        format!(
+            "{}alert_file = \"alerts.json\"\nalert_command = \"exit 0\"\n\
+             [profiles.mock.thresholds]\nPlats = \"1 KB\"\n",
+            config
+        ),
+    )
+    .unwrap();
+
+    let output = ws.run_with(&["report"], &[]).await;
+    assert_eq!(output.status.code(), Some(3));
+    assert!(ws.path().join("alerts.json").is_file());
+    assert!(!ws.path().join("alerts.json.tmp").exists());
+}
@@ -0,0 +1,105 @@
+//! Records runs of the command line program against a [`MockServer`] and replays them.
+use linkbuilder::fixture::{self, Fixture, FixtureServer};
+use linkbuilder::mock::{MockServer, MOCK_API_KEY, MOCK_PASSWORD};
+use linkbuilder::prelude::*;
+use std::path::Path;
+use std::process::Output;
+
+/// Fixtures recorded from the mock server, checked in to catch changes to the parsers.
+const FIXTURES: &str = "tests/fixtures/civicengage";
+
+/// Runs the program in `dir` with `args`, asserting that it succeeds.
+async fn run(dir: &Path, args: &[&str]) -> Output {
+    let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_linkbuilder"))
+        .args(args)
+        .args(["--format", "json"])
+        .current_dir(dir)
+        .env_clear()
+        .env("HOME", dir)
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .output()
+        .await
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recorded_runs_replay_without_the_server() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    server.add_document(plats, "Plat 1", true, b"Plat 1");
+    server.add_document(plats, "Plat 2", false, b"Plat 2");
+    let dir = tempfile::tempdir().unwrap();
+    let config = format!("default_profile = \"mock\"\n{}", server.profile("mock"));
+    std::fs::write(dir.path().join("linkbuilder.toml"), config).unwrap();
+
+    let recorded = run(
+        dir.path(),
+        &["docs", "list", "-f", "Plats", "--record", "fixtures"],
+    )
+    .await;
+    drop(server);
+
+    let fixtures = dir.path().join("fixtures");
+    for (path, _) in Fixture::read_dir(&fixtures).unwrap() {
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(!text.contains(MOCK_PASSWORD), "{}", path.display());
+        assert!(!text.contains(MOCK_API_KEY), "{}", path.display());
+        assert!(!text.contains("mock-session"), "{}", path.display());
+    }
+    assert_eq!(fixture::verify(&fixtures).unwrap(), 3);
+
+    for mode in ["match", "sequence"] {
+        let replayed = run(
+            dir.path(),
+            &[
+                "docs",
+                "list",
+                "-f",
+                "Plats",
+                "--replay",
+                "fixtures",
+                "--replay-mode",
+                mode,
+            ],
+        )
+        .await;
+        assert_eq!(recorded.stdout, replayed.stdout);
+    }
+}
+
+#[test]
+fn checked_in_fixtures_parse() {
+    assert!(fixture::verify(FIXTURES).unwrap() > 0);
+}
+
+/// Records the checked-in fixtures from the mock server.  Run with `cargo test -- --ignored`
+/// after changing the mock server, or pass `--record` to the program to record from the site.
+#[tokio::test]
+#[ignore]
+async fn record_checked_in_fixtures() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    server.add_folder("Subdivisions", None);
+    server.add_document(plats, "Plat 1", true, b"Plat 1");
+    server.add_document(plats, "Plat 2", false, b"Plat 2");
+    let _ = std::fs::remove_dir_all(FIXTURES);
+    let recorder = FixtureServer::record(&server.site(), FIXTURES).unwrap();
+    let mut settings = server.settings();
+    settings.site = Some(recorder.site());
+    let user = authorize_user(&settings).await.unwrap();
+    let store = CivicEngageStore::from_settings(&settings, &user).unwrap();
+    store.folders().await.unwrap();
+    let docs = store.documents(Some(plats)).await.unwrap();
+    store
+        .download(&docs.source().unwrap_or_default()[0])
+        .await
+        .unwrap();
+}
@@ -0,0 +1,196 @@
+//! Calls the library against a [`MockServer`].
+use linkbuilder::mock::MockServer;
+use linkbuilder::prelude::*;
+
+#[tokio::test]
+async fn queries_are_paged() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    for i in 0..5 {
+        server.add_document(plats, &format!("Plat {}", i), true, b"");
+    }
+    server.page_size(2);
+    let settings = server.settings();
+    let user = authorize_user(&settings).await.unwrap();
+
+    let mut args = DocQuery::new();
+    args.inlinecount("allpages")
+        .filter(&format!("FolderId eq {}", plats))
+        .skip(4);
+    let info = DocInfo::new(
+        &DocumentHeaders::default(),
+        &args,
+        &settings.document_url().unwrap(),
+    );
+    let docs = Documents::query(&info, &user).await.unwrap();
+    assert_eq!(docs.total_count(), Some(5));
+    assert_eq!(*docs.current_page_ref(), Some(3));
+    assert_eq!(docs.source().map(|d| d.len()), Some(1));
+    assert_eq!(*docs.has_next_page_ref(), Some(false));
+    assert_eq!(*docs.has_previous_page_ref(), Some(true));
+
+    let requests = server.requests();
+    let query = &requests.last().unwrap().query;
+    assert_eq!(
+        query.get("$filter"),
+        Some(&format!("FolderId eq {}", plats))
+    );
+}
+
+#[tokio::test]
+async fn login_failures_map_to_errors() {
+    let server = MockServer::start().unwrap();
+    let mut settings = server.settings();
+    settings.partition = Some("0000".to_owned());
+    assert!(matches!(
+        authorize_user(&settings).await,
+        Err(LinkError::BadPartition { .. })
+    ));
+
+    let mut settings = server.settings();
+    settings.api_key = Some("wrong".to_owned());
+    assert!(matches!(
+        authorize_user(&settings).await,
+        Err(LinkError::BadApiKey { .. })
+    ));
+
+    server.lock_account(true);
+    assert!(matches!(
+        authorize_user(&server.settings()).await,
+        Err(LinkError::AccountLocked { .. })
+    ));
+    assert_eq!(server.logins(), 0);
+}
+
+#[tokio::test]
+async fn failed_responses_map_to_errors() {
+    let server = MockServer::start().unwrap();
+    let settings = server.settings();
+    let user = authorize_user(&settings).await.unwrap();
+    let store = CivicEngageStore::from_settings(&settings, &user).unwrap();
+
+    server.fail_next("/api/DocumentCenter/v1/Folder", 500);
+    assert!(matches!(store.folders().await, Err(LinkError::AuthError)));
+    assert!(store.folders().await.is_ok());
+
+    server.rate_limit(0);
+    assert!(matches!(
+        store.documents(None).await,
+        Err(LinkError::RateLimited { retry_after: 1 })
+    ));
+
+    let server = MockServer::start().unwrap();
+    let store = CivicEngageStore::from_settings(&server.settings(), &user).unwrap();
+    assert!(matches!(store.folders().await, Err(LinkError::AuthError)));
+}
+
+#[tokio::test]
+async fn unknown_fields_round_trip_on_update() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let plat = server.add_document(plats, "Plat 1", true, b"Plat 1");
+    server.set_field(plat, "Tags", serde_json::json!(["plat", "recorded"]));
+    let settings = server.settings();
+    let user = authorize_user(&settings).await.unwrap();
+    let store = CivicEngageStore::from_settings(&settings, &user).unwrap();
+
+    let docs = store.documents(Some(plats)).await.unwrap();
+    let doc = &docs.source().unwrap()[0];
+    assert_eq!(
+        doc.extra_ref().get("Tags"),
+        Some(&serde_json::json!(["plat", "recorded"]))
+    );
+
+    store.update(doc, "draft").await.unwrap();
+    let docs = server.documents(Some(plats));
+    let doc = &docs.source().unwrap()[0];
+    assert_eq!(
+        doc.extra_ref().get("Tags"),
+        Some(&serde_json::json!(["plat", "recorded"]))
+    );
+    assert_eq!(*doc.status_ref(), Some(10));
+}
+
+#[tokio::test]
+async fn updates_send_changed_fields_and_refuse_newer_edits() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let plat = server.add_document(plats, "Plat 1", true, b"Plat 1");
+    let settings = server.settings();
+    let user = authorize_user(&settings).await.unwrap();
+    let store = CivicEngageStore::from_settings(&settings, &user).unwrap();
+
+    let docs = store.documents(Some(plats)).await.unwrap();
+    let doc = docs.source().unwrap()[0].clone();
+    store.update(&doc, "draft").await.unwrap();
+    let requests = server.requests();
+    let body = requests.last().unwrap().body.clone().unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({ "Id": plat, "Name": "Plat 1", "FolderId": plats, "Status": 10 })
+    );
+
+    // The first update changed the document, so the copy read before it is stale.
+    assert!(matches!(
+        store.update(&doc, "archive").await,
+        Err(LinkError::ConflictError { id, .. }) if id == plat
+    ));
+    server.set_field(
+        plat,
+        "LastModifiedOn",
+        serde_json::json!("2024-01-01T00:00:00"),
+    );
+    let docs = store.documents(Some(plats)).await.unwrap();
+    let doc = docs.source().unwrap()[0].clone();
+    store.update(&doc, "archive").await.unwrap();
+    let docs = server.documents(Some(plats));
+    assert_eq!(*docs.source().unwrap()[0].is_archived(), Some(true));
+}
+
+#[tokio::test]
+async fn backups_made_in_the_same_second_do_not_share_a_directory() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    server.add_document(plats, "Plat 1", false, b"Plat 1");
+    let settings = server.settings();
+    let user = authorize_user(&settings).await.unwrap();
+    let store = CivicEngageStore::from_settings(&settings, &user).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+
+    let docs = store.documents(Some(plats)).await.unwrap();
+    let plan = PurgePlan::new(&docs, &PurgeFilter::new());
+    let first = plan.backup(dir.path(), &store).await.unwrap();
+    let second = plan.backup(dir.path(), &store).await.unwrap();
+    assert_ne!(first, second);
+    assert!(first.join("metadata.json").is_file());
+    assert!(second.join("metadata.json").is_file());
+}
+
+#[tokio::test]
+async fn rejected_updates_return_errors() {
+    let server = MockServer::start().unwrap();
+    let plats = server.add_folder("Plats", None);
+    let plat = server.add_document(plats, "Plat 1", true, b"Plat 1");
+    let settings = server.settings();
+    let user = authorize_user(&settings).await.unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let journal = Journal::new(dir.path().join("journal.jsonl"));
+    let mut store = CivicEngageStore::from_settings(&settings, &user).unwrap();
+    store.journal(&journal);
+
+    let docs = store.documents(Some(plats)).await.unwrap();
+    let doc = docs.source().unwrap()[0].clone();
+    let mut patch = doc.patch("draft", &doc).unwrap();
+    patch.unchecked();
+    server.fail_next(&format!("/api/DocumentCenter/v1/Document/{}", plat), 500);
+    assert!(matches!(
+        store.patch(&doc, "draft", &patch).await,
+        Err(LinkError::UpdateRejected { id, status: 500, .. }) if id == plat
+    ));
+    assert_eq!(
+        *server.documents(Some(plats)).source().unwrap()[0].status_ref(),
+        Some(30)
+    );
+    let entries = Journal::read(journal.path_ref()).unwrap();
+    assert!(!entries[0].success);
+}